@@ -1,47 +1,44 @@
+use super::reference;
 use super::Rectangle;
+use super::value::CellValue;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub struct Cell {
-  row_idx: u16,
-  col_idx: u8,
+  row_idx: u32,
+  col_idx: u32,
   width: usize,
   height: usize,
   boundary: Option<Rectangle>,
+  value: CellValue,
+  formula: Option<String>,
 }
 
 impl Cell {
-  pub fn new(col_idx: u8, row_idx: u16) -> Self {
+  pub fn new(col_idx: u32, row_idx: u32) -> Self {
     Self {
       row_idx,
       col_idx,
       width: 20,
       height: 20,
       boundary: None,
+      value: CellValue::Empty,
+      formula: None,
     }
   }
 
   fn get_column_name(&self) -> String {
-    let mut dividend = self.col_idx;
-    let mut column_name = String::new();
-    let mut modulo: u8;
-
-    while dividend > 0 {
-      modulo = (dividend - 1) % 26;
-      column_name = [((65 + modulo) as char).to_string(), column_name].concat();
-      dividend = (dividend - modulo) / 26;
-    }
-    column_name
+    reference::column_name(self.col_idx)
   }
 
   pub fn name(&self) -> String {
     [self.get_column_name(), self.row_idx.to_string()].concat()
   }
 
-  pub fn get_col_idx(&self) -> u8 {
+  pub fn get_col_idx(&self) -> u32 {
     self.col_idx
   }
 
-  pub fn get_row_idx(&self) -> u16 {
+  pub fn get_row_idx(&self) -> u32 {
     self.row_idx
   }
 
@@ -68,4 +65,20 @@ impl Cell {
   pub fn get_boundary(&mut self) -> Option<Rectangle> {
     self.boundary
   }
+
+  pub fn get_value(&self) -> &CellValue {
+    &self.value
+  }
+
+  pub fn set_value(&mut self, value: CellValue) {
+    self.value = value;
+  }
+
+  pub fn get_formula(&self) -> Option<&String> {
+    self.formula.as_ref()
+  }
+
+  pub fn set_formula(&mut self, formula: Option<String>) {
+    self.formula = formula;
+  }
 }
@@ -1,25 +1,54 @@
+use super::rectangle::Rectangle;
 use wasm_bindgen::JsCast;
 
 pub struct CellEditor {
   element: web_sys::HtmlInputElement,
+  cell: Option<(u32, u32)>,
 }
 
 impl CellEditor {
-  pub fn new(container_name: &str) -> Self {
-    let window = web_sys::window().expect("No global window exist");
-    let document = window.document().expect("Should have a doc on window");
-    let wrapper = document
-      .get_element_by_id(container_name)
-      .unwrap()
-      .dyn_into::<web_sys::HtmlElement>()
-      .unwrap();
+  /// Create the editor inside `wrapper`, whose coordinate space is used to
+  /// position it over cells.
+  pub fn attach(wrapper: &web_sys::Element) -> Self {
+    let document = wrapper.owner_document().expect("Should have a doc on element");
     let element = document
       .create_element("input")
       .unwrap()
       .dyn_into::<web_sys::HtmlInputElement>()
       .unwrap();
     element.set_class_name("hypersheet-editor");
+    element.style().set_property("position", "absolute").unwrap();
+    element.style().set_property("box-sizing", "border-box").unwrap();
+    element.style().set_property("display", "none").unwrap();
     wrapper.append_child(&element).unwrap();
-    Self { element }
+    Self { element, cell: None }
+  }
+
+  /// Show the editor over `boundary` for the given cell, seeded with `text`.
+  pub fn open(&mut self, col_idx: u32, row_idx: u32, boundary: Rectangle, text: &str) {
+    let style = self.element.style();
+    style.set_property("left", &boundary.x_as_px()).unwrap();
+    style.set_property("top", &boundary.y_as_px()).unwrap();
+    style.set_property("width", &boundary.width_as_px()).unwrap();
+    style.set_property("height", &boundary.height_as_px()).unwrap();
+    style.set_property("display", "block").unwrap();
+    self.element.set_value(text);
+    self.element.focus().unwrap();
+    self.cell = Some((col_idx, row_idx));
+  }
+
+  /// Hide the editor, returning the cell it was editing.
+  pub fn close(&mut self) -> Option<(u32, u32)> {
+    self.element.style().set_property("display", "none").unwrap();
+    self.element.blur().unwrap();
+    self.cell.take()
+  }
+
+  pub fn is_editing(&self) -> bool {
+    self.cell.is_some()
+  }
+
+  pub fn get_value(&self) -> String {
+    self.element.value()
   }
 }
@@ -0,0 +1,244 @@
+use super::csv;
+
+/// Write rows as tab separated text. Fields holding a tab, a quote or a line
+/// break are quoted, with quotes doubled.
+pub fn to_tsv(rows: &[Vec<String>]) -> String {
+  rows
+    .iter()
+    .map(|row| row.iter().map(|field| csv::quote_field(field, '\t')).collect::<Vec<String>>().join("\t"))
+    .collect::<Vec<String>>()
+    .join("\n")
+}
+
+/// Read tab separated text, honouring quoted fields that span lines.
+pub fn parse_tsv(text: &str) -> Vec<Vec<String>> {
+  csv::parse(text, '\t')
+}
+
+/// Write rows as an HTML table.
+pub fn to_html(rows: &[Vec<String>]) -> String {
+  let mut html = String::from("<table>");
+  for row in rows {
+    html.push_str("<tr>");
+    for field in row {
+      html.push_str("<td>");
+      html.push_str(&escape_html(field).replace('\n', "<br>"));
+      html.push_str("</td>");
+    }
+    html.push_str("</tr>");
+  }
+  html.push_str("</table>");
+  html
+}
+
+fn escape_html(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+/// Read the first table in an HTML fragment. Cells spanning several columns
+/// are followed by empty cells; other markup inside cells is dropped. Returns
+/// `None` when there is no table.
+pub fn parse_html(html: &str) -> Option<Vec<Vec<String>>> {
+  let lower = html.to_ascii_lowercase();
+  let start = lower.find("<table")?;
+  let end = lower[start..].find("</table").map_or(html.len(), |end| start + end);
+  let mut rows = vec![];
+  let mut row: Option<Vec<String>> = None;
+  let mut cell: Option<(String, usize)> = None;
+  let mut pos = start;
+  while pos < end {
+    let tag_start = match lower[pos..end].find('<') {
+      Some(offset) => pos + offset,
+      None => end,
+    };
+    if let Some((text, _)) = cell.as_mut() {
+      text.push_str(&html[pos..tag_start]);
+    }
+    if tag_start == end {
+      break;
+    }
+    let tag_end = match lower[tag_start..end].find('>') {
+      Some(offset) => tag_start + offset + 1,
+      None => break,
+    };
+    let tag = &lower[tag_start + 1..tag_end - 1];
+    let name: String = tag
+      .trim_start_matches('/')
+      .chars()
+      .take_while(|c| c.is_ascii_alphanumeric())
+      .collect();
+    let closing = tag.starts_with('/');
+    match (name.as_str(), closing) {
+      ("tr", false) => {
+        finish_cell(&mut cell, &mut row);
+        if let Some(row) = row.take() {
+          rows.push(row);
+        }
+        row = Some(vec![]);
+      }
+      ("tr", true) => {
+        finish_cell(&mut cell, &mut row);
+        if let Some(row) = row.take() {
+          rows.push(row);
+        }
+      }
+      ("td", false) | ("th", false) => {
+        finish_cell(&mut cell, &mut row);
+        let span = attribute(tag, "colspan").and_then(|span| span.parse().ok()).unwrap_or(1);
+        let span = span.min(MAX_COLSPAN);
+        cell = Some((String::new(), span));
+      }
+      ("td", true) | ("th", true) => finish_cell(&mut cell, &mut row),
+      ("br", _) => {
+        if let Some((text, _)) = cell.as_mut() {
+          text.push(LINE_BREAK);
+        }
+      }
+      _ => (),
+    }
+    pos = tag_end;
+  }
+  finish_cell(&mut cell, &mut row);
+  if let Some(row) = row {
+    rows.push(row);
+  }
+  Some(rows)
+}
+
+fn finish_cell(cell: &mut Option<(String, usize)>, row: &mut Option<Vec<String>>) {
+  if let Some((text, span)) = cell.take() {
+    let row = row.get_or_insert_with(Vec::new);
+    row.push(decode_entities(&collapse_whitespace(&text)).trim().to_string());
+    for _ in 1..span {
+      row.push(String::new());
+    }
+  }
+}
+
+/// Largest `colspan` honoured, as in HTML.
+const MAX_COLSPAN: usize = 1000;
+
+/// Stands for `<br>` in cell text until whitespace has been collapsed.
+const LINE_BREAK: char = '\u{0}';
+
+/// Collapse runs of whitespace as HTML rendering does, keeping the line
+/// breaks that came from `<br>`.
+fn collapse_whitespace(text: &str) -> String {
+  text
+    .split(LINE_BREAK)
+    .map(|line| line.split_whitespace().collect::<Vec<&str>>().join(" "))
+    .collect::<Vec<String>>()
+    .join("\n")
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+  let start = tag.find(name)? + name.len();
+  let rest = tag[start..].trim_start().strip_prefix('=')?.trim_start();
+  let value: String = match rest.chars().next()? {
+    quote @ '"' | quote @ '\'' => rest[1..].chars().take_while(|c| *c != quote).collect(),
+    _ => rest.chars().take_while(|c| !c.is_whitespace()).collect(),
+  };
+  Some(value)
+}
+
+fn decode_entities(text: &str) -> String {
+  let mut decoded = String::new();
+  let mut rest = text;
+  while let Some(amp) = rest.find('&') {
+    decoded.push_str(&rest[..amp]);
+    rest = &rest[amp..];
+    let semi = match rest.find(';') {
+      Some(semi) if semi <= 10 => semi,
+      _ => {
+        decoded.push('&');
+        rest = &rest[1..];
+        continue;
+      }
+    };
+    let entity = &rest[1..semi];
+    let c = match entity {
+      "amp" => Some('&'),
+      "lt" => Some('<'),
+      "gt" => Some('>'),
+      "quot" => Some('"'),
+      "apos" => Some('\''),
+      "nbsp" => Some(' '),
+      _ if entity.starts_with("#x") || entity.starts_with("#X") => {
+        u32::from_str_radix(&entity[2..], 16).ok().and_then(std::char::from_u32)
+      }
+      _ if entity.starts_with('#') => entity[1..].parse().ok().and_then(std::char::from_u32),
+      _ => None,
+    };
+    match c {
+      Some(c) => {
+        decoded.push(c);
+        rest = &rest[semi + 1..];
+      }
+      None => {
+        decoded.push('&');
+        rest = &rest[1..];
+      }
+    }
+  }
+  decoded.push_str(rest);
+  decoded
+}
+
+/// Columns and rows a paste of `data` covers when `target` is selected: the
+/// whole target when the data tiles it exactly, else just the data.
+pub fn paste_extent(data: (u32, u32), target: (u32, u32)) -> (u32, u32) {
+  let (data_cols, data_rows) = data;
+  let (target_cols, target_rows) = target;
+  if data_cols > 0 && data_rows > 0 && target_cols % data_cols == 0 && target_rows % data_rows == 0 {
+    target
+  } else {
+    data
+  }
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::{paste_extent, parse_html, parse_tsv, to_html, to_tsv};
+  #[test]
+  fn tsv_round_trip() {
+    let rows = vec![
+      vec!["a".to_string(), "tab\there".to_string(), "".to_string()],
+      vec!["say \"hi\"".to_string(), "two\nlines".to_string(), "3".to_string()],
+    ];
+    let text = to_tsv(&rows);
+    assert_eq!(text, "a\t\"tab\there\"\t\n\"say \"\"hi\"\"\"\t\"two\nlines\"\t3");
+    assert_eq!(parse_tsv(&text), rows);
+    assert_eq!(parse_tsv("1\t2\r\n3\t4\r\n"), vec![vec!["1", "2"], vec!["3", "4"]]);
+    assert_eq!(parse_tsv("x\n\ny"), vec![vec!["x"], vec![""], vec!["y"]]);
+  }
+
+  #[test]
+  fn html_tables() {
+    let rows = vec![vec!["<b>&".to_string(), "a\nb".to_string()]];
+    assert_eq!(parse_html(&to_html(&rows)), Some(rows));
+    let html = r#"<html><body><!--StartFragment--><TABLE border=1>
+      <tr><th colspan="2">Head&nbsp;line</th></tr>
+      <tr><td><span style="x">1</span></td><td>
+        caf&#233;
+      </td></tr></TABLE></body></html>"#;
+    assert_eq!(
+      parse_html(html),
+      Some(vec![vec!["Head line".to_string(), "".to_string()], vec!["1".to_string(), "café".to_string()]])
+    );
+    assert_eq!(parse_html("<p>no table</p>"), None);
+    let wide = parse_html(r#"<table><tr><td colspan="100000000">x</td></tr></table>"#).unwrap();
+    assert_eq!(wide[0].len(), 1000);
+  }
+
+  #[test]
+  fn paste_tiling() {
+    assert_eq!(paste_extent((2, 1), (6, 3)), (6, 3));
+    assert_eq!(paste_extent((2, 2), (3, 4)), (2, 2));
+    assert_eq!(paste_extent((1, 1), (1, 1)), (1, 1));
+  }
+}
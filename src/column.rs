@@ -1,13 +1,25 @@
 use std::collections::BTreeMap;
 
+use super::reference::shift_index;
+use super::sizeindex::{step_shown, SizeIndex};
+
 pub struct Column {
-  idx: u8,
+  idx: u32,
   width: u16,
+  hidden: bool,
 }
 
 impl Column {
-  pub fn new(idx: u8) -> Self {
-    Self { idx, width: 20 }
+  pub fn new(idx: u32) -> Self {
+    Self {
+      idx,
+      width: 20,
+      hidden: false,
+    }
+  }
+
+  pub fn get_idx(&self) -> u32 {
+    self.idx
   }
 
   pub fn get_width(&self) -> u16 {
@@ -17,31 +29,300 @@ impl Column {
   pub fn set_width(&mut self, width: u16) {
     self.width = width;
   }
+
+  pub fn is_hidden(&self) -> bool {
+    self.hidden
+  }
+
+  pub fn set_hidden(&mut self, hidden: bool) {
+    self.hidden = hidden;
+  }
+
+  /// Size taken up on screen: none when hidden.
+  fn get_visible_width(&self) -> u16 {
+    if self.hidden {
+      0
+    } else {
+      self.width
+    }
+  }
 }
 
+/// Sizes of the columns, and the order they are shown in. Columns keep their
+/// index wherever they are shown; sizes are looked up by index, while offsets
+/// and the size index go by position on screen.
 pub struct ColumnManager {
-  items: BTreeMap<u8, Column>,
+  items: BTreeMap<u32, Column>,
+  col_count: u32,
+  default_width: u16,
+  index: SizeIndex,
+  /// Column shown at each position, empty while none have been moved.
+  order: Vec<u32>,
+  /// Position of each column, the inverse of `order`.
+  positions: Vec<u32>,
 }
 
 impl ColumnManager {
-  pub fn new() -> Self {
-    Self { items: BTreeMap::new() }
+  pub fn new(col_count: u32, default_width: u16) -> Self {
+    Self {
+      items: BTreeMap::new(),
+      col_count,
+      default_width,
+      index: SizeIndex::new(col_count, default_width),
+      order: vec![],
+      positions: vec![],
+    }
+  }
+
+  fn rebuild_index(&mut self) {
+    let widths = self
+      .items
+      .values()
+      .map(|col| (self.get_position(col.idx), col.get_visible_width()));
+    self.index = SizeIndex::build(self.col_count, self.default_width, widths);
   }
 
   pub fn set_column(&mut self, column: Column) {
+    let delta = column.get_visible_width() as i64 - self.get_width(column.idx) as i64;
+    self.index.add(self.get_position(column.idx), delta);
     self.items.insert(column.idx, column);
   }
 
-  pub fn get_column(&self, idx: u8) -> Option<&Column> {
+  pub fn get_column(&self, idx: u32) -> Option<&Column> {
     self.items.get(&idx)
   }
 
-  pub fn get_cols_within(&self, col_idx: u8) -> Vec<&Column> {
+  /// Size of the column on screen, zero when hidden.
+  pub fn get_width(&self, idx: u32) -> u16 {
+    match self.items.get(&idx) {
+      Some(col) => col.get_visible_width(),
+      None => self.default_width,
+    }
+  }
+
+  /// Size of the column when shown.
+  pub fn get_shown_width(&self, idx: u32) -> u16 {
+    match self.items.get(&idx) {
+      Some(col) => col.width,
+      None => self.default_width,
+    }
+  }
+
+  pub fn is_hidden(&self, idx: u32) -> bool {
+    matches!(self.items.get(&idx), Some(col) if col.hidden)
+  }
+
+  /// Hide or show a column, keeping its size for when it is shown.
+  pub fn set_hidden(&mut self, idx: u32, hidden: bool) {
+    let mut column = Column::new(idx);
+    column.width = self.get_shown_width(idx);
+    column.hidden = hidden;
+    self.set_column(column);
+  }
+
+  pub fn get_default_width(&self) -> u16 {
+    self.default_width
+  }
+
+  pub fn set_default_width(&mut self, default_width: u16) {
+    self.default_width = default_width;
+    self.rebuild_index();
+  }
+
+  /// Move custom sizes as columns are inserted or deleted, see `shift_index`.
+  /// Sizes pushed past the last column are dropped. Returns the sizes of the
+  /// deleted columns, and whether they were hidden.
+  /// Moved columns keep their place on screen, inserted ones are shown
+  /// before the column they were inserted at.
+  pub fn shift(&mut self, at: u32, delta: i64) -> Vec<(u32, u16, bool)> {
+    let mut removed = vec![];
+    let items = std::mem::take(&mut self.items);
+    for (idx, mut item) in items {
+      match shift_index(idx, at, delta) {
+        Some(new_idx) if new_idx <= self.col_count => {
+          item.idx = new_idx;
+          self.items.insert(new_idx, item);
+        }
+        Some(_) => (),
+        None => removed.push((idx, item.width, item.hidden)),
+      }
+    }
+    let mut order = vec![];
+    for idx in self.order.iter() {
+      if delta > 0 && *idx == at {
+        order.extend(at..at.saturating_add(delta as u32));
+      }
+      match shift_index(*idx, at, delta) {
+        Some(new_idx) if new_idx <= self.col_count => order.push(new_idx),
+        _ => (),
+      }
+    }
+    self.set_full_order(order);
+    removed
+  }
+
+  pub fn get_col_count(&self) -> u32 {
+    self.col_count
+  }
+
+  pub fn set_col_count(&mut self, col_count: u32) {
+    self.col_count = col_count;
+    let order = std::mem::take(&mut self.order);
+    self.set_full_order(order);
+  }
+
+  /// Column shown at a position.
+  pub fn get_column_at(&self, pos: u32) -> u32 {
+    match pos.checked_sub(1).and_then(|i| self.order.get(i as usize)) {
+      Some(idx) => *idx,
+      None => pos,
+    }
+  }
+
+  /// Position a column is shown at.
+  pub fn get_position(&self, idx: u32) -> u32 {
+    match idx.checked_sub(1).and_then(|i| self.positions.get(i as usize)) {
+      Some(pos) => *pos,
+      None => idx,
+    }
+  }
+
+  /// Show the column at position `from` at position `to` instead, moving
+  /// those in between over by one.
+  pub fn move_column(&mut self, from: u32, to: u32) {
+    if from == to || from == 0 || to == 0 || from.max(to) > self.col_count {
+      return;
+    }
+    let mut order = self.get_full_order();
+    let idx = order.remove(from as usize - 1);
+    order.insert(to as usize - 1, idx);
+    self.set_full_order(order);
+  }
+
+  /// Columns in the order shown, leaving off those at the end that are in
+  /// their own place.
+  pub fn get_order(&self) -> Vec<u32> {
+    let mut order = self.order.clone();
+    while matches!(order.last(), Some(idx) if *idx as usize == order.len()) {
+      order.pop();
+    }
+    order
+  }
+
+  /// Show columns in the given order, followed by the rest in their own
+  /// place. The order must hold each of `1..=n` once, for an `n` up to the
+  /// column count.
+  pub fn set_order(&mut self, order: &[u32]) -> Result<(), String> {
+    let len = order.len();
+    if len > self.col_count as usize {
+      return Err(format!("Column order lists {} columns, more than the {} there are", len, self.col_count));
+    }
+    let mut seen = vec![false; len];
+    for idx in order.iter() {
+      match (*idx as usize).checked_sub(1) {
+        Some(i) if i < len && !seen[i] => seen[i] = true,
+        _ => return Err(format!("Column order must hold each of 1 to {} once", len)),
+      }
+    }
+    self.set_full_order(order.to_vec());
+    Ok(())
+  }
+
+  /// Every column in the order shown.
+  fn get_full_order(&self) -> Vec<u32> {
+    (1..=self.col_count).map(|pos| self.get_column_at(pos)).collect()
+  }
+
+  /// Take the columns to show first, then any left out in their own order,
+  /// dropping those past the column count.
+  fn set_full_order(&mut self, mut order: Vec<u32>) {
+    let count = self.col_count as usize;
+    let mut seen = vec![false; count];
+    order.retain(|idx| *idx >= 1 && *idx as usize <= count && !std::mem::replace(&mut seen[*idx as usize - 1], true));
+    if !order.is_empty() {
+      order.extend((1..=self.col_count).filter(|idx| !seen[*idx as usize - 1]));
+    }
+    if order.iter().enumerate().all(|(i, idx)| *idx as usize == i + 1) {
+      order.clear();
+    }
+    self.positions = vec![0; if order.is_empty() { 0 } else { count }];
+    for (i, idx) in order.iter().enumerate() {
+      self.positions[*idx as usize - 1] = i as u32 + 1;
+    }
+    self.order = order;
+    self.rebuild_index();
+  }
+
+  /// Left offset of the column at a position.
+  pub fn get_offset(&self, pos: u32) -> f64 {
+    self.index.end_offset(pos.saturating_sub(1))
+  }
+
+  /// Number of positions taken by columns ending before `offset`, with the
+  /// offset where they end.
+  pub fn get_cols_before(&self, offset: f64) -> (u32, f64) {
+    self.index.find(offset)
+  }
+
+  /// Column `delta` shown columns away from `idx` in the order shown,
+  /// skipping hidden ones and stopping at the last shown column before an
+  /// edge.
+  pub fn step_visible(&self, idx: u32, delta: i64) -> u32 {
+    let pos = step_shown(self.get_position(idx), delta, self.col_count, |pos| {
+      self.get_width(self.get_column_at(pos)) > 0
+    });
+    self.get_column_at(pos)
+  }
+
+  /// Total width of all columns.
+  pub fn get_extent(&self) -> f64 {
+    self.index.end_offset(self.col_count)
+  }
+
+  pub fn get_cols_within(&self, col_idx: u32) -> Vec<&Column> {
     let mut results = vec![];
-    let cols: Vec<&u8> = self.items.keys().filter(|x| x <= &&col_idx).collect();
+    let cols: Vec<&u32> = self.items.keys().filter(|x| x <= &&col_idx).collect();
     for col in cols {
       results.push(self.items.get(col).unwrap());
     }
     results
   }
 }
+
+#[cfg(test)]
+mod tests {
+
+  use crate::column::{Column, ColumnManager};
+  #[test]
+  fn hidden_columns() {
+    let mut cols = ColumnManager::new(6, 100);
+    let mut column = Column::new(2);
+    column.set_width(60);
+    cols.set_column(column);
+    cols.set_hidden(2, true);
+    cols.set_hidden(3, true);
+    assert!(cols.is_hidden(2) && cols.is_hidden(3) && !cols.is_hidden(4));
+    assert_eq!((cols.get_width(2), cols.get_shown_width(2)), (0, 60));
+    assert_eq!((cols.get_width(3), cols.get_shown_width(3)), (0, 100));
+    assert_eq!(cols.get_offset(2), 100.0);
+    assert_eq!(cols.get_offset(4), 100.0);
+    assert_eq!(cols.get_offset(5), 200.0);
+    assert_eq!(cols.get_extent(), 400.0);
+    assert_eq!(cols.get_cols_before(150.0), (3, 100.0));
+
+    assert_eq!(cols.step_visible(1, 1), 4);
+    assert_eq!(cols.step_visible(4, -1), 1);
+    assert_eq!(cols.step_visible(1, 9), 6);
+
+    // Stepping goes by the order shown, with sizes following the columns.
+    cols.move_column(6, 1);
+    assert_eq!(cols.get_offset(3), 200.0);
+    assert_eq!(cols.step_visible(6, 1), 1);
+    assert_eq!(cols.step_visible(1, 1), 4);
+    assert_eq!(cols.step_visible(6, -1), 6);
+
+    cols.set_hidden(2, false);
+    assert_eq!(cols.get_width(2), 60);
+    assert_eq!(cols.step_visible(1, 1), 2);
+  }
+}
@@ -0,0 +1,148 @@
+/// Delimiters recognised when sniffing, in order of preference on a tie.
+const DELIMITERS: [char; 4] = [',', ';', '\t', '|'];
+
+/// Number of lines looked at when sniffing the delimiter.
+const SNIFF_LINES: usize = 20;
+
+pub fn strip_bom(text: &str) -> &str {
+  text.trim_start_matches('\u{feff}')
+}
+
+/// Guess the delimiter of delimited text: the candidate occurring the same,
+/// non-zero number of times on the most of the first lines, then the most
+/// often. Quoted fields are skipped. Falls back to a comma.
+pub fn sniff_delimiter(text: &str) -> char {
+  let mut counts: Vec<[usize; 4]> = vec![[0; 4]];
+  let mut quoted = false;
+  for c in text.chars() {
+    match c {
+      '"' => quoted = !quoted,
+      '\n' if !quoted => {
+        if counts.len() == SNIFF_LINES {
+          break;
+        }
+        counts.push([0; 4]);
+      }
+      _ if !quoted => {
+        if let Some(idx) = DELIMITERS.iter().position(|delimiter| *delimiter == c) {
+          counts.last_mut().unwrap()[idx] += 1;
+        }
+      }
+      _ => (),
+    }
+  }
+  if counts.len() > 1 && counts.last().into_iter().flatten().all(|count| *count == 0) {
+    counts.pop();
+  }
+  let mut best = (0, 0, ',');
+  for (idx, delimiter) in DELIMITERS.iter().enumerate() {
+    let first = counts[0][idx];
+    if first == 0 {
+      continue;
+    }
+    let consistent = counts.iter().filter(|line| line[idx] == first).count();
+    let total = counts.iter().map(|line| line[idx]).sum();
+    if (consistent, total) > (best.0, best.1) {
+      best = (consistent, total, *delimiter);
+    }
+  }
+  best.2
+}
+
+/// Read delimited text as RFC 4180 describes it: fields may be quoted, with
+/// quotes doubled, and quoted fields may span lines. Lines may end in CRLF or
+/// LF, and a final line break does not start another row.
+pub fn parse(text: &str, delimiter: char) -> Vec<Vec<String>> {
+  let mut rows = vec![];
+  let mut row = vec![];
+  let mut field = String::new();
+  let mut quoted = false;
+  let mut chars = text.chars().peekable();
+  while let Some(c) = chars.next() {
+    if quoted {
+      match c {
+        '"' if chars.peek() == Some(&'"') => {
+          chars.next();
+          field.push('"');
+        }
+        '"' => quoted = false,
+        _ => field.push(c),
+      }
+      continue;
+    }
+    match c {
+      '"' if field.is_empty() => quoted = true,
+      '\r' | '\n' => {
+        if c == '\r' && chars.peek() == Some(&'\n') {
+          chars.next();
+        }
+        row.push(std::mem::take(&mut field));
+        rows.push(std::mem::take(&mut row));
+      }
+      _ if c == delimiter => row.push(std::mem::take(&mut field)),
+      _ => field.push(c),
+    }
+  }
+  if !field.is_empty() || !row.is_empty() {
+    row.push(field);
+    rows.push(row);
+  }
+  rows
+}
+
+/// Read CSV of unknown dialect: a byte order mark is dropped and the delimiter
+/// sniffed.
+pub fn parse_sniffed(text: &str) -> Vec<Vec<String>> {
+  let text = strip_bom(text);
+  parse(text, sniff_delimiter(text))
+}
+
+/// Quote a field if it holds the delimiter, a quote or a line break.
+pub fn quote_field(field: &str, delimiter: char) -> String {
+  if field.contains(&[delimiter, '"', '\n', '\r'][..]) {
+    ["\"", &field.replace('"', "\"\""), "\""].concat()
+  } else {
+    field.to_string()
+  }
+}
+
+/// Write rows as RFC 4180 CSV, with CRLF line endings.
+pub fn write(rows: &[Vec<String>], delimiter: char) -> String {
+  let mut text = String::new();
+  for row in rows {
+    let fields: Vec<String> = row.iter().map(|field| quote_field(field, delimiter)).collect();
+    text.push_str(&fields.join(&delimiter.to_string()));
+    text.push_str("\r\n");
+  }
+  text
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::{parse, parse_sniffed, sniff_delimiter, write};
+  #[test]
+  fn dialects() {
+    assert_eq!(sniff_delimiter("a,b;c\n1,2;3\n4,5;6\n"), ',');
+    assert_eq!(sniff_delimiter("name;note\nx;\"a, b, c\"\ny;z"), ';');
+    assert_eq!(sniff_delimiter("a\tb\n1\t2"), '\t');
+    assert_eq!(sniff_delimiter("a|b|c\n1|2|3"), '|');
+    assert_eq!(sniff_delimiter("single column\nvalues"), ',');
+    assert_eq!(
+      parse_sniffed("\u{feff}id;text\r\n1;\"two\r\nlines; \"\"quoted\"\"\"\r\n"),
+      vec![vec!["id", "text"], vec!["1", "two\r\nlines; \"quoted\""]]
+    );
+    assert_eq!(parse("a,,\n", ','), vec![vec!["a", "", ""]]);
+  }
+
+  #[test]
+  fn write_round_trip() {
+    let rows = vec![
+      vec!["1".to_string(), "a,b".to_string()],
+      vec!["say \"hi\"".to_string(), "x\ny".to_string()],
+    ];
+    let text = write(&rows, ',');
+    assert_eq!(text, "1,\"a,b\"\r\n\"say \"\"hi\"\"\",\"x\ny\"\r\n");
+    assert_eq!(parse(&text, ','), rows);
+  }
+}
@@ -0,0 +1,235 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use super::cell::Cell;
+use super::reference::{RangeKind, RangeRef};
+
+/// Cells to recalculate in order, then the cells caught in reference cycles.
+pub type RecalcOrder = (Vec<(u32, u32)>, Vec<(u32, u32)>);
+
+/// Tracks which formula cells read from which cells so that a change can be
+/// propagated to its dependents only. Cells are keyed by `Cell::name()`.
+pub struct DependencyGraph {
+  positions: BTreeMap<String, (u32, u32)>,
+  precedents: BTreeMap<String, Vec<RangeRef>>,
+  cell_dependents: BTreeMap<String, BTreeSet<String>>,
+  range_dependents: BTreeMap<String, Vec<RangeRef>>,
+  /// Formula cells reading ranges, by each column or each row the ranges
+  /// span, see `index_lines`, so that a lookup only checks those ranges.
+  cols_read: BTreeMap<u32, BTreeSet<String>>,
+  rows_read: BTreeMap<u32, BTreeSet<String>>,
+}
+
+/// Lines a range is indexed by: the columns it spans, or the rows when those
+/// are fewer. Whole columns and rows go by the lines they name.
+fn index_lines(range: &RangeRef) -> (bool, std::ops::RangeInclusive<u32>) {
+  let by_col = match range.kind {
+    RangeKind::Columns => true,
+    RangeKind::Rows => false,
+    RangeKind::Cells => range.end.col - range.start.col <= range.end.row - range.start.row,
+  };
+  if by_col {
+    (true, range.start.col..=range.end.col)
+  } else {
+    (false, range.start.row..=range.end.row)
+  }
+}
+
+impl DependencyGraph {
+  pub fn new() -> Self {
+    Self {
+      positions: BTreeMap::new(),
+      precedents: BTreeMap::new(),
+      cell_dependents: BTreeMap::new(),
+      range_dependents: BTreeMap::new(),
+      cols_read: BTreeMap::new(),
+      rows_read: BTreeMap::new(),
+    }
+  }
+
+  /// Record the references read by the formula in the given cell, replacing
+  /// whatever was recorded for it before.
+  pub fn set_precedents(&mut self, col_idx: u32, row_idx: u32, references: Vec<RangeRef>) {
+    self.remove(col_idx, row_idx);
+    let name = Cell::new(col_idx, row_idx).name();
+    let mut ranges = vec![];
+    for reference in references.iter() {
+      if reference.is_cell() {
+        self
+          .cell_dependents
+          .entry(Cell::new(reference.start.col, reference.start.row).name())
+          .or_default()
+          .insert(name.clone());
+      } else {
+        let (by_col, lines) = index_lines(reference);
+        let index = if by_col { &mut self.cols_read } else { &mut self.rows_read };
+        for line in lines {
+          index.entry(line).or_default().insert(name.clone());
+        }
+        ranges.push(*reference);
+      }
+    }
+    if !ranges.is_empty() {
+      self.range_dependents.insert(name.clone(), ranges);
+    }
+    self.positions.insert(name.clone(), (col_idx, row_idx));
+    self.precedents.insert(name, references);
+  }
+
+  /// Forget the formula held by the given cell.
+  pub fn remove(&mut self, col_idx: u32, row_idx: u32) {
+    let name = Cell::new(col_idx, row_idx).name();
+    if let Some(references) = self.precedents.remove(&name) {
+      for reference in references {
+        if reference.is_cell() {
+          let key = Cell::new(reference.start.col, reference.start.row).name();
+          let empty = match self.cell_dependents.get_mut(&key) {
+            Some(dependents) => {
+              dependents.remove(&name);
+              dependents.is_empty()
+            }
+            None => false,
+          };
+          if empty {
+            self.cell_dependents.remove(&key);
+          }
+        }
+      }
+    }
+    for range in self.range_dependents.remove(&name).unwrap_or_default() {
+      let (by_col, lines) = index_lines(&range);
+      let index = if by_col { &mut self.cols_read } else { &mut self.rows_read };
+      for line in lines {
+        let empty = match index.get_mut(&line) {
+          Some(dependents) => {
+            dependents.remove(&name);
+            dependents.is_empty()
+          }
+          None => false,
+        };
+        if empty {
+          index.remove(&line);
+        }
+      }
+    }
+    self.positions.remove(&name);
+  }
+
+  /// Formula cells reading directly from the given cell.
+  pub fn direct_dependents(&self, col_idx: u32, row_idx: u32) -> BTreeSet<String> {
+    let mut dependents = match self.cell_dependents.get(&Cell::new(col_idx, row_idx).name()) {
+      Some(names) => names.clone(),
+      None => BTreeSet::new(),
+    };
+    let candidates = self.cols_read.get(&col_idx).into_iter().chain(self.rows_read.get(&row_idx)).flatten();
+    for name in candidates {
+      if self.range_dependents[name].iter().any(|range| range.contains(col_idx, row_idx)) {
+        dependents.insert(name.clone());
+      }
+    }
+    dependents
+  }
+
+  /// Order in which formula cells must be recalculated after the given cell
+  /// changed, with precedents before dependents. `include_self` adds the cell
+  /// itself when it holds a formula. Cells that take part in a reference cycle
+  /// are returned separately.
+  pub fn recalc_order(&self, col_idx: u32, row_idx: u32, include_self: bool) -> RecalcOrder {
+    self.recalc_order_from(&[(col_idx, row_idx)], include_self)
+  }
+
+  /// Like `recalc_order`, after several cells changed at once.
+  pub fn recalc_order_from(&self, cells: &[(u32, u32)], include_self: bool) -> RecalcOrder {
+    let mut affected = BTreeSet::new();
+    let mut queue = VecDeque::new();
+    for (col_idx, row_idx) in cells.iter() {
+      let name = Cell::new(*col_idx, *row_idx).name();
+      if include_self && self.positions.contains_key(&name) {
+        affected.insert(name);
+      }
+      queue.push_back((*col_idx, *row_idx));
+    }
+    while let Some((col, row)) = queue.pop_front() {
+      for dependent in self.direct_dependents(col, row) {
+        if affected.insert(dependent.clone()) {
+          queue.push_back(self.positions[&dependent]);
+        }
+      }
+    }
+    self.sort(&affected)
+  }
+
+  /// Order in which every formula cell must be evaluated.
+  pub fn full_order(&self) -> RecalcOrder {
+    let affected: BTreeSet<String> = self.positions.keys().cloned().collect();
+    self.sort(&affected)
+  }
+
+  fn sort(&self, affected: &BTreeSet<String>) -> RecalcOrder {
+    let mut in_degree: BTreeMap<&String, usize> = affected.iter().map(|name| (name, 0)).collect();
+    let mut edges: BTreeMap<&String, Vec<String>> = BTreeMap::new();
+    for name in affected.iter() {
+      let (col, row) = self.positions[name];
+      let dependents: Vec<String> = self
+        .direct_dependents(col, row)
+        .into_iter()
+        .filter(|dependent| affected.contains(dependent))
+        .collect();
+      for dependent in dependents.iter() {
+        *in_degree.get_mut(dependent).unwrap() += 1;
+      }
+      edges.insert(name, dependents);
+    }
+
+    let mut ready: VecDeque<&String> = in_degree
+      .iter()
+      .filter(|(_, degree)| **degree == 0)
+      .map(|(name, _)| *name)
+      .collect();
+    let mut order = vec![];
+    while let Some(name) = ready.pop_front() {
+      order.push(self.positions[name]);
+      for dependent in edges[name].iter() {
+        let degree = in_degree.get_mut(dependent).unwrap();
+        *degree -= 1;
+        if *degree == 0 {
+          ready.push_back(affected.get(dependent).unwrap());
+        }
+      }
+    }
+    let cyclic = in_degree
+      .iter()
+      .filter(|(_, degree)| **degree > 0)
+      .map(|(name, _)| self.positions[*name])
+      .collect();
+    (order, cyclic)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+
+  use crate::cell::Cell;
+  use crate::dependency::DependencyGraph;
+  use crate::reference::RangeRef;
+  fn names(graph: &DependencyGraph, col_idx: u32, row_idx: u32) -> Vec<String> {
+    graph.direct_dependents(col_idx, row_idx).into_iter().collect()
+  }
+
+  #[test]
+  fn range_dependents() {
+    let mut graph = DependencyGraph::new();
+    let ranges = |text: &str| text.split(',').map(|range| RangeRef::parse(range).unwrap()).collect();
+    graph.set_precedents(5, 1, ranges("A1:B3"));
+    graph.set_precedents(5, 2, ranges("A2:Z2,C:C"));
+    graph.set_precedents(5, 3, ranges("4:5,A1"));
+    assert_eq!(names(&graph, 2, 2), [Cell::new(5, 1).name(), Cell::new(5, 2).name()]);
+    assert_eq!(names(&graph, 3, 100), [Cell::new(5, 2).name()]);
+    assert_eq!(names(&graph, 7, 5), [Cell::new(5, 3).name()]);
+    assert_eq!(names(&graph, 1, 1), [Cell::new(5, 1).name(), Cell::new(5, 3).name()]);
+    assert!(names(&graph, 4, 3).is_empty());
+
+    graph.remove(5, 2);
+    assert_eq!(names(&graph, 2, 2), [Cell::new(5, 1).name()]);
+    assert!(!graph.cols_read.contains_key(&3) && !graph.rows_read.contains_key(&2));
+  }
+}
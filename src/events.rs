@@ -36,6 +36,14 @@ pub trait CustomEvent {
   ) -> web_sys::CustomEvent;
 }
 
+/// Create a bubbling custom event carrying `detail` as its detail object.
+pub fn create_detail_event(name: &str, detail: &JsValue) -> web_sys::CustomEvent {
+  let mut custom_event_init = web_sys::CustomEventInit::new();
+  custom_event_init.bubbles(true);
+  custom_event_init.detail(detail);
+  web_sys::CustomEvent::new_with_event_init_dict(name, &custom_event_init).unwrap()
+}
+
 pub trait MousePosition {
   fn location(&self, boundary: BoundingRect, zoom_factor: f64) -> Point;
 }
@@ -0,0 +1,496 @@
+use super::value::{is_date_serial, serial_to_ymd, CellValue};
+
+const MONTHS: [&str; 12] = [
+  "January",
+  "February",
+  "March",
+  "April",
+  "May",
+  "June",
+  "July",
+  "August",
+  "September",
+  "October",
+  "November",
+  "December",
+];
+
+const DAYS: [&str; 7] = ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday"];
+
+const COLORS: [&str; 8] = ["black", "blue", "cyan", "green", "magenta", "red", "white", "yellow"];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+  Literal(String),
+  /// A `0`, `#` or `?` placeholder.
+  Digit(char),
+  Point,
+  Comma,
+  Percent,
+  /// `E+` when true, `E-` when false.
+  Exponent(bool),
+  Text,
+  General,
+  /// A run of `y`, `m`, `d`, `h`, `s` or, for minutes, `n`, and its length.
+  Date(char, usize),
+  /// `AM/PM`, or `A/P` when short, in lower case when lower.
+  AmPm { short: bool, lower: bool },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Section {
+  color: Option<&'static str>,
+  tokens: Vec<Token>,
+  date: bool,
+  grouping: bool,
+  /// Trailing commas, each dividing by a thousand.
+  scale: i32,
+}
+
+/// An Excel format code such as `#,##0.00`, `0%`, `$#,##0;[Red]($#,##0)`,
+/// `yyyy-mm-dd hh:mm`, `0.00E+00` or `@`. Up to four `;` separated sections
+/// format positive numbers, negative numbers, zero and text, each optionally
+/// coloured by a `[Red]` style prefix. Conditions and other bracketed codes
+/// are ignored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NumberFormat {
+  sections: Vec<Section>,
+}
+
+impl NumberFormat {
+  pub fn parse(code: &str) -> Result<Self, String> {
+    let sections = split_sections(code)?
+      .iter()
+      .map(|section| parse_section(section))
+      .collect::<Result<Vec<Section>, String>>()?;
+    if sections.len() > 4 {
+      return Err(format!("Too many sections in number format: {}", code));
+    }
+    Ok(Self { sections })
+  }
+
+  /// Text of a value under this format, with the colour its section sets.
+  /// Booleans and errors are shown as they are.
+  pub fn format(&self, value: &CellValue) -> (String, Option<&'static str>) {
+    let number = match value {
+      CellValue::Number(number) => *number,
+      CellValue::DateTime(serial) => *serial,
+      CellValue::Text(text) => {
+        let section = self
+          .sections
+          .get(3)
+          .or_else(|| self.sections.iter().find(|section| section.tokens.contains(&Token::Text)));
+        return match section {
+          Some(section) => (section.format_text(text), section.color),
+          None => (text.clone(), None),
+        };
+      }
+      _ => return (value.to_string(), None),
+    };
+    let serial = number;
+    let (section, number, minus) = match self.sections.len() {
+      len if number < 0.0 && len >= 2 => (&self.sections[1], -number, false),
+      len if number == 0.0 && len >= 3 => (&self.sections[2], number, false),
+      _ => (&self.sections[0], number.abs(), number < 0.0),
+    };
+    let numeric = section
+      .tokens
+      .iter()
+      .any(|token| matches!(token, Token::Digit(_) | Token::Date(..) | Token::General));
+    if !numeric && section.tokens.contains(&Token::Text) {
+      return (value.to_string(), None);
+    }
+    // Dates go by the serial as it is, and like Excel show `####` when it is
+    // not a day that can be shown.
+    let text = if section.date && !is_date_serial(serial) {
+      "####".to_string()
+    } else if section.date {
+      section.format_date(serial)
+    } else {
+      section.format_number(number)
+    };
+    let text = if minus && !section.date { format!("-{}", text) } else { text };
+    (text, section.color)
+  }
+}
+
+/// Split at `;` outside quotes and brackets, and not where taken as the
+/// character after `\`, `_` or `*`.
+fn split_sections(code: &str) -> Result<Vec<String>, String> {
+  let mut sections = vec![String::new()];
+  let mut chars = code.chars();
+  while let Some(c) = chars.next() {
+    let section = sections.last_mut().unwrap();
+    match c {
+      ';' => sections.push(String::new()),
+      '"' | '[' => {
+        let close = if c == '"' { '"' } else { ']' };
+        section.push(c);
+        loop {
+          match chars.next() {
+            Some(next) => {
+              section.push(next);
+              if next == close {
+                break;
+              }
+            }
+            None => return Err(format!("Unterminated {} in number format: {}", c, code)),
+          }
+        }
+      }
+      '\\' | '_' | '*' => {
+        section.push(c);
+        if let Some(next) = chars.next() {
+          section.push(next);
+        }
+      }
+      _ => section.push(c),
+    }
+  }
+  Ok(sections)
+}
+
+fn parse_section(code: &str) -> Result<Section, String> {
+  let chars: Vec<char> = code.chars().collect();
+  let mut tokens = vec![];
+  let mut color = None;
+  let mut i = 0;
+  while i < chars.len() {
+    let c = chars[i];
+    let rest: String = chars[i..].iter().collect();
+    let upper = rest.to_uppercase();
+    i += 1;
+    match c {
+      '"' | '[' => {
+        let close = if c == '"' { '"' } else { ']' };
+        let end = match chars[i..].iter().position(|next| *next == close) {
+          Some(end) => end + i,
+          None => return Err(format!("Unterminated {} in number format: {}", c, code)),
+        };
+        let content: String = chars[i..end].iter().collect();
+        i = end + 1;
+        if c == '"' {
+          tokens.push(Token::Literal(content));
+          continue;
+        }
+        if let Some(currency) = content.strip_prefix('$') {
+          tokens.push(Token::Literal(currency.split('-').next().unwrap().to_string()));
+        } else if let Some(name) = COLORS.iter().find(|name| name.eq_ignore_ascii_case(&content)) {
+          color = Some(*name);
+        }
+      }
+      '\\' | '_' | '*' => {
+        if let Some(next) = chars.get(i) {
+          match c {
+            '\\' => tokens.push(Token::Literal(next.to_string())),
+            '_' => tokens.push(Token::Literal(" ".to_string())),
+            _ => (),
+          }
+          i += 1;
+        }
+      }
+      '0' | '#' | '?' => tokens.push(Token::Digit(c)),
+      '.' => tokens.push(Token::Point),
+      ',' => tokens.push(Token::Comma),
+      '%' => tokens.push(Token::Percent),
+      '@' => tokens.push(Token::Text),
+      'E' | 'e' if matches!(chars.get(i), Some('+') | Some('-')) => {
+        tokens.push(Token::Exponent(chars[i] == '+'));
+        i += 1;
+      }
+      _ if upper.starts_with("GENERAL") => {
+        tokens.push(Token::General);
+        i += 6;
+      }
+      _ if upper.starts_with("AM/PM") => {
+        tokens.push(Token::AmPm { short: false, lower: c == 'a' });
+        i += 4;
+      }
+      _ if upper.starts_with("A/P") => {
+        tokens.push(Token::AmPm { short: true, lower: c == 'a' });
+        i += 2;
+      }
+      'y' | 'Y' | 'm' | 'M' | 'd' | 'D' | 'h' | 'H' | 's' | 'S' => {
+        let letter = c.to_ascii_lowercase();
+        let mut count = 1;
+        while matches!(chars.get(i), Some(next) if next.to_ascii_lowercase() == letter) {
+          count += 1;
+          i += 1;
+        }
+        tokens.push(Token::Date(letter, count));
+      }
+      _ => tokens.push(Token::Literal(c.to_string())),
+    }
+  }
+  let date = tokens.iter().any(|token| matches!(token, Token::Date(..)));
+  if date {
+    resolve_minutes(&mut tokens);
+    return Ok(Section { color, tokens, date, grouping: false, scale: 0 });
+  }
+  let mut grouping = false;
+  let mut scale = 0;
+  let mut resolved = vec![];
+  for (i, token) in tokens.iter().enumerate() {
+    if *token != Token::Comma {
+      resolved.push(token.clone());
+      continue;
+    }
+    let following = tokens[i + 1..]
+      .iter()
+      .take_while(|token| !matches!(token, Token::Point | Token::Exponent(_)))
+      .any(|token| matches!(token, Token::Digit(_)));
+    let after_digits = matches!(resolved.last(), Some(Token::Digit(_)));
+    if following && after_digits {
+      grouping = true;
+    } else if after_digits || (scale > 0 && matches!(tokens[i - 1], Token::Comma)) {
+      scale += 1;
+    } else {
+      resolved.push(Token::Literal(",".to_string()));
+    }
+  }
+  Ok(Section { color, tokens: resolved, date, grouping, scale })
+}
+
+/// An `m` run after hours or before seconds means minutes.
+fn resolve_minutes(tokens: &mut [Token]) {
+  let parts: Vec<(usize, char)> = tokens
+    .iter()
+    .enumerate()
+    .filter_map(|(i, token)| match token {
+      Token::Date(letter, _) => Some((i, *letter)),
+      _ => None,
+    })
+    .collect();
+  for (n, (i, letter)) in parts.iter().enumerate() {
+    let after_hours = n > 0 && parts[n - 1].1 == 'h';
+    let before_seconds = matches!(parts.get(n + 1), Some((_, 's')));
+    if *letter == 'm' && (after_hours || before_seconds) {
+      if let Token::Date(_, count) = tokens[*i] {
+        tokens[*i] = Token::Date('n', count);
+      }
+    }
+  }
+}
+
+impl Section {
+  fn format_text(&self, text: &str) -> String {
+    self
+      .tokens
+      .iter()
+      .map(|token| match token {
+        Token::Literal(literal) => literal.as_str(),
+        Token::Text => text,
+        _ => "",
+      })
+      .collect()
+  }
+
+  fn format_number(&self, number: f64) -> String {
+    let tokens = &self.tokens;
+    let percents = tokens.iter().filter(|token| **token == Token::Percent).count() as i32;
+    let mut number = number * 100f64.powi(percents) / 1000f64.powi(self.scale);
+    let exponent_at = tokens.iter().position(|token| matches!(token, Token::Exponent(_)));
+    let mantissa_end = exponent_at.unwrap_or(tokens.len());
+    let point_at = tokens[..mantissa_end].iter().position(|token| *token == Token::Point);
+    let int_end = point_at.unwrap_or(mantissa_end);
+    let count_digits = |tokens: &[Token]| tokens.iter().filter(|token| matches!(token, Token::Digit(_))).count();
+    let int_digits = count_digits(&tokens[..int_end]);
+    let frac_digits = point_at.map_or(0, |at| count_digits(&tokens[at + 1..mantissa_end]));
+    let mut exponent = 0;
+    if exponent_at.is_some() && number != 0.0 {
+      let step = int_digits.max(1) as i32;
+      exponent = (number.log10().floor() as i32).div_euclid(step) * step;
+      number /= 10f64.powi(exponent);
+      if round(number, frac_digits) >= 10f64.powi(step) {
+        number /= 10f64.powi(step);
+        exponent += step;
+      }
+    }
+    let text = format!("{:.*}", frac_digits, round(number, frac_digits));
+    let (int_text, frac_text) = match text.find('.') {
+      Some(at) => (&text[..at], &text[at + 1..]),
+      None => (text.as_str(), ""),
+    };
+    let int_text = if int_text == "0" { "" } else { int_text };
+
+    let mut parts: Vec<String> = tokens
+      .iter()
+      .map(|token| match token {
+        Token::Literal(literal) => literal.clone(),
+        Token::Point => ".".to_string(),
+        Token::Percent => "%".to_string(),
+        Token::General => CellValue::Number(number).to_string(),
+        _ => String::new(),
+      })
+      .collect();
+
+    // Integer digits fill their placeholders from the right, any left over
+    // going to the first.
+    let digits: Vec<char> = int_text.chars().collect();
+    let mut next = digits.len();
+    let mut position = 0;
+    let placeholders: Vec<usize> = (0..int_end).filter(|i| matches!(tokens[*i], Token::Digit(_))).collect();
+    for (n, i) in placeholders.iter().enumerate().rev() {
+      let take = if n == 0 { next } else { next.min(1) };
+      let mut chunk: Vec<char> = digits[next - take..next].to_vec();
+      next -= take;
+      if chunk.is_empty() {
+        match tokens[*i] {
+          Token::Digit('0') => chunk.push('0'),
+          Token::Digit('?') => chunk.push(' '),
+          _ => (),
+        }
+      }
+      let mut part = String::new();
+      for (k, c) in chunk.iter().enumerate() {
+        let place = position + chunk.len() - 1 - k;
+        part.push(*c);
+        if self.grouping && place > 0 && place % 3 == 0 && c.is_ascii_digit() {
+          part.push(',');
+        }
+      }
+      position += chunk.len();
+      parts[*i] = part;
+    }
+
+    // Fraction digits fill from the left; trailing zeros are dropped for
+    // `#` and padded for `?`.
+    if let Some(at) = point_at {
+      let placeholders: Vec<usize> = (at + 1..mantissa_end).filter(|i| matches!(tokens[*i], Token::Digit(_))).collect();
+      let mut trimming = true;
+      for (n, i) in placeholders.iter().enumerate().rev() {
+        let c = frac_text.chars().nth(n).unwrap_or('0');
+        parts[*i] = match tokens[*i] {
+          Token::Digit('#') if trimming && c == '0' => String::new(),
+          Token::Digit('?') if trimming && c == '0' => " ".to_string(),
+          _ => {
+            trimming = false;
+            c.to_string()
+          }
+        };
+      }
+    }
+
+    if let Some(at) = exponent_at {
+      let width = count_digits(&tokens[at + 1..]).max(1);
+      let sign = match (exponent < 0, tokens[at] == Token::Exponent(true)) {
+        (true, _) => "-",
+        (false, true) => "+",
+        (false, false) => "",
+      };
+      parts[at] = format!("E{}{:0width$}", sign, exponent.abs(), width = width);
+    }
+    parts.concat()
+  }
+
+  fn format_date(&self, serial: f64) -> String {
+    let (year, month, day) = serial_to_ymd(serial);
+    let seconds = ((serial - serial.floor()) * 86_400.0).round().min(86_399.0) as u32;
+    let twelve_hour = self.tokens.iter().any(|token| matches!(token, Token::AmPm { .. }));
+    let hour = seconds / 3600;
+    let weekday = (serial.floor() as i64 + 6).rem_euclid(7) as usize;
+    self
+      .tokens
+      .iter()
+      .map(|token| match token {
+        Token::Literal(literal) => literal.clone(),
+        Token::Point => ".".to_string(),
+        Token::Comma => ",".to_string(),
+        Token::Date('y', count) if *count <= 2 => format!("{:02}", year.rem_euclid(100)),
+        Token::Date('y', _) => format!("{:04}", year),
+        Token::Date('m', 1) => month.to_string(),
+        Token::Date('m', 2) => format!("{:02}", month),
+        Token::Date('m', 3) => MONTHS[month as usize - 1][..3].to_string(),
+        Token::Date('m', 5) => MONTHS[month as usize - 1][..1].to_string(),
+        Token::Date('m', _) => MONTHS[month as usize - 1].to_string(),
+        Token::Date('d', 1) => day.to_string(),
+        Token::Date('d', 2) => format!("{:02}", day),
+        Token::Date('d', 3) => DAYS[weekday][..3].to_string(),
+        Token::Date('d', _) => DAYS[weekday].to_string(),
+        Token::Date('h', count) => {
+          let hour = if twelve_hour { (hour + 11) % 12 + 1 } else { hour };
+          pad(hour, *count)
+        }
+        Token::Date('n', count) => pad(seconds % 3600 / 60, *count),
+        Token::Date('s', count) => pad(seconds % 60, *count),
+        Token::AmPm { short, lower } => {
+          let text = match (hour < 12, short) {
+            (true, false) => "AM",
+            (false, false) => "PM",
+            (true, true) => "A",
+            (false, true) => "P",
+          };
+          if *lower {
+            text.to_lowercase()
+          } else {
+            text.to_string()
+          }
+        }
+        _ => String::new(),
+      })
+      .collect()
+  }
+}
+
+fn pad(value: u32, count: usize) -> String {
+  if count >= 2 {
+    format!("{:02}", value)
+  } else {
+    value.to_string()
+  }
+}
+
+/// Round half away from zero, as spreadsheets do.
+fn round(number: f64, digits: usize) -> f64 {
+  let scale = 10f64.powi(digits as i32);
+  (number * scale).round() / scale
+}
+
+#[cfg(test)]
+mod tests {
+
+  use crate::format::NumberFormat;
+  use crate::value::{ymd_to_serial, CellValue};
+  #[test]
+  fn formats_numbers() {
+    let format = |code: &str, number: f64| NumberFormat::parse(code).unwrap().format(&CellValue::Number(number));
+    assert_eq!(format("#,##0.00", 1234567.891).0, "1,234,567.89");
+    assert_eq!(format("#,##0.00", -0.5).0, "-0.50");
+    assert_eq!(format("0%", 0.125).0, "13%");
+    assert_eq!(format("0.0%", 0.5).0, "50.0%");
+    assert_eq!(format("$#,##0;[Red]($#,##0)", 1500.0), ("$1,500".to_string(), None));
+    assert_eq!(format("$#,##0;[Red]($#,##0)", -1500.0), ("($1,500)".to_string(), Some("red")));
+    assert_eq!(format("0.00;-0.00;\"zero\"", 0.0).0, "zero");
+    assert_eq!(format("0.00E+00", 12345.0).0, "1.23E+04");
+    assert_eq!(format("0.00E+00", 0.00012).0, "1.20E-04");
+    assert_eq!(format("##0.0E+0", 12345.0).0, "12.3E+3");
+    assert_eq!(format("#.##", 0.5).0, ".5");
+    assert_eq!(format("000-0000", 5551234.0).0, "555-1234");
+    assert_eq!(format("#,##0,\"K\"", 12345.0).0, "12K");
+    assert_eq!(format("0", 2.5).0, "3");
+    assert_eq!(format("General", 0.25).0, "0.25");
+    assert_eq!(format("@", 3.0).0, "3");
+    assert!(NumberFormat::parse("0.00\"").is_err());
+    assert!(NumberFormat::parse("_\"x\"0").is_err());
+    assert_eq!(format("_\"x\"0\"", 7.0).0, " x0");
+    assert_eq!(format("0_);(0)", 7.0).0, "7 ");
+    assert!(NumberFormat::parse("0[Red").is_err());
+  }
+
+  #[test]
+  fn formats_dates_and_text() {
+    let serial = ymd_to_serial(2020, 10, 18) + 0.5 + 5.0 / 1440.0;
+    let format = |code: &str, value: CellValue| NumberFormat::parse(code).unwrap().format(&value).0;
+    assert_eq!(format("yyyy-mm-dd hh:mm", CellValue::DateTime(serial)), "2020-10-18 12:05");
+    assert_eq!(format("d mmm yy", CellValue::DateTime(serial)), "18 Oct 20");
+    assert_eq!(format("dddd, mmmm d", CellValue::Number(serial)), "Sunday, October 18");
+    assert_eq!(format("h:mm AM/PM", CellValue::DateTime(serial)), "12:05 PM");
+    assert_eq!(format("m/d/yyyy", CellValue::DateTime(serial)), "10/18/2020");
+    assert_eq!(format("mmm yyyy", CellValue::Number(1e300)), "####");
+    assert_eq!(format("yyyy-mm-dd", CellValue::Number(-3.0)), "####");
+    assert_eq!(format("yyyy-mm-dd;0", CellValue::Number(-3.0)), "3");
+    assert_eq!(format("\"Name: \"@", CellValue::Text("Ada".to_string())), "Name: Ada");
+    assert_eq!(format("0.00", CellValue::Text("Ada".to_string())), "Ada");
+    assert_eq!(format("0;0;0;[Blue]@", CellValue::Boolean(true)), "TRUE");
+  }
+}
@@ -0,0 +1,896 @@
+use super::functions::FunctionRegistry;
+use super::model::DataModel;
+use super::reference::{CellRef, RangeKind, RangeRef};
+use super::value::{CellError, CellValue};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+  Number(f64),
+  Text(String),
+  Error(CellError),
+  Ident(String),
+  /// Sheet name prefixing a reference, without the `!`.
+  Sheet(String),
+  Operator(char),
+  Compare(&'static str),
+  Colon,
+  Comma,
+  LParen,
+  RParen,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+  Value(CellValue),
+  Reference(RangeRef),
+  /// A reference to another sheet, by name.
+  External(String, RangeRef),
+  Negate(Box<Expr>),
+  Percent(Box<Expr>),
+  Binary(char, Box<Expr>, Box<Expr>),
+  Compare(&'static str, Box<Expr>, Box<Expr>),
+  Function(String, Vec<Expr>),
+}
+
+/// Argument handed to a function: either a single value or a block of values
+/// read from a range, stored row by row.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+  Value(CellValue),
+  Range(Vec<Vec<CellValue>>),
+}
+
+impl Operand {
+  /// All values of the operand, flattened row by row.
+  pub fn values(&self) -> Vec<&CellValue> {
+    match self {
+      Operand::Value(value) => vec![value],
+      Operand::Range(rows) => rows.iter().flatten().collect(),
+    }
+  }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, CellError> {
+  let chars: Vec<char> = text.chars().collect();
+  let mut tokens = vec![];
+  let mut pos = 0;
+  while pos < chars.len() {
+    let ch = chars[pos];
+    match ch {
+      ' ' | '\t' | '\n' | '\r' => pos += 1,
+      '0'..='9' | '.' => {
+        let start = pos;
+        while pos < chars.len() && (chars[pos].is_ascii_digit() || chars[pos] == '.') {
+          pos += 1;
+        }
+        if pos < chars.len() && (chars[pos] == 'e' || chars[pos] == 'E') {
+          let mark = pos;
+          pos += 1;
+          if pos < chars.len() && (chars[pos] == '+' || chars[pos] == '-') {
+            pos += 1;
+          }
+          if pos < chars.len() && chars[pos].is_ascii_digit() {
+            while pos < chars.len() && chars[pos].is_ascii_digit() {
+              pos += 1;
+            }
+          } else {
+            pos = mark;
+          }
+        }
+        let literal: String = chars[start..pos].iter().collect();
+        match literal.parse::<f64>() {
+          Ok(number) => tokens.push(Token::Number(number)),
+          Err(_) => return Err(CellError::Value),
+        }
+      }
+      '"' => {
+        let mut literal = String::new();
+        pos += 1;
+        loop {
+          if pos >= chars.len() {
+            return Err(CellError::Value);
+          }
+          if chars[pos] == '"' {
+            if pos + 1 < chars.len() && chars[pos + 1] == '"' {
+              literal.push('"');
+              pos += 2;
+              continue;
+            }
+            pos += 1;
+            break;
+          }
+          literal.push(chars[pos]);
+          pos += 1;
+        }
+        tokens.push(Token::Text(literal));
+      }
+      '#' => {
+        let start = pos;
+        pos += 1;
+        while pos < chars.len() && (chars[pos].is_ascii_alphanumeric() || chars[pos] == '/') {
+          pos += 1;
+        }
+        if pos < chars.len() && (chars[pos] == '!' || chars[pos] == '?') {
+          pos += 1;
+        }
+        let literal: String = chars[start..pos].iter().collect();
+        match CellError::parse(&literal) {
+          Some(error) => tokens.push(Token::Error(error)),
+          None => return Err(CellError::Name),
+        }
+      }
+      '+' | '-' | '*' | '/' | '^' | '&' | '%' => {
+        tokens.push(Token::Operator(ch));
+        pos += 1;
+      }
+      '=' => {
+        tokens.push(Token::Compare("="));
+        pos += 1;
+      }
+      '<' | '>' => {
+        let next = chars.get(pos + 1).cloned();
+        let op = match (ch, next) {
+          ('<', Some('=')) => "<=",
+          ('<', Some('>')) => "<>",
+          ('>', Some('=')) => ">=",
+          ('<', _) => "<",
+          _ => ">",
+        };
+        pos += op.len();
+        tokens.push(Token::Compare(op));
+      }
+      ':' => {
+        tokens.push(Token::Colon);
+        pos += 1;
+      }
+      ',' | ';' => {
+        tokens.push(Token::Comma);
+        pos += 1;
+      }
+      '(' => {
+        tokens.push(Token::LParen);
+        pos += 1;
+      }
+      ')' => {
+        tokens.push(Token::RParen);
+        pos += 1;
+      }
+      _ if ch.is_alphabetic() || ch == '$' || ch == '_' => {
+        let start = pos;
+        let is_name_char = |c: char| c.is_alphanumeric() || c == '$' || c == '_' || c == '.';
+        while pos < chars.len() && is_name_char(chars[pos]) {
+          pos += 1;
+        }
+        let ident: String = chars[start..pos].iter().collect();
+        if pos < chars.len() && chars[pos] == '!' {
+          pos += 1;
+          tokens.push(Token::Sheet(ident));
+        } else {
+          tokens.push(Token::Ident(ident));
+        }
+      }
+      '\'' => {
+        let (name, end) = read_quoted_name(&chars, pos).ok_or(CellError::Name)?;
+        if chars.get(end) != Some(&'!') {
+          return Err(CellError::Name);
+        }
+        pos = end + 1;
+        tokens.push(Token::Sheet(name));
+      }
+      _ => return Err(CellError::Name),
+    }
+  }
+  Ok(tokens)
+}
+
+/// Read a sheet name quoted with `'`, quotes inside doubled, starting at the
+/// opening quote. Returns the name and the position after the closing quote.
+fn read_quoted_name(chars: &[char], start: usize) -> Option<(String, usize)> {
+  let mut name = String::new();
+  let mut pos = start + 1;
+  loop {
+    match chars.get(pos)? {
+      '\'' if chars.get(pos + 1) == Some(&'\'') => {
+        name.push('\'');
+        pos += 2;
+      }
+      '\'' => return Some((name, pos + 1)),
+      c => {
+        name.push(*c);
+        pos += 1;
+      }
+    }
+  }
+}
+
+struct Parser {
+  tokens: Vec<Token>,
+  pos: usize,
+}
+
+impl Parser {
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.pos)
+  }
+
+  fn next(&mut self) -> Option<Token> {
+    let token = self.tokens.get(self.pos).cloned();
+    self.pos += 1;
+    token
+  }
+
+  fn expect(&mut self, token: Token) -> Result<(), CellError> {
+    match self.next() {
+      Some(ref next) if *next == token => Ok(()),
+      _ => Err(CellError::Value),
+    }
+  }
+
+  fn parse_comparison(&mut self) -> Result<Expr, CellError> {
+    let mut lhs = self.parse_concat()?;
+    while let Some(Token::Compare(op)) = self.peek().cloned() {
+      self.pos += 1;
+      let rhs = self.parse_concat()?;
+      lhs = Expr::Compare(op, Box::new(lhs), Box::new(rhs));
+    }
+    Ok(lhs)
+  }
+
+  fn parse_concat(&mut self) -> Result<Expr, CellError> {
+    let mut lhs = self.parse_additive()?;
+    while let Some(Token::Operator('&')) = self.peek() {
+      self.pos += 1;
+      let rhs = self.parse_additive()?;
+      lhs = Expr::Binary('&', Box::new(lhs), Box::new(rhs));
+    }
+    Ok(lhs)
+  }
+
+  fn parse_additive(&mut self) -> Result<Expr, CellError> {
+    let mut lhs = self.parse_multiplicative()?;
+    while let Some(Token::Operator(op)) = self.peek().cloned() {
+      if op != '+' && op != '-' {
+        break;
+      }
+      self.pos += 1;
+      let rhs = self.parse_multiplicative()?;
+      lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+    }
+    Ok(lhs)
+  }
+
+  fn parse_multiplicative(&mut self) -> Result<Expr, CellError> {
+    let mut lhs = self.parse_power()?;
+    while let Some(Token::Operator(op)) = self.peek().cloned() {
+      if op != '*' && op != '/' {
+        break;
+      }
+      self.pos += 1;
+      let rhs = self.parse_power()?;
+      lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+    }
+    Ok(lhs)
+  }
+
+  fn parse_power(&mut self) -> Result<Expr, CellError> {
+    let mut lhs = self.parse_unary()?;
+    while let Some(Token::Operator('^')) = self.peek() {
+      self.pos += 1;
+      let rhs = self.parse_unary()?;
+      lhs = Expr::Binary('^', Box::new(lhs), Box::new(rhs));
+    }
+    Ok(lhs)
+  }
+
+  fn parse_unary(&mut self) -> Result<Expr, CellError> {
+    match self.peek() {
+      Some(Token::Operator('-')) => {
+        self.pos += 1;
+        Ok(Expr::Negate(Box::new(self.parse_unary()?)))
+      }
+      Some(Token::Operator('+')) => {
+        self.pos += 1;
+        self.parse_unary()
+      }
+      _ => self.parse_postfix(),
+    }
+  }
+
+  fn parse_postfix(&mut self) -> Result<Expr, CellError> {
+    let mut expr = self.parse_primary()?;
+    while let Some(Token::Operator('%')) = self.peek() {
+      self.pos += 1;
+      expr = Expr::Percent(Box::new(expr));
+    }
+    Ok(expr)
+  }
+
+  fn parse_primary(&mut self) -> Result<Expr, CellError> {
+    match self.next() {
+      Some(Token::Number(number)) => match self.peek() {
+        Some(Token::Colon) => self.parse_reference(number.to_string()),
+        _ => Ok(Expr::Value(CellValue::Number(number))),
+      },
+      Some(Token::Text(text)) => Ok(Expr::Value(CellValue::Text(text))),
+      Some(Token::Error(error)) => Ok(Expr::Value(CellValue::Error(error))),
+      Some(Token::LParen) => {
+        let expr = self.parse_comparison()?;
+        self.expect(Token::RParen)?;
+        Ok(expr)
+      }
+      Some(Token::Ident(ident)) => {
+        if let Some(Token::LParen) = self.peek() {
+          self.pos += 1;
+          return self.parse_function(ident.to_uppercase());
+        }
+        match ident.to_uppercase().as_str() {
+          "TRUE" => return Ok(Expr::Value(CellValue::Boolean(true))),
+          "FALSE" => return Ok(Expr::Value(CellValue::Boolean(false))),
+          _ => (),
+        }
+        self.parse_reference(ident)
+      }
+      Some(Token::Sheet(sheet)) => {
+        let reference = match self.next() {
+          Some(Token::Ident(ident)) => self.parse_reference(ident)?,
+          Some(Token::Number(number)) => self.parse_reference(number.to_string())?,
+          _ => return Err(CellError::Ref),
+        };
+        match reference {
+          Expr::Reference(range) => Ok(Expr::External(sheet, range)),
+          _ => Err(CellError::Ref),
+        }
+      }
+      _ => Err(CellError::Value),
+    }
+  }
+
+  /// Parse a reference whose first part has been consumed, e.g. `A1`, `B2:C3`,
+  /// `C:C` or `3:3`.
+  fn parse_reference(&mut self, lhs: String) -> Result<Expr, CellError> {
+    let text = match self.peek() {
+      Some(Token::Colon) => {
+        self.pos += 1;
+        let rhs = match self.next() {
+          Some(Token::Ident(ident)) => ident,
+          Some(Token::Number(number)) => number.to_string(),
+          _ => return Err(CellError::Ref),
+        };
+        [lhs, rhs].join(":")
+      }
+      _ => lhs,
+    };
+    RangeRef::parse(&text).map(Expr::Reference).ok_or(CellError::Name)
+  }
+
+  fn parse_function(&mut self, name: String) -> Result<Expr, CellError> {
+    let mut args = vec![];
+    if let Some(Token::RParen) = self.peek() {
+      self.pos += 1;
+      return Ok(Expr::Function(name, args));
+    }
+    loop {
+      args.push(self.parse_comparison()?);
+      match self.next() {
+        Some(Token::Comma) => continue,
+        Some(Token::RParen) => break,
+        _ => return Err(CellError::Value),
+      }
+    }
+    Ok(Expr::Function(name, args))
+  }
+}
+
+/// Parse a formula. The leading `=` is optional.
+pub fn parse(formula: &str) -> Result<Expr, CellError> {
+  let source = formula.trim_start();
+  let source = source.strip_prefix('=').unwrap_or(source);
+  let mut parser = Parser {
+    tokens: tokenize(source)?,
+    pos: 0,
+  };
+  let expr = parser.parse_comparison()?;
+  if parser.pos < parser.tokens.len() {
+    return Err(CellError::Value);
+  }
+  Ok(expr)
+}
+
+/// A stretch of formula source: a reference, possibly prefixed by a sheet
+/// name, or anything else.
+#[derive(Debug, PartialEq)]
+enum Piece {
+  Text(String),
+  /// The sheet name as written, quotes and `!` included, and the name itself.
+  Sheet(String, String),
+  /// A cell, range, column or row reference, with the sheet named before it.
+  Reference(String, Option<String>),
+}
+
+/// Split formula source into references and the text between them. Words that
+/// are not references, like numbers, names or `TRUE`, are left to the caller
+/// to tell apart.
+fn split_references(source: &str) -> Vec<Piece> {
+  let chars: Vec<char> = source.chars().collect();
+  let is_word = |c: char| c.is_alphanumeric() || c == '$' || c == '_' || c == '.';
+  let read_word = |start: usize| {
+    let mut end = start;
+    while end < chars.len() && is_word(chars[end]) {
+      end += 1;
+    }
+    end
+  };
+  let mut pieces = vec![];
+  let mut text = String::new();
+  let mut sheet: Option<String> = None;
+  let mut pos = 0;
+  while pos < chars.len() {
+    let ch = chars[pos];
+    if ch == '"' {
+      let start = pos;
+      pos += 1;
+      while pos < chars.len() && chars[pos] != '"' {
+        pos += 1;
+      }
+      pos = (pos + 1).min(chars.len());
+      text.extend(&chars[start..pos]);
+    } else if ch == '#' {
+      // Error literals such as #DIV/0! hold characters found in references.
+      let start = pos;
+      pos += 1;
+      while pos < chars.len() && (chars[pos].is_ascii_alphanumeric() || "/!?".contains(chars[pos])) {
+        pos += 1;
+      }
+      text.extend(&chars[start..pos]);
+    } else if ch == '\'' {
+      match read_quoted_name(&chars, pos) {
+        Some((name, end)) if chars.get(end) == Some(&'!') => {
+          pieces.push(Piece::Text(std::mem::take(&mut text)));
+          pieces.push(Piece::Sheet(chars[pos..=end].iter().collect(), name.clone()));
+          sheet = Some(name);
+          pos = end + 1;
+          continue;
+        }
+        _ => {
+          text.extend(&chars[pos..]);
+          pos = chars.len();
+        }
+      }
+    } else if is_word(ch) {
+      let mut end = read_word(pos);
+      let word: String = chars[pos..end].iter().collect();
+      if chars.get(end) == Some(&'!') {
+        pieces.push(Piece::Text(std::mem::take(&mut text)));
+        pieces.push(Piece::Sheet([word.as_str(), "!"].concat(), word));
+        sheet = Some(chars[pos..end].iter().collect());
+        pos = end + 1;
+        continue;
+      }
+      if chars.get(end) == Some(&':') && end + 1 < chars.len() && is_word(chars[end + 1]) {
+        end = read_word(end + 1);
+      }
+      let is_call = chars[end..].iter().find(|c| !c.is_whitespace()) == Some(&'(');
+      let word: String = chars[pos..end].iter().collect();
+      if is_call || ch.is_ascii_digit() && !word.contains(':') {
+        text.push_str(&word);
+      } else {
+        pieces.push(Piece::Text(std::mem::take(&mut text)));
+        pieces.push(Piece::Reference(word, sheet.take()));
+      }
+      pos = end;
+      continue;
+    } else {
+      text.push(ch);
+      pos += 1;
+    }
+    sheet = None;
+  }
+  pieces.push(Piece::Text(text));
+  pieces.retain(|piece| piece != &Piece::Text(String::new()));
+  pieces
+}
+
+/// Rewrite the references in formula source, keeping the rest as written.
+/// `f` gets each reference with the sheet it names, if any. References mapped
+/// to `None` become `#REF!`, dropping the sheet name.
+pub fn rewrite_references<F>(source: &str, mut f: F) -> String
+where
+  F: FnMut(Option<&str>, RangeRef) -> Option<RangeRef>,
+{
+  let mut result = String::new();
+  let mut sheet_start = None;
+  for piece in split_references(source) {
+    match piece {
+      Piece::Text(text) => result.push_str(&text),
+      Piece::Sheet(text, _) => {
+        sheet_start = Some(result.len());
+        result.push_str(&text);
+        continue;
+      }
+      Piece::Reference(text, sheet) => match RangeRef::parse(&text) {
+        Some(range) => match f(sheet.as_deref(), range) {
+          Some(range) => result.push_str(&range.to_a1()),
+          None => {
+            if let (Some(start), Some(_)) = (sheet_start, sheet) {
+              result.truncate(start);
+            }
+            result.push_str(CellError::Ref.as_str());
+          }
+        },
+        None => result.push_str(&text),
+      },
+    }
+    sheet_start = None;
+  }
+  result
+}
+
+/// Source of a formula copied from `from` to `to`, with its relative
+/// references moved by the same offset.
+pub fn translate(source: &str, from: (u32, u32), to: (u32, u32)) -> String {
+  let (delta_col, delta_row) = (to.0 as i64 - from.0 as i64, to.1 as i64 - from.1 as i64);
+  let shift = |idx: u32, absolute: bool, delta: i64| {
+    let idx = if absolute { idx as i64 } else { idx as i64 + delta };
+    if idx < 1 {
+      None
+    } else {
+      Some(idx as u32)
+    }
+  };
+  rewrite_references(source, |_, range| {
+    let move_cell = |cell: CellRef| -> Option<CellRef> {
+      Some(CellRef {
+        col: match range.kind {
+          RangeKind::Rows => cell.col,
+          _ => shift(cell.col, cell.col_absolute, delta_col)?,
+        },
+        row: match range.kind {
+          RangeKind::Columns => cell.row,
+          _ => shift(cell.row, cell.row_absolute, delta_row)?,
+        },
+        ..cell
+      })
+    };
+    Some(RangeRef {
+      start: move_cell(range.start)?,
+      end: move_cell(range.end)?,
+      kind: range.kind,
+    })
+  })
+}
+
+/// Source of a formula with references to sheet `from` pointing at sheet `to`
+/// instead. Sheet names match case-insensitively.
+pub fn rename_sheet(source: &str, from: &str, to: &str) -> String {
+  split_references(source)
+    .into_iter()
+    .map(|piece| match piece {
+      Piece::Sheet(_, name) if name.to_uppercase() == from.to_uppercase() => sheet_prefix(to),
+      Piece::Text(text) | Piece::Sheet(text, _) | Piece::Reference(text, _) => text,
+    })
+    .collect()
+}
+
+/// A sheet name as written before a reference, quoted when it would not read
+/// as a name otherwise, followed by `!`.
+pub fn sheet_prefix(name: &str) -> String {
+  let plain = name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '.')
+    && !name.starts_with(|c: char| c.is_ascii_digit())
+    // Names such as `AB12` read as cells, within Excel's XFD1048576 limit.
+    && !matches!(RangeRef::parse(name), Some(range) if range.start.col <= 16_384 && range.start.row <= 1_048_576)
+    && !name.is_empty();
+  if plain {
+    [name, "!"].concat()
+  } else {
+    ["'", &name.replace('\'', "''"), "'!"].concat()
+  }
+}
+
+impl Expr {
+  /// Cells and ranges the expression reads from.
+  pub fn references(&self) -> Vec<RangeRef> {
+    let mut refs = vec![];
+    self.collect_references(&mut refs);
+    refs
+  }
+
+  /// Ranges the expression reads from other sheets, with the sheet names.
+  pub fn external_references(&self) -> Vec<(String, RangeRef)> {
+    let mut refs = vec![];
+    self.visit(&mut |expr| {
+      if let Expr::External(sheet, reference) = expr {
+        refs.push((sheet.clone(), *reference));
+      }
+    });
+    refs
+  }
+
+  fn collect_references(&self, refs: &mut Vec<RangeRef>) {
+    self.visit(&mut |expr| {
+      if let Expr::Reference(reference) = expr {
+        refs.push(*reference);
+      }
+    });
+  }
+
+  /// Whether the expression calls a volatile function, see
+  /// `FunctionRegistry::is_volatile`.
+  pub fn is_volatile(&self, functions: &FunctionRegistry) -> bool {
+    let mut volatile = false;
+    self.visit(&mut |expr| {
+      if let Expr::Function(name, _) = expr {
+        volatile |= functions.is_volatile(name);
+      }
+    });
+    volatile
+  }
+
+  /// Call `f` on the expression and everything within it.
+  fn visit<F: FnMut(&Expr)>(&self, f: &mut F) {
+    f(self);
+    match self {
+      Expr::Value(_) | Expr::Reference(_) | Expr::External(_, _) => (),
+      Expr::Negate(expr) | Expr::Percent(expr) => expr.visit(f),
+      Expr::Binary(_, lhs, rhs) | Expr::Compare(_, lhs, rhs) => {
+        lhs.visit(f);
+        rhs.visit(f);
+      }
+      Expr::Function(_, args) => {
+        for arg in args {
+          arg.visit(f);
+        }
+      }
+    }
+  }
+}
+
+/// Coerce a value to a number following spreadsheet rules.
+pub fn to_number(value: &CellValue) -> Result<f64, CellError> {
+  match value {
+    CellValue::Empty => Ok(0.0),
+    CellValue::Number(number) | CellValue::DateTime(number) => Ok(*number),
+    CellValue::Boolean(boolean) => Ok(if *boolean { 1.0 } else { 0.0 }),
+    CellValue::Text(text) => text.trim().parse::<f64>().map_err(|_| CellError::Value),
+    CellValue::Error(error) => Err(*error),
+  }
+}
+
+/// Coerce a value to text following spreadsheet rules.
+pub fn to_text(value: &CellValue) -> Result<String, CellError> {
+  match value {
+    CellValue::Error(error) => Err(*error),
+    CellValue::DateTime(serial) => Ok(serial.to_string()),
+    _ => Ok(value.to_string()),
+  }
+}
+
+/// Coerce a value to a boolean following spreadsheet rules.
+pub fn to_bool(value: &CellValue) -> Result<bool, CellError> {
+  match value {
+    CellValue::Empty => Ok(false),
+    CellValue::Boolean(boolean) => Ok(*boolean),
+    CellValue::Number(number) | CellValue::DateTime(number) => Ok(*number != 0.0),
+    CellValue::Text(text) => match text.to_uppercase().as_str() {
+      "TRUE" => Ok(true),
+      "FALSE" => Ok(false),
+      _ => Err(CellError::Value),
+    },
+    CellValue::Error(error) => Err(*error),
+  }
+}
+
+/// Order two values the way comparison operators do: numbers sort before text,
+/// text before booleans, and text compares case-insensitively.
+pub fn compare_values(lhs: &CellValue, rhs: &CellValue) -> std::cmp::Ordering {
+  use std::cmp::Ordering;
+  fn rank(value: &CellValue) -> u8 {
+    match value {
+      CellValue::Text(_) => 1,
+      CellValue::Boolean(_) => 2,
+      _ => 0,
+    }
+  }
+  let (lhs, rhs) = match (lhs, rhs) {
+    (CellValue::Empty, CellValue::Text(_)) => (CellValue::Text(String::new()), rhs.clone()),
+    (CellValue::Text(_), CellValue::Empty) => (lhs.clone(), CellValue::Text(String::new())),
+    (CellValue::Empty, CellValue::Boolean(_)) => (CellValue::Boolean(false), rhs.clone()),
+    (CellValue::Boolean(_), CellValue::Empty) => (lhs.clone(), CellValue::Boolean(false)),
+    _ => (lhs.clone(), rhs.clone()),
+  };
+  match (&lhs, &rhs) {
+    (CellValue::Text(a), CellValue::Text(b)) => a.to_lowercase().cmp(&b.to_lowercase()),
+    (CellValue::Boolean(a), CellValue::Boolean(b)) => a.cmp(b),
+    _ if rank(&lhs) != rank(&rhs) => rank(&lhs).cmp(&rank(&rhs)),
+    _ => {
+      let a = to_number(&lhs).unwrap_or(0.0);
+      let b = to_number(&rhs).unwrap_or(0.0);
+      a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+    }
+  }
+}
+
+fn number_result(number: f64) -> CellValue {
+  if number.is_finite() {
+    CellValue::Number(number)
+  } else {
+    CellValue::Error(CellError::Num)
+  }
+}
+
+/// Values of a range row by row. Whole-column and whole-row ranges stop at the
+/// last row or column holding data.
+fn read_range(model: &DataModel, range: RangeRef) -> Vec<Vec<CellValue>> {
+  let (max_col, max_row) = model.get_data_extent();
+  let (mut end_col, mut end_row) = (range.end.col, range.end.row);
+  match range.kind {
+    RangeKind::Columns => end_row = max_row.max(range.start.row),
+    RangeKind::Rows => end_col = max_col.max(range.start.col),
+    RangeKind::Cells => (),
+  }
+  (range.start.row..=end_row)
+    .map(|row| (range.start.col..=end_col).map(|col| model.get_value(col, row).clone()).collect())
+    .collect()
+}
+
+/// Evaluate an expression as a function argument, keeping ranges intact.
+pub fn evaluate_operand(expr: &Expr, model: &DataModel) -> Operand {
+  match expr {
+    Expr::Reference(range) if !range.is_cell() => Operand::Range(read_range(model, *range)),
+    Expr::External(sheet, range) if !range.is_cell() => match model.get_external_range(sheet, *range) {
+      Ok(rows) => Operand::Range(rows),
+      Err(error) => Operand::Value(CellValue::Error(error)),
+    },
+    _ => Operand::Value(evaluate_scalar(expr, model)),
+  }
+}
+
+fn evaluate_scalar(expr: &Expr, model: &DataModel) -> CellValue {
+  match expr {
+    Expr::Value(value) => value.clone(),
+    Expr::Reference(range) if range.is_cell() => model.get_value(range.start.col, range.start.row).clone(),
+    Expr::Reference(_) => CellValue::Error(CellError::Value),
+    Expr::External(sheet, range) if range.is_cell() => {
+      model.get_external_value(sheet, range.start.col, range.start.row)
+    }
+    Expr::External(_, _) => CellValue::Error(CellError::Value),
+    Expr::Negate(expr) => match to_number(&evaluate_scalar(expr, model)) {
+      Ok(number) => CellValue::Number(-number),
+      Err(error) => CellValue::Error(error),
+    },
+    Expr::Percent(expr) => match to_number(&evaluate_scalar(expr, model)) {
+      Ok(number) => CellValue::Number(number / 100.0),
+      Err(error) => CellValue::Error(error),
+    },
+    Expr::Binary(op, lhs, rhs) => {
+      let lhs = evaluate_scalar(lhs, model);
+      let rhs = evaluate_scalar(rhs, model);
+      if *op == '&' {
+        return match (to_text(&lhs), to_text(&rhs)) {
+          (Ok(a), Ok(b)) => CellValue::Text([a, b].concat()),
+          (Err(error), _) | (_, Err(error)) => CellValue::Error(error),
+        };
+      }
+      let (a, b) = match (to_number(&lhs), to_number(&rhs)) {
+        (Ok(a), Ok(b)) => (a, b),
+        (Err(error), _) | (_, Err(error)) => return CellValue::Error(error),
+      };
+      match op {
+        '+' => number_result(a + b),
+        '-' => number_result(a - b),
+        '*' => number_result(a * b),
+        '/' if b == 0.0 => CellValue::Error(CellError::Div0),
+        '/' => number_result(a / b),
+        '^' => number_result(a.powf(b)),
+        _ => CellValue::Error(CellError::Value),
+      }
+    }
+    Expr::Compare(op, lhs, rhs) => {
+      let lhs = evaluate_scalar(lhs, model);
+      let rhs = evaluate_scalar(rhs, model);
+      if let CellValue::Error(error) = lhs {
+        return CellValue::Error(error);
+      }
+      if let CellValue::Error(error) = rhs {
+        return CellValue::Error(error);
+      }
+      let ordering = compare_values(&lhs, &rhs);
+      let result = match *op {
+        "=" => ordering == std::cmp::Ordering::Equal,
+        "<>" => ordering != std::cmp::Ordering::Equal,
+        "<" => ordering == std::cmp::Ordering::Less,
+        "<=" => ordering != std::cmp::Ordering::Greater,
+        ">" => ordering == std::cmp::Ordering::Greater,
+        _ => ordering != std::cmp::Ordering::Less,
+      };
+      CellValue::Boolean(result)
+    }
+    Expr::Function(name, args) => {
+      let operands: Vec<Operand> = args.iter().map(|arg| evaluate_operand(arg, model)).collect();
+      model.get_functions().call(name, &operands)
+    }
+  }
+}
+
+/// Evaluate a parsed formula against the model. A formula pointing at an empty
+/// cell yields zero, as in other spreadsheets.
+pub fn evaluate(expr: &Expr, model: &DataModel) -> CellValue {
+  match evaluate_scalar(expr, model) {
+    CellValue::Empty => CellValue::Number(0.0),
+    value => value,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::{evaluate, parse, rename_sheet, rewrite_references, sheet_prefix, translate, Expr};
+  use crate::model::DataModel;
+  use crate::reference::RangeRef;
+  use crate::value::{CellError, CellValue};
+  #[test]
+  fn parse_and_evaluate() {
+    let mut model = DataModel::new();
+    model.set_value(1, 1, CellValue::Number(1.0));
+    model.set_value(2, 1, CellValue::Number(2.0));
+    model.set_value(1, 3, CellValue::Number(4.5));
+    let expr = parse("=SUM(A1:B3)*2").unwrap();
+    assert_eq!(expr.references(), vec![RangeRef::parse("A1:B3").unwrap()]);
+    assert_eq!(evaluate(&expr, &model), CellValue::Number(15.0));
+    assert_eq!(evaluate(&parse("=-2^2+10%").unwrap(), &model), CellValue::Number(4.1));
+    assert_eq!(evaluate(&parse("=A1/(B1-2)").unwrap(), &model), CellValue::Error(CellError::Div0));
+    assert_eq!(evaluate(&parse("=\"a\"\"b\"&$A$1").unwrap(), &model), CellValue::Text("a\"b1".to_string()));
+    assert_eq!(evaluate(&parse("=B1>=A1").unwrap(), &model), CellValue::Boolean(true));
+    assert_eq!(evaluate(&parse("=SUM(A:A)+SUM($1:1)").unwrap(), &model), CellValue::Number(8.5));
+    assert_eq!(parse("=FOO"), Err(CellError::Name));
+    assert_eq!(parse("=1+"), Err(CellError::Value));
+    assert_eq!(parse("1"), Ok(Expr::Value(CellValue::Number(1.0))));
+  }
+
+  #[test]
+  fn recalculate_dependents() {
+    let mut model = DataModel::new();
+    model.set_formula(2, 1, "=A1*2");
+    model.set_formula(3, 1, "=B1+SUM(A1:B1)");
+    assert_eq!(model.get_value(3, 1), &CellValue::Number(0.0));
+    model.set_value(1, 1, CellValue::Number(5.0));
+    assert_eq!(model.get_value(2, 1), &CellValue::Number(10.0));
+    assert_eq!(model.get_value(3, 1), &CellValue::Number(25.0));
+    model.set_formula(1, 1, "=C1");
+    assert_eq!(model.get_value(3, 1), &CellValue::Error(CellError::Ref));
+  }
+
+  #[test]
+  fn translate_references() {
+    assert_eq!(translate("=A1*$B2+SUM(A$1:C3)", (2, 2), (3, 4)), "=B3*$B4+SUM(B$1:D5)");
+    assert_eq!(translate("=\"A1\"&LOG10(A1)&#N/A", (1, 1), (1, 2)), "=\"A1\"&LOG10(A2)&#N/A");
+    assert_eq!(translate("=A1+1E5", (2, 2), (1, 1)), "=#REF!+1E5");
+    assert_eq!(translate("=SUM(B:B,$2:3,Data!A1:B2)", (1, 1), (2, 2)), "=SUM(C:C,$2:4,Data!B2:C3)");
+  }
+
+  #[test]
+  fn sheet_references() {
+    let expr = parse("=SUM(Data!A1:B2)+'Q1 ''24'!C3").unwrap();
+    assert_eq!(
+      expr.external_references(),
+      vec![
+        ("Data".to_string(), RangeRef::parse("A1:B2").unwrap()),
+        ("Q1 '24".to_string(), RangeRef::parse("C3").unwrap()),
+      ]
+    );
+    assert!(expr.references().is_empty());
+    assert_eq!(parse("='Data!A1"), Err(CellError::Name));
+    assert_eq!(
+      rename_sheet("=data!A1+'Q1 ''24'!A1&\"Data!A1\"", "Data", "My Data"),
+      "='My Data'!A1+'Q1 ''24'!A1&\"Data!A1\""
+    );
+    let deleted = rewrite_references("=Data!A1+'Q1'!B2:C3+B2", |sheet, range| match sheet {
+      Some("Data") => None,
+      _ => Some(range),
+    });
+    assert_eq!(deleted, "=#REF!+'Q1'!B2:C3+B2");
+    assert_eq!(sheet_prefix("Sheet2"), "Sheet2!");
+    assert_eq!(sheet_prefix("A1"), "'A1'!");
+    assert_eq!(sheet_prefix("XFD1"), "'XFD1'!");
+    assert_eq!(sheet_prefix("2020"), "'2020'!");
+  }
+}
@@ -0,0 +1,644 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use super::formula::{compare_values, to_bool, to_number, to_text, Operand};
+use super::value::{is_date_serial, serial_to_ymd, ymd_to_serial, CellError, CellValue};
+
+pub type Function = Rc<dyn Fn(&[Operand]) -> CellValue>;
+
+/// A function registered on creation.
+type Builtin = fn(&[Operand]) -> CellValue;
+
+/// Functions callable from formulas, keyed by upper-case name. Built-ins are
+/// registered on creation; hosts can add or override entries at runtime.
+pub struct FunctionRegistry {
+  items: BTreeMap<String, Entry>,
+}
+
+struct Entry {
+  function: Function,
+  /// Whether the result can change without any argument changing, as with
+  /// `TODAY`, so that formulas calling it are recalculated on every refresh.
+  volatile: bool,
+}
+
+impl FunctionRegistry {
+  pub fn new() -> Self {
+    let mut registry = Self { items: BTreeMap::new() };
+    let builtins: Vec<(&str, Builtin)> = vec![
+      ("SUM", sum),
+      ("PRODUCT", product),
+      ("AVERAGE", average),
+      ("MIN", min),
+      ("MAX", max),
+      ("COUNT", count),
+      ("COUNTA", counta),
+      ("COUNTBLANK", countblank),
+      ("ABS", abs),
+      ("INT", int),
+      ("ROUND", round),
+      ("ROUNDUP", roundup),
+      ("ROUNDDOWN", rounddown),
+      ("MOD", modulo),
+      ("POWER", power),
+      ("SQRT", sqrt),
+      ("IF", if_),
+      ("IFERROR", iferror),
+      ("AND", and),
+      ("OR", or),
+      ("NOT", not),
+      ("ISBLANK", isblank),
+      ("ISNUMBER", isnumber),
+      ("ISTEXT", istext),
+      ("ISERROR", iserror),
+      ("CONCAT", concat),
+      ("CONCATENATE", concat),
+      ("LEFT", left),
+      ("RIGHT", right),
+      ("MID", mid),
+      ("LEN", len),
+      ("UPPER", upper),
+      ("LOWER", lower),
+      ("TRIM", trim),
+      ("VLOOKUP", vlookup),
+      ("HLOOKUP", hlookup),
+      ("INDEX", index),
+      ("MATCH", match_),
+      ("DATE", date),
+      ("YEAR", year),
+      ("MONTH", month),
+      ("DAY", day),
+    ];
+    for (name, function) in builtins {
+      registry.register(name, Rc::new(function), false);
+    }
+    registry.register("TODAY", Rc::new(today), true);
+    registry.register("NOW", Rc::new(now), true);
+    registry
+  }
+
+  pub fn register(&mut self, name: &str, function: Function, volatile: bool) {
+    self.items.insert(name.to_uppercase(), Entry { function, volatile });
+  }
+
+  pub fn is_volatile(&self, name: &str) -> bool {
+    self.items.get(&name.to_uppercase()).map(|entry| entry.volatile).unwrap_or(false)
+  }
+
+  /// Call a function by name. Unknown names evaluate to `#NAME?`.
+  pub fn call(&self, name: &str, args: &[Operand]) -> CellValue {
+    match self.items.get(&name.to_uppercase()) {
+      Some(entry) => (entry.function)(args),
+      None => CellValue::Error(CellError::Name),
+    }
+  }
+}
+
+fn result(value: Result<CellValue, CellError>) -> CellValue {
+  match value {
+    Ok(CellValue::Number(number)) if !number.is_finite() => CellValue::Error(CellError::Num),
+    Ok(value) => value,
+    Err(error) => CellValue::Error(error),
+  }
+}
+
+fn check_arity(args: &[Operand], min: usize, max: usize) -> Result<(), CellError> {
+  if args.len() < min || args.len() > max {
+    Err(CellError::Value)
+  } else {
+    Ok(())
+  }
+}
+
+/// Single value of an argument. A one-cell range is accepted as its value.
+fn scalar(arg: &Operand) -> Result<CellValue, CellError> {
+  match arg {
+    Operand::Value(CellValue::Error(error)) => Err(*error),
+    Operand::Value(value) => Ok(value.clone()),
+    Operand::Range(rows) if rows.len() == 1 && rows[0].len() == 1 => match &rows[0][0] {
+      CellValue::Error(error) => Err(*error),
+      value => Ok(value.clone()),
+    },
+    Operand::Range(_) => Err(CellError::Value),
+  }
+}
+
+fn number_arg(arg: &Operand) -> Result<f64, CellError> {
+  to_number(&scalar(arg)?)
+}
+
+fn text_arg(arg: &Operand) -> Result<String, CellError> {
+  to_text(&scalar(arg)?)
+}
+
+fn optional_number(args: &[Operand], idx: usize, default: f64) -> Result<f64, CellError> {
+  match args.get(idx) {
+    Some(arg) => number_arg(arg),
+    None => Ok(default),
+  }
+}
+
+/// Numbers taken from all arguments. Values passed directly are coerced, while
+/// text, booleans and blanks inside ranges are skipped. Errors propagate.
+fn numbers(args: &[Operand]) -> Result<Vec<f64>, CellError> {
+  let mut results = vec![];
+  for arg in args {
+    match arg {
+      Operand::Value(value) => results.push(to_number(value)?),
+      Operand::Range(_) => {
+        for value in arg.values() {
+          match value {
+            CellValue::Number(number) | CellValue::DateTime(number) => results.push(*number),
+            CellValue::Error(error) => return Err(*error),
+            _ => (),
+          }
+        }
+      }
+    }
+  }
+  Ok(results)
+}
+
+fn booleans(args: &[Operand]) -> Result<Vec<bool>, CellError> {
+  let mut results = vec![];
+  for arg in args {
+    match arg {
+      Operand::Value(value) => results.push(to_bool(value)?),
+      Operand::Range(_) => {
+        for value in arg.values() {
+          match value {
+            CellValue::Number(_) | CellValue::Boolean(_) => results.push(to_bool(value)?),
+            CellValue::Error(error) => return Err(*error),
+            _ => (),
+          }
+        }
+      }
+    }
+  }
+  if results.is_empty() {
+    return Err(CellError::Value);
+  }
+  Ok(results)
+}
+
+fn rows_of(arg: &Operand) -> Vec<Vec<CellValue>> {
+  match arg {
+    Operand::Value(value) => vec![vec![value.clone()]],
+    Operand::Range(rows) => rows.clone(),
+  }
+}
+
+fn sum(args: &[Operand]) -> CellValue {
+  result(numbers(args).map(|numbers| CellValue::Number(numbers.iter().sum())))
+}
+
+fn product(args: &[Operand]) -> CellValue {
+  result(numbers(args).map(|numbers| CellValue::Number(numbers.iter().product())))
+}
+
+fn average(args: &[Operand]) -> CellValue {
+  result(numbers(args).and_then(|numbers| {
+    if numbers.is_empty() {
+      Err(CellError::Div0)
+    } else {
+      Ok(CellValue::Number(numbers.iter().sum::<f64>() / numbers.len() as f64))
+    }
+  }))
+}
+
+fn extreme(args: &[Operand], f: fn(f64, f64) -> f64) -> CellValue {
+  result(numbers(args).map(|numbers| {
+    let mut iter = numbers.into_iter();
+    match iter.next() {
+      Some(first) => CellValue::Number(iter.fold(first, f)),
+      None => CellValue::Number(0.0),
+    }
+  }))
+}
+
+fn min(args: &[Operand]) -> CellValue {
+  extreme(args, f64::min)
+}
+
+fn max(args: &[Operand]) -> CellValue {
+  extreme(args, f64::max)
+}
+
+fn count(args: &[Operand]) -> CellValue {
+  let mut total = 0;
+  for arg in args {
+    for value in arg.values() {
+      match (arg, value) {
+        (_, CellValue::Number(_)) | (_, CellValue::DateTime(_)) => total += 1,
+        (Operand::Value(_), value) if to_number(value).is_ok() && !value.is_empty() => total += 1,
+        _ => (),
+      }
+    }
+  }
+  CellValue::Number(total as f64)
+}
+
+fn counta(args: &[Operand]) -> CellValue {
+  let total = args.iter().flat_map(|arg| arg.values()).filter(|value| !value.is_empty()).count();
+  CellValue::Number(total as f64)
+}
+
+fn countblank(args: &[Operand]) -> CellValue {
+  let total = args
+    .iter()
+    .flat_map(|arg| arg.values())
+    .filter(|value| match value {
+      CellValue::Empty => true,
+      CellValue::Text(text) => text.is_empty(),
+      _ => false,
+    })
+    .count();
+  CellValue::Number(total as f64)
+}
+
+fn unary(args: &[Operand], f: fn(f64) -> Result<f64, CellError>) -> CellValue {
+  result(check_arity(args, 1, 1).and_then(|_| number_arg(&args[0])).and_then(f).map(CellValue::Number))
+}
+
+fn abs(args: &[Operand]) -> CellValue {
+  unary(args, |n| Ok(n.abs()))
+}
+
+fn int(args: &[Operand]) -> CellValue {
+  unary(args, |n| Ok(n.floor()))
+}
+
+fn sqrt(args: &[Operand]) -> CellValue {
+  unary(args, |n| if n < 0.0 { Err(CellError::Num) } else { Ok(n.sqrt()) })
+}
+
+fn rounding(args: &[Operand], f: fn(f64) -> f64) -> CellValue {
+  result(check_arity(args, 1, 2).and_then(|_| {
+    let number = number_arg(&args[0])?;
+    let digits = optional_number(args, 1, 0.0)?.trunc() as i32;
+    let factor = 10f64.powi(digits);
+    // Trim binary noise to 15 significant digits first so 2.345 rounds up.
+    let scaled: f64 = format!("{:.14e}", number * factor).parse().unwrap();
+    Ok(CellValue::Number(f(scaled) / factor))
+  }))
+}
+
+fn round(args: &[Operand]) -> CellValue {
+  rounding(args, |n| n.abs().round().copysign(n))
+}
+
+fn roundup(args: &[Operand]) -> CellValue {
+  rounding(args, |n| n.abs().ceil().copysign(n))
+}
+
+fn rounddown(args: &[Operand]) -> CellValue {
+  rounding(args, f64::trunc)
+}
+
+fn modulo(args: &[Operand]) -> CellValue {
+  result(check_arity(args, 2, 2).and_then(|_| {
+    let number = number_arg(&args[0])?;
+    let divisor = number_arg(&args[1])?;
+    if divisor == 0.0 {
+      return Err(CellError::Div0);
+    }
+    Ok(CellValue::Number(number - divisor * (number / divisor).floor()))
+  }))
+}
+
+fn power(args: &[Operand]) -> CellValue {
+  result(check_arity(args, 2, 2).and_then(|_| Ok(CellValue::Number(number_arg(&args[0])?.powf(number_arg(&args[1])?)))))
+}
+
+fn if_(args: &[Operand]) -> CellValue {
+  result(check_arity(args, 1, 3).and_then(|_| {
+    let branch = if to_bool(&scalar(&args[0])?)? { 1 } else { 2 };
+    match args.get(branch) {
+      Some(arg) => scalar(arg),
+      None => Ok(CellValue::Boolean(branch == 1)),
+    }
+  }))
+}
+
+fn iferror(args: &[Operand]) -> CellValue {
+  result(check_arity(args, 2, 2).and_then(|_| match scalar(&args[0]) {
+    Ok(value) => Ok(value),
+    Err(_) => scalar(&args[1]),
+  }))
+}
+
+fn and(args: &[Operand]) -> CellValue {
+  result(booleans(args).map(|values| CellValue::Boolean(values.iter().all(|b| *b))))
+}
+
+fn or(args: &[Operand]) -> CellValue {
+  result(booleans(args).map(|values| CellValue::Boolean(values.iter().any(|b| *b))))
+}
+
+fn not(args: &[Operand]) -> CellValue {
+  result(check_arity(args, 1, 1).and_then(|_| Ok(CellValue::Boolean(!to_bool(&scalar(&args[0])?)?))))
+}
+
+fn is(args: &[Operand], f: fn(&CellValue) -> bool) -> CellValue {
+  result(check_arity(args, 1, 1).map(|_| {
+    let value = match scalar(&args[0]) {
+      Ok(value) => value,
+      Err(error) => CellValue::Error(error),
+    };
+    CellValue::Boolean(f(&value))
+  }))
+}
+
+fn isblank(args: &[Operand]) -> CellValue {
+  is(args, CellValue::is_empty)
+}
+
+fn isnumber(args: &[Operand]) -> CellValue {
+  is(args, |value| matches!(value, CellValue::Number(_) | CellValue::DateTime(_)))
+}
+
+fn istext(args: &[Operand]) -> CellValue {
+  is(args, |value| matches!(value, CellValue::Text(_)))
+}
+
+fn iserror(args: &[Operand]) -> CellValue {
+  is(args, |value| matches!(value, CellValue::Error(_)))
+}
+
+fn concat(args: &[Operand]) -> CellValue {
+  let mut text = String::new();
+  for arg in args {
+    for value in arg.values() {
+      match to_text(value) {
+        Ok(part) => text.push_str(&part),
+        Err(error) => return CellValue::Error(error),
+      }
+    }
+  }
+  CellValue::Text(text)
+}
+
+fn left(args: &[Operand]) -> CellValue {
+  result(check_arity(args, 1, 2).and_then(|_| {
+    let text = text_arg(&args[0])?;
+    let count = optional_number(args, 1, 1.0)?;
+    if count < 0.0 {
+      return Err(CellError::Value);
+    }
+    Ok(CellValue::Text(text.chars().take(count as usize).collect()))
+  }))
+}
+
+fn right(args: &[Operand]) -> CellValue {
+  result(check_arity(args, 1, 2).and_then(|_| {
+    let text = text_arg(&args[0])?;
+    let count = optional_number(args, 1, 1.0)?;
+    if count < 0.0 {
+      return Err(CellError::Value);
+    }
+    let length = text.chars().count();
+    Ok(CellValue::Text(text.chars().skip(length.saturating_sub(count as usize)).collect()))
+  }))
+}
+
+fn mid(args: &[Operand]) -> CellValue {
+  result(check_arity(args, 3, 3).and_then(|_| {
+    let text = text_arg(&args[0])?;
+    let start = number_arg(&args[1])?;
+    let count = number_arg(&args[2])?;
+    if start < 1.0 || count < 0.0 {
+      return Err(CellError::Value);
+    }
+    Ok(CellValue::Text(text.chars().skip(start as usize - 1).take(count as usize).collect()))
+  }))
+}
+
+fn len(args: &[Operand]) -> CellValue {
+  result(check_arity(args, 1, 1).and_then(|_| Ok(CellValue::Number(text_arg(&args[0])?.chars().count() as f64))))
+}
+
+fn upper(args: &[Operand]) -> CellValue {
+  result(check_arity(args, 1, 1).and_then(|_| Ok(CellValue::Text(text_arg(&args[0])?.to_uppercase()))))
+}
+
+fn lower(args: &[Operand]) -> CellValue {
+  result(check_arity(args, 1, 1).and_then(|_| Ok(CellValue::Text(text_arg(&args[0])?.to_lowercase()))))
+}
+
+fn trim(args: &[Operand]) -> CellValue {
+  result(check_arity(args, 1, 1).and_then(|_| {
+    let text = text_arg(&args[0])?;
+    Ok(CellValue::Text(text.split_whitespace().collect::<Vec<&str>>().join(" ")))
+  }))
+}
+
+/// Position of `needle` in `haystack`. Exact matching returns the first equal
+/// value; approximate matching assumes ascending (or descending when
+/// `descending`) order and returns the last position not past the needle.
+fn lookup_position(
+  needle: &CellValue,
+  haystack: &[CellValue],
+  exact: bool,
+  descending: bool,
+) -> Result<usize, CellError> {
+  if exact {
+    return haystack
+      .iter()
+      .position(|value| compare_values(value, needle) == Ordering::Equal && !value.is_empty())
+      .ok_or(CellError::Na);
+  }
+  let mut found = None;
+  for (idx, value) in haystack.iter().enumerate() {
+    if value.is_empty() {
+      continue;
+    }
+    let ordering = compare_values(value, needle);
+    let within = if descending { ordering != Ordering::Less } else { ordering != Ordering::Greater };
+    if within {
+      found = Some(idx);
+    } else {
+      break;
+    }
+  }
+  found.ok_or(CellError::Na)
+}
+
+fn table_lookup(args: &[Operand], vertical: bool) -> CellValue {
+  result(check_arity(args, 3, 4).and_then(|_| {
+    let needle = scalar(&args[0])?;
+    let mut table = rows_of(&args[1]);
+    if !vertical {
+      let width = table.iter().map(|row| row.len()).max().unwrap_or(0);
+      table = (0..width).map(|col| table.iter().map(|row| row[col].clone()).collect()).collect();
+    }
+    let offset = number_arg(&args[2])?.trunc();
+    let approximate = match args.get(3) {
+      Some(arg) => to_bool(&scalar(arg)?)?,
+      None => true,
+    };
+    if offset < 1.0 {
+      return Err(CellError::Value);
+    }
+    let offset = offset as usize - 1;
+    if table.is_empty() || offset >= table[0].len() {
+      return Err(CellError::Ref);
+    }
+    let keys: Vec<CellValue> = table.iter().map(|row| row[0].clone()).collect();
+    let position = lookup_position(&needle, &keys, !approximate, false)?;
+    Ok(table[position][offset].clone())
+  }))
+}
+
+fn vlookup(args: &[Operand]) -> CellValue {
+  table_lookup(args, true)
+}
+
+fn hlookup(args: &[Operand]) -> CellValue {
+  table_lookup(args, false)
+}
+
+fn index(args: &[Operand]) -> CellValue {
+  result(check_arity(args, 2, 3).and_then(|_| {
+    let table = rows_of(&args[0]);
+    let mut row = number_arg(&args[1])?.trunc();
+    let mut col = optional_number(args, 2, 1.0)?.trunc();
+    if args.len() == 2 && table.len() == 1 {
+      col = row;
+      row = 1.0;
+    }
+    if row < 1.0 || col < 1.0 {
+      return Err(CellError::Value);
+    }
+    match table.get(row as usize - 1).and_then(|cells| cells.get(col as usize - 1)) {
+      Some(CellValue::Empty) => Ok(CellValue::Number(0.0)),
+      Some(value) => Ok(value.clone()),
+      None => Err(CellError::Ref),
+    }
+  }))
+}
+
+fn match_(args: &[Operand]) -> CellValue {
+  result(check_arity(args, 2, 3).and_then(|_| {
+    let needle = scalar(&args[0])?;
+    let table = rows_of(&args[1]);
+    let haystack: Vec<CellValue> = if table.len() == 1 {
+      table[0].clone()
+    } else if table.iter().all(|row| row.len() == 1) {
+      table.into_iter().map(|mut row| row.remove(0)).collect()
+    } else {
+      return Err(CellError::Na);
+    };
+    let kind = optional_number(args, 2, 1.0)?;
+    let position = lookup_position(&needle, &haystack, kind == 0.0, kind < 0.0)?;
+    Ok(CellValue::Number((position + 1) as f64))
+  }))
+}
+
+fn date(args: &[Operand]) -> CellValue {
+  result(check_arity(args, 3, 3).and_then(|_| {
+    let year = number_arg(&args[0])?.trunc();
+    let month = number_arg(&args[1])?.trunc();
+    let day = number_arg(&args[2])?.trunc();
+    // Past these bounds no month or day rolls the date back within years 0
+    // to 9999; checked before casting so that nothing overflows.
+    if !(0.0..=9999.0).contains(&year) || month.abs() > 120_000.0 || day.abs() > 3_700_000.0 {
+      return Err(CellError::Num);
+    }
+    let year = if year < 1900.0 { year + 1900.0 } else { year } as i64;
+    let month = month as i64;
+    let year = year + (month - 1).div_euclid(12);
+    let month = (month - 1).rem_euclid(12) as u32 + 1;
+    let serial = ymd_to_serial(year, month, 1) + day - 1.0;
+    if !is_date_serial(serial) {
+      return Err(CellError::Num);
+    }
+    Ok(CellValue::DateTime(serial))
+  }))
+}
+
+fn date_part(args: &[Operand], f: fn((i64, u32, u32)) -> f64) -> CellValue {
+  result(check_arity(args, 1, 1).and_then(|_| {
+    let serial = number_arg(&args[0])?;
+    if !is_date_serial(serial) {
+      return Err(CellError::Num);
+    }
+    Ok(CellValue::Number(f(serial_to_ymd(serial))))
+  }))
+}
+
+fn year(args: &[Operand]) -> CellValue {
+  date_part(args, |(year, _, _)| year as f64)
+}
+
+fn month(args: &[Operand]) -> CellValue {
+  date_part(args, |(_, month, _)| month as f64)
+}
+
+fn day(args: &[Operand]) -> CellValue {
+  date_part(args, |(_, _, day)| day as f64)
+}
+
+fn current_serial() -> f64 {
+  let date = js_sys::Date::new_0();
+  let offset = date.get_timezone_offset() * 60_000.0;
+  (date.get_time() - offset) / 86_400_000.0 + 25569.0
+}
+
+fn today(args: &[Operand]) -> CellValue {
+  result(check_arity(args, 0, 0).map(|_| CellValue::DateTime(current_serial().floor())))
+}
+
+fn now(args: &[Operand]) -> CellValue {
+  result(check_arity(args, 0, 0).map(|_| CellValue::DateTime(current_serial())))
+}
+
+#[cfg(test)]
+mod tests {
+
+  use crate::formula::{evaluate, parse};
+  use crate::model::DataModel;
+  use crate::value::{CellError, CellValue};
+
+  fn eval(model: &DataModel, formula: &str) -> CellValue {
+    evaluate(&parse(formula).unwrap(), model)
+  }
+
+  #[test]
+  fn builtins() {
+    let mut model = DataModel::new();
+    let rows = [("apple", 3.0), ("banana", 5.0), ("cherry", 7.0)];
+    for (idx, (name, qty)) in rows.iter().enumerate() {
+      model.set_value(1, idx as u32 + 1, CellValue::Text(name.to_string()));
+      model.set_value(2, idx as u32 + 1, CellValue::Number(*qty));
+    }
+    assert_eq!(eval(&model, "=AVERAGE(B1:B3)"), CellValue::Number(5.0));
+    assert_eq!(eval(&model, "=MAX(B1:B3)-MIN(B1:B3)"), CellValue::Number(4.0));
+    assert_eq!(eval(&model, "=COUNT(A1:B3)"), CellValue::Number(3.0));
+    assert_eq!(eval(&model, "=VLOOKUP(\"banana\",A1:B3,2,FALSE)"), CellValue::Number(5.0));
+    assert_eq!(eval(&model, "=VLOOKUP(\"kiwi\",A1:B3,2,FALSE)"), CellValue::Error(CellError::Na));
+    assert_eq!(eval(&model, "=VLOOKUP(\"apple\",A1:B3,3,FALSE)"), CellValue::Error(CellError::Ref));
+    assert_eq!(eval(&model, "=INDEX(A1:B3,MATCH(\"cherry\",A1:A3,0),2)"), CellValue::Number(7.0));
+    assert_eq!(eval(&model, "=IF(AND(B1>1,OR(FALSE,B2=5)),\"yes\",\"no\")"), CellValue::Text("yes".to_string()));
+    assert_eq!(eval(&model, "=IFERROR(1/0,\"none\")"), CellValue::Text("none".to_string()));
+    assert_eq!(eval(&model, "=AVERAGE(1/0,2)"), CellValue::Error(CellError::Div0));
+    assert_eq!(
+      eval(&model, "=CONCAT(LEFT(A1,2),MID(A2,3,2),UPPER(RIGHT(A3)))&LEN(A3)"),
+      CellValue::Text("apnaY6".to_string())
+    );
+    assert_eq!(eval(&model, "=ROUND(2.345,2)+ROUNDDOWN(-1.9)"), CellValue::Number(1.35));
+    assert_eq!(eval(&model, "=YEAR(DATE(2020,14,1))*100+MONTH(DATE(2020,14,1))"), CellValue::Number(202102.0));
+    assert_eq!(eval(&model, "=YEAR(DATE(9999,12,31))"), CellValue::Number(9999.0));
+    assert_eq!(eval(&model, "=DATE(10000,1,1)"), CellValue::Error(CellError::Num));
+    assert_eq!(eval(&model, "=DATE(9999,13,1)"), CellValue::Error(CellError::Num));
+    assert_eq!(eval(&model, "=DATE(2000,-1E300,1)"), CellValue::Error(CellError::Num));
+    assert_eq!(eval(&model, "=DATE(-5000000000000000000,1,1)"), CellValue::Error(CellError::Num));
+    assert_eq!(eval(&model, "=DATE(-1,1,1)"), CellValue::Error(CellError::Num));
+    assert_eq!(eval(&model, "=YEAR(DATE(2020,1,-30))*100+MONTH(DATE(2020,1,-30))"), CellValue::Number(201912.0));
+    assert_eq!(eval(&model, "=YEAR(1E300)"), CellValue::Error(CellError::Num));
+    assert_eq!(eval(&model, "=DAY(1E20)"), CellValue::Error(CellError::Num));
+    assert!(model.get_functions().is_volatile("now") && !model.get_functions().is_volatile("SUM"));
+    assert_eq!(eval(&model, "=LEN(1,2)"), CellValue::Error(CellError::Value));
+    assert_eq!(eval(&model, "=NOSUCH(1)"), CellValue::Error(CellError::Name));
+  }
+}
@@ -0,0 +1,506 @@
+use std::collections::VecDeque;
+
+use super::column::{Column, ColumnManager};
+use super::model::{CellSnapshot, DataModel, RewrittenFormulas, ShiftedCells};
+use super::reference::{Axis, RangeRef};
+use super::row::{Row, RowManager};
+use super::style::{Style, StyleTarget};
+
+/// A reversible change to the sheet, holding both sides of the change.
+#[derive(Debug, Clone)]
+pub enum Command {
+  SetCell {
+    col_idx: u32,
+    row_idx: u32,
+    before: CellSnapshot,
+    after: CellSnapshot,
+  },
+  SetRowHeight {
+    row_idx: u32,
+    before: u16,
+    after: u16,
+  },
+  SetColumnWidth {
+    col_idx: u32,
+    before: u16,
+    after: u16,
+  },
+  InsertLines {
+    axis: Axis,
+    at: u32,
+    count: u32,
+  },
+  /// Deleted lines, with what they held and its styles, their sizes and
+  /// whether they were hidden, and the formulas and merges the deletion
+  /// changed, so that undoing it can put them back. `external` holds the
+  /// formulas of other sheets it rewrote, by sheet name, which only the
+  /// workbook can restore.
+  DeleteLines {
+    axis: Axis,
+    at: u32,
+    count: u32,
+    cells: ShiftedCells,
+    sizes: Vec<(u32, u16, bool)>,
+    external: Vec<(String, RewrittenFormulas)>,
+  },
+  /// Rows or columns hidden or shown; `indices` are those that changed.
+  SetHidden {
+    axis: Axis,
+    indices: Vec<u32>,
+    hidden: bool,
+  },
+  /// Boxed, as styles are large next to the other commands.
+  SetStyle {
+    target: StyleTarget,
+    before: Box<Style>,
+    after: Box<Style>,
+  },
+  /// Merges removed and added, as when merging over existing merges.
+  SetMerges {
+    before: Vec<RangeRef>,
+    after: Vec<RangeRef>,
+  },
+  /// A column shown at another position, see `ColumnManager::move_column`.
+  MoveColumn {
+    from: u32,
+    to: u32,
+  },
+  /// Commands undone and redone as one, such as a paste.
+  Group(Vec<Command>),
+}
+
+impl Command {
+  pub fn apply(&self, model: &mut DataModel, rows: &mut RowManager, cols: &mut ColumnManager) {
+    match self {
+      Command::SetCell {
+        col_idx, row_idx, after, ..
+      } => model.restore(*col_idx, *row_idx, after),
+      Command::SetRowHeight { row_idx, after, .. } => set_row_height(rows, *row_idx, *after),
+      Command::SetColumnWidth { col_idx, after, .. } => set_column_width(cols, *col_idx, *after),
+      Command::InsertLines { axis, at, count } => {
+        shift_lines(model, rows, cols, *axis, *at, *count as i64);
+      }
+      Command::DeleteLines { axis, at, count, .. } => {
+        shift_lines(model, rows, cols, *axis, *at, -(*count as i64));
+      }
+      Command::SetHidden { axis, indices, hidden } => set_hidden(rows, cols, *axis, indices, *hidden),
+      Command::SetStyle { target, after, .. } => model.styles.set(*target, (**after).clone()),
+      Command::SetMerges { before, after } => set_merges(model, before, after),
+      Command::MoveColumn { from, to } => cols.move_column(*from, *to),
+      Command::Group(commands) => {
+        for command in commands.iter() {
+          command.apply(model, rows, cols);
+        }
+      }
+    }
+  }
+
+  pub fn revert(&self, model: &mut DataModel, rows: &mut RowManager, cols: &mut ColumnManager) {
+    match self {
+      Command::SetCell {
+        col_idx, row_idx, before, ..
+      } => model.restore(*col_idx, *row_idx, before),
+      Command::SetRowHeight { row_idx, before, .. } => set_row_height(rows, *row_idx, *before),
+      Command::SetColumnWidth { col_idx, before, .. } => set_column_width(cols, *col_idx, *before),
+      Command::InsertLines { axis, at, count } => {
+        shift_lines(model, rows, cols, *axis, *at, -(*count as i64));
+      }
+      Command::DeleteLines {
+        axis,
+        at,
+        count,
+        cells,
+        sizes,
+        ..
+      } => {
+        shift_lines(model, rows, cols, *axis, *at, *count as i64);
+        for (col_idx, row_idx, source) in cells.rewritten.iter() {
+          let snapshot = CellSnapshot::formula(source);
+          model.insert_cell(*col_idx, *row_idx, snapshot);
+        }
+        for (col_idx, row_idx, snapshot) in cells.removed.iter() {
+          model.insert_cell(*col_idx, *row_idx, snapshot.clone());
+        }
+        for (target, style) in cells.styles.iter() {
+          model.styles.set(*target, style.clone());
+        }
+        for merge in cells.merges.iter() {
+          let grown = model.merges.get_overlapping(merge);
+          set_merges(model, &grown, &[*merge]);
+        }
+        model.recalculate_all();
+        for (idx, size, hidden) in sizes.iter() {
+          match axis {
+            Axis::Rows => set_row_height(rows, *idx, *size),
+            Axis::Columns => set_column_width(cols, *idx, *size),
+          }
+          set_hidden(rows, cols, *axis, &[*idx], *hidden);
+        }
+      }
+      Command::SetHidden { axis, indices, hidden } => set_hidden(rows, cols, *axis, indices, !*hidden),
+      Command::SetStyle { target, before, .. } => model.styles.set(*target, (**before).clone()),
+      Command::SetMerges { before, after } => set_merges(model, after, before),
+      Command::MoveColumn { from, to } => cols.move_column(*to, *from),
+      Command::Group(commands) => {
+        for command in commands.iter().rev() {
+          command.revert(model, rows, cols);
+        }
+      }
+    }
+  }
+
+  /// Formulas of other sheets rewritten by the command's deletions, in order.
+  pub fn get_external_formulas(&self) -> Vec<&(String, RewrittenFormulas)> {
+    match self {
+      Command::DeleteLines { external, .. } => external.iter().collect(),
+      Command::Group(commands) => commands.iter().flat_map(|command| command.get_external_formulas()).collect(),
+      _ => vec![],
+    }
+  }
+
+  /// Row and column shifts the command makes, in order, as `(axis, at, delta)`
+  /// for `DataModel::shift_lines`.
+  pub fn get_shifts(&self) -> Vec<(Axis, u32, i64)> {
+    match self {
+      Command::InsertLines { axis, at, count } => vec![(*axis, *at, *count as i64)],
+      Command::DeleteLines { axis, at, count, .. } => vec![(*axis, *at, -(*count as i64))],
+      Command::Group(commands) => commands.iter().flat_map(|command| command.get_shifts()).collect(),
+      _ => vec![],
+    }
+  }
+}
+
+/// Shift cells and sizes alike, returning what was deleted.
+pub fn shift_lines(
+  model: &mut DataModel,
+  rows: &mut RowManager,
+  cols: &mut ColumnManager,
+  axis: Axis,
+  at: u32,
+  delta: i64,
+) -> (ShiftedCells, Vec<(u32, u16, bool)>) {
+  let cells = model.shift_lines(axis, at, delta);
+  let sizes = match axis {
+    Axis::Rows => rows.shift(at, delta),
+    Axis::Columns => cols.shift(at, delta),
+  };
+  (cells, sizes)
+}
+
+fn set_merges(model: &mut DataModel, removed: &[RangeRef], added: &[RangeRef]) {
+  for range in removed.iter() {
+    model.merges.remove(range);
+  }
+  for range in added.iter() {
+    model.merges.add(*range);
+  }
+}
+
+fn set_row_height(rows: &mut RowManager, row_idx: u32, height: u16) {
+  let mut row = Row::new(row_idx);
+  row.set_height(height);
+  row.set_hidden(rows.is_hidden(row_idx));
+  rows.set_row(row);
+}
+
+fn set_column_width(cols: &mut ColumnManager, col_idx: u32, width: u16) {
+  let mut column = Column::new(col_idx);
+  column.set_width(width);
+  column.set_hidden(cols.is_hidden(col_idx));
+  cols.set_column(column);
+}
+
+fn set_hidden(rows: &mut RowManager, cols: &mut ColumnManager, axis: Axis, indices: &[u32], hidden: bool) {
+  for idx in indices.iter() {
+    match axis {
+      Axis::Rows => rows.set_hidden(*idx, hidden),
+      Axis::Columns => cols.set_hidden(*idx, hidden),
+    }
+  }
+}
+
+/// Applied commands that can be undone, and undone ones that can be redone.
+/// Only the most recent `limit` commands are kept. Commands recorded between
+/// `begin_group` and `end_group` are undone as one.
+pub struct History {
+  done: VecDeque<Command>,
+  undone: Vec<Command>,
+  limit: usize,
+  groups: Vec<Vec<Command>>,
+}
+
+impl History {
+  pub fn new(limit: usize) -> Self {
+    Self {
+      done: VecDeque::new(),
+      undone: vec![],
+      limit,
+      groups: vec![],
+    }
+  }
+
+  pub fn get_limit(&self) -> usize {
+    self.limit
+  }
+
+  pub fn set_limit(&mut self, limit: usize) {
+    self.limit = limit;
+    self.trim();
+  }
+
+  fn trim(&mut self) {
+    while self.done.len() > self.limit {
+      self.done.pop_front();
+    }
+  }
+
+  /// Record a command that has already been applied. Recording discards
+  /// whatever could have been redone.
+  pub fn record(&mut self, command: Command) {
+    match self.groups.last_mut() {
+      Some(group) => group.push(command),
+      None => {
+        self.undone.clear();
+        self.done.push_back(command);
+        self.trim();
+      }
+    }
+  }
+
+  pub fn begin_group(&mut self) {
+    self.groups.push(vec![]);
+  }
+
+  pub fn end_group(&mut self) {
+    let mut commands = match self.groups.pop() {
+      Some(commands) => commands,
+      None => return,
+    };
+    match commands.len() {
+      0 => (),
+      1 => self.record(commands.pop().unwrap()),
+      _ => self.record(Command::Group(commands)),
+    }
+  }
+
+  pub fn can_undo(&self) -> bool {
+    !self.done.is_empty()
+  }
+
+  pub fn can_redo(&self) -> bool {
+    !self.undone.is_empty()
+  }
+
+  /// Revert the most recent command. Returns it, or `None` when there is
+  /// nothing to undo.
+  pub fn undo(&mut self, model: &mut DataModel, rows: &mut RowManager, cols: &mut ColumnManager) -> Option<&Command> {
+    let command = self.done.pop_back()?;
+    command.revert(model, rows, cols);
+    self.undone.push(command);
+    self.undone.last()
+  }
+
+  /// Apply the most recently undone command again.
+  pub fn redo(&mut self, model: &mut DataModel, rows: &mut RowManager, cols: &mut ColumnManager) -> Option<&Command> {
+    let command = self.undone.pop()?;
+    command.apply(model, rows, cols);
+    self.done.push_back(command);
+    self.trim();
+    self.done.back()
+  }
+
+  pub fn clear(&mut self) {
+    self.done.clear();
+    self.undone.clear();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::{shift_lines, Command, History};
+  use crate::column::{Column, ColumnManager};
+  use crate::model::{CellSnapshot, DataModel};
+  use crate::reference::{Axis, RangeRef};
+  use crate::row::{Row, RowManager};
+  use crate::style::{Style, StyleTarget};
+  use crate::value::{CellError, CellValue};
+
+  fn set_cell(model: &mut DataModel, history: &mut History, col_idx: u32, row_idx: u32, after: CellSnapshot) {
+    let before = model.snapshot(col_idx, row_idx);
+    model.restore(col_idx, row_idx, &after);
+    history.record(Command::SetCell {
+      col_idx,
+      row_idx,
+      before,
+      after,
+    });
+  }
+
+  #[test]
+  fn undo_redo() {
+    let mut model = DataModel::new();
+    let mut rows = RowManager::new(100, 20);
+    let mut cols = ColumnManager::new(100, 85);
+    let mut history = History::new(2);
+    set_cell(&mut model, &mut history, 1, 1, CellSnapshot::value(CellValue::Number(1.0)));
+    set_cell(&mut model, &mut history, 1, 2, CellSnapshot::formula("=A1*2"));
+    history.begin_group();
+    set_cell(&mut model, &mut history, 1, 1, CellSnapshot::value(CellValue::Number(5.0)));
+    let mut row = Row::new(2);
+    row.set_height(40);
+    rows.set_row(row);
+    history.record(Command::SetRowHeight {
+      row_idx: 2,
+      before: 20,
+      after: 40,
+    });
+    history.end_group();
+    assert_eq!(model.get_value(1, 2), &CellValue::Number(10.0));
+
+    assert!(history.undo(&mut model, &mut rows, &mut cols).is_some());
+    assert_eq!(model.get_value(1, 2), &CellValue::Number(2.0));
+    assert_eq!(rows.get_height(2), 20);
+    history.undo(&mut model, &mut rows, &mut cols);
+    assert_eq!(model.get_formula(1, 2), None);
+    // The first edit fell off the two-command history.
+    assert!(history.undo(&mut model, &mut rows, &mut cols).is_none());
+    assert_eq!(model.get_value(1, 1), &CellValue::Number(1.0));
+
+    history.redo(&mut model, &mut rows, &mut cols);
+    history.redo(&mut model, &mut rows, &mut cols);
+    assert_eq!(model.get_value(1, 2), &CellValue::Number(10.0));
+    assert_eq!(rows.get_height(2), 40);
+    assert!(!history.can_redo());
+    history.undo(&mut model, &mut rows, &mut cols);
+    set_cell(&mut model, &mut history, 2, 1, CellSnapshot::value(CellValue::Boolean(true)));
+    assert!(!history.can_redo());
+  }
+
+  #[test]
+  fn insert_and_delete_lines() {
+    let mut model = DataModel::new();
+    let mut rows = RowManager::new(100, 20);
+    let mut cols = ColumnManager::new(100, 85);
+    let mut history = History::new(10);
+    for row_idx in 1..=4 {
+      model.set_value(1, row_idx, CellValue::Number(row_idx as f64));
+    }
+    model.set_formula(2, 1, "=SUM(A1:A4)+A3");
+    let mut row = Row::new(3);
+    row.set_height(40);
+    rows.set_row(row);
+
+    shift_lines(&mut model, &mut rows, &mut cols, Axis::Rows, 2, 2);
+    history.record(Command::InsertLines {
+      axis: Axis::Rows,
+      at: 2,
+      count: 2,
+    });
+    assert_eq!(model.get_formula(2, 1), Some(&"=SUM(A1:A6)+A5".to_string()));
+    assert_eq!(model.get_value(1, 5), &CellValue::Number(3.0));
+    assert_eq!(rows.get_height(5), 40);
+
+    let (cells, sizes) = shift_lines(&mut model, &mut rows, &mut cols, Axis::Rows, 5, -1);
+    history.record(Command::DeleteLines {
+      axis: Axis::Rows,
+      at: 5,
+      count: 1,
+      cells,
+      sizes,
+      external: vec![],
+    });
+    assert_eq!(model.get_formula(2, 1), Some(&"=SUM(A1:A5)+#REF!".to_string()));
+    assert_eq!(model.get_value(2, 1), &CellValue::Error(CellError::Ref));
+    assert_eq!(model.get_value(1, 5), &CellValue::Number(4.0));
+    assert_eq!(rows.get_height(5), 20);
+
+    history.undo(&mut model, &mut rows, &mut cols);
+    assert_eq!(model.get_formula(2, 1), Some(&"=SUM(A1:A6)+A5".to_string()));
+    assert_eq!(model.get_value(2, 1), &CellValue::Number(13.0));
+    assert_eq!(rows.get_height(5), 40);
+    history.undo(&mut model, &mut rows, &mut cols);
+    assert_eq!(model.get_formula(2, 1), Some(&"=SUM(A1:A4)+A3".to_string()));
+    assert_eq!(model.get_value(1, 3), &CellValue::Number(3.0));
+    assert_eq!(rows.get_height(3), 40);
+    history.redo(&mut model, &mut rows, &mut cols);
+
+    rows.set_hidden(5, true);
+    let style = Style {
+      italic: Some(true),
+      ..Style::default()
+    };
+    model.styles.set(StyleTarget::Row(5), style.clone());
+    let (cells, sizes) = shift_lines(&mut model, &mut rows, &mut cols, Axis::Rows, 5, -1);
+    history.record(Command::DeleteLines {
+      axis: Axis::Rows,
+      at: 5,
+      count: 1,
+      cells,
+      sizes,
+      external: vec![],
+    });
+    history.undo(&mut model, &mut rows, &mut cols);
+    assert!(rows.is_hidden(5));
+    assert_eq!(model.styles.resolve(1, 5), style);
+    let merge = RangeRef::parse("A4:B6").unwrap();
+    Command::SetMerges {
+      before: vec![],
+      after: vec![merge],
+    }
+    .apply(&mut model, &mut rows, &mut cols);
+    let (cells, sizes) = shift_lines(&mut model, &mut rows, &mut cols, Axis::Rows, 3, -2);
+    assert_eq!(model.merges.get_ranges(), [RangeRef::parse("A3:B4").unwrap()]);
+    history.record(Command::DeleteLines {
+      axis: Axis::Rows,
+      at: 3,
+      count: 2,
+      cells,
+      sizes,
+      external: vec![],
+    });
+    history.undo(&mut model, &mut rows, &mut cols);
+    assert_eq!(model.merges.get_ranges(), [merge]);
+    assert_eq!((rows.get_height(5), rows.get_shown_height(5)), (0, 40));
+    let command = Command::SetHidden {
+      axis: Axis::Rows,
+      indices: vec![5],
+      hidden: false,
+    };
+    command.apply(&mut model, &mut rows, &mut cols);
+    assert_eq!(rows.get_height(5), 40);
+    command.revert(&mut model, &mut rows, &mut cols);
+    assert_eq!(rows.get_height(5), 0);
+    shift_lines(&mut model, &mut rows, &mut cols, Axis::Columns, 1, -1);
+    assert_eq!(model.get_formula(1, 1), Some(&"=SUM(#REF!)+#REF!".to_string()));
+  }
+
+  #[test]
+  fn move_columns() {
+    let mut model = DataModel::new();
+    let mut rows = RowManager::new(100, 20);
+    let mut cols = ColumnManager::new(5, 10);
+    let mut col = Column::new(2);
+    col.set_width(30);
+    cols.set_column(col);
+    let command = Command::MoveColumn { from: 2, to: 4 };
+    command.apply(&mut model, &mut rows, &mut cols);
+    assert_eq!(cols.get_order(), vec![1, 3, 4, 2]);
+    assert_eq!((cols.get_column_at(4), cols.get_position(2)), (2, 4));
+    assert_eq!((cols.get_offset(4), cols.get_offset(5)), (30.0, 60.0));
+    assert_eq!(cols.get_width(2), 30);
+
+    shift_lines(&mut model, &mut rows, &mut cols, Axis::Columns, 3, 1);
+    assert_eq!(cols.get_order(), vec![1, 3, 4, 5, 2]);
+    shift_lines(&mut model, &mut rows, &mut cols, Axis::Columns, 1, -1);
+    assert_eq!(cols.get_order(), vec![2, 3, 4, 1]);
+    command.revert(&mut model, &mut rows, &mut cols);
+    assert_eq!(cols.get_order(), vec![2, 1]);
+
+    assert!(cols.set_order(&[3, 1, 2]).is_ok());
+    assert_eq!(cols.get_order(), vec![3, 1, 2]);
+    assert!(cols.set_order(&[2, 2]).is_err());
+    assert!(cols.set_order(&[]).is_ok());
+    assert_eq!(cols.get_position(2), 2);
+  }
+}
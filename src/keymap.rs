@@ -0,0 +1,275 @@
+use std::collections::BTreeMap;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Direction {
+  Left,
+  Right,
+  Up,
+  Down,
+}
+
+impl Direction {
+  const ALL: [Direction; 4] = [Direction::Left, Direction::Right, Direction::Up, Direction::Down];
+
+  /// Column and row delta of one step.
+  pub fn delta(&self) -> (i64, i64) {
+    match self {
+      Direction::Left => (-1, 0),
+      Direction::Right => (1, 0),
+      Direction::Up => (0, -1),
+      Direction::Down => (0, 1),
+    }
+  }
+
+  fn name(&self) -> &'static str {
+    match self {
+      Direction::Left => "Left",
+      Direction::Right => "Right",
+      Direction::Up => "Up",
+      Direction::Down => "Down",
+    }
+  }
+}
+
+/// How far a move goes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Stride {
+  /// One cell.
+  Cell,
+  /// To the edge of the current or next block of data.
+  DataEdge,
+  /// By the number of rows or columns visible.
+  Page,
+  /// To the first or last used column or row.
+  Line,
+}
+
+impl Stride {
+  const ALL: [Stride; 4] = [Stride::Cell, Stride::DataEdge, Stride::Page, Stride::Line];
+
+  fn suffix(&self) -> &'static str {
+    match self {
+      Stride::Cell => "",
+      Stride::DataEdge => "ToEdge",
+      Stride::Page => "ByPage",
+      Stride::Line => "ToLineEnd",
+    }
+  }
+}
+
+/// What a key chord does outside of editing.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Action {
+  /// Move the active cell, or with `extend` the selection focus.
+  Move {
+    direction: Direction,
+    stride: Stride,
+    extend: bool,
+  },
+  /// Go to the first cell of the sheet.
+  GoToStart { extend: bool },
+  /// Go to the last used cell of the sheet.
+  GoToEnd { extend: bool },
+  /// Step the active cell through the selection, as Tab and Enter do.
+  Advance(Direction),
+  SelectAll,
+  Edit,
+  Undo,
+  Redo,
+}
+
+impl Action {
+  /// Every action, in the order used to resolve names.
+  pub fn all() -> Vec<Action> {
+    let mut actions = vec![];
+    for &extend in [false, true].iter() {
+      for &stride in Stride::ALL.iter() {
+        for &direction in Direction::ALL.iter() {
+          actions.push(Action::Move {
+            direction,
+            stride,
+            extend,
+          });
+        }
+      }
+      actions.push(Action::GoToStart { extend });
+      actions.push(Action::GoToEnd { extend });
+    }
+    for &direction in Direction::ALL.iter() {
+      actions.push(Action::Advance(direction));
+    }
+    actions.push(Action::SelectAll);
+    actions.push(Action::Edit);
+    actions.push(Action::Undo);
+    actions.push(Action::Redo);
+    actions
+  }
+
+  /// Name used to bind the action from JS, e.g. `moveLeft`, `extendDownToEdge`,
+  /// `moveUpByPage`, `goToEnd` or `advanceRight`.
+  pub fn name(&self) -> String {
+    let verb = |extend: bool| if extend { "extend" } else { "move" };
+    match self {
+      Action::Move {
+        direction,
+        stride,
+        extend,
+      } => [verb(*extend), direction.name(), stride.suffix()].concat(),
+      Action::GoToStart { extend: false } => "goToStart".to_string(),
+      Action::GoToStart { extend: true } => "extendToStart".to_string(),
+      Action::GoToEnd { extend: false } => "goToEnd".to_string(),
+      Action::GoToEnd { extend: true } => "extendToEnd".to_string(),
+      Action::Advance(direction) => ["advance", direction.name()].concat(),
+      Action::SelectAll => "selectAll".to_string(),
+      Action::Edit => "edit".to_string(),
+      Action::Undo => "undo".to_string(),
+      Action::Redo => "redo".to_string(),
+    }
+  }
+
+  pub fn parse(name: &str) -> Option<Self> {
+    Self::all().into_iter().find(|action| action.name() == name)
+  }
+}
+
+/// Key chords and the actions bound to them. Chords are written as modifiers
+/// followed by the `KeyboardEvent.key` value, joined by `+`, e.g.
+/// `Ctrl+Shift+ArrowDown`; they are matched case-insensitively and Cmd counts
+/// as Ctrl.
+pub struct KeyMap {
+  bindings: BTreeMap<String, Action>,
+}
+
+impl KeyMap {
+  pub fn new() -> Self {
+    Self {
+      bindings: BTreeMap::new(),
+    }
+  }
+
+  /// The usual spreadsheet bindings.
+  pub fn default() -> Self {
+    let mut map = Self::new();
+    let arrows = [
+      ("ArrowLeft", Direction::Left),
+      ("ArrowRight", Direction::Right),
+      ("ArrowUp", Direction::Up),
+      ("ArrowDown", Direction::Down),
+    ];
+    for &extend in [false, true].iter() {
+      let shift = if extend { "Shift+" } else { "" };
+      let move_by = |direction, stride| Action::Move {
+        direction,
+        stride,
+        extend,
+      };
+      for (key, direction) in arrows.iter() {
+        map.bind(&[shift, key].concat(), move_by(*direction, Stride::Cell));
+        map.bind(&["Ctrl+", shift, key].concat(), move_by(*direction, Stride::DataEdge));
+      }
+      map.bind(&[shift, "PageUp"].concat(), move_by(Direction::Up, Stride::Page));
+      map.bind(&[shift, "PageDown"].concat(), move_by(Direction::Down, Stride::Page));
+      map.bind(&["Alt+", shift, "PageUp"].concat(), move_by(Direction::Left, Stride::Page));
+      map.bind(&["Alt+", shift, "PageDown"].concat(), move_by(Direction::Right, Stride::Page));
+      map.bind(&[shift, "Home"].concat(), move_by(Direction::Left, Stride::Line));
+      map.bind(&[shift, "End"].concat(), move_by(Direction::Right, Stride::Line));
+      map.bind(&["Ctrl+", shift, "Home"].concat(), Action::GoToStart { extend });
+      map.bind(&["Ctrl+", shift, "End"].concat(), Action::GoToEnd { extend });
+    }
+    map.bind("Tab", Action::Advance(Direction::Right));
+    map.bind("Shift+Tab", Action::Advance(Direction::Left));
+    map.bind("Enter", Action::Advance(Direction::Down));
+    map.bind("Shift+Enter", Action::Advance(Direction::Up));
+    map.bind("Ctrl+A", Action::SelectAll);
+    map.bind("F2", Action::Edit);
+    map.bind("Ctrl+Z", Action::Undo);
+    map.bind("Ctrl+Y", Action::Redo);
+    map.bind("Ctrl+Shift+Z", Action::Redo);
+    map
+  }
+
+  /// Bind a chord, replacing whatever it was bound to.
+  pub fn bind(&mut self, chord: &str, action: Action) {
+    self.bindings.insert(Self::normalize(chord), action);
+  }
+
+  pub fn unbind(&mut self, chord: &str) -> Option<Action> {
+    self.bindings.remove(&Self::normalize(chord))
+  }
+
+  pub fn get(&self, chord: &str) -> Option<Action> {
+    self.bindings.get(&Self::normalize(chord)).copied()
+  }
+
+  /// The chord of a keyboard event.
+  pub fn chord(event: &web_sys::KeyboardEvent) -> String {
+    let mut parts = vec![];
+    if event.ctrl_key() || event.meta_key() {
+      parts.push("Ctrl".to_string());
+    }
+    if event.alt_key() {
+      parts.push("Alt".to_string());
+    }
+    if event.shift_key() {
+      parts.push("Shift".to_string());
+    }
+    parts.push(event.key());
+    parts.join("+")
+  }
+
+  /// Canonical form of a chord: upper case, modifiers in a fixed order.
+  fn normalize(chord: &str) -> String {
+    let mut modifiers = [false; 3];
+    let mut key = String::new();
+    let mut parts: Vec<&str> = chord.split('+').collect();
+    // A trailing empty part means the key itself is `+`.
+    if chord.ends_with('+') {
+      parts.pop();
+      parts.pop();
+      key = "+".to_string();
+    }
+    for part in parts {
+      match part.trim().to_uppercase().as_str() {
+        "CTRL" | "CONTROL" | "CMD" | "META" => modifiers[0] = true,
+        "ALT" | "OPTION" => modifiers[1] = true,
+        "SHIFT" => modifiers[2] = true,
+        other => key = other.to_string(),
+      }
+    }
+    let mut normalized = String::new();
+    for (present, name) in modifiers.iter().zip(["CTRL+", "ALT+", "SHIFT+"].iter()) {
+      if *present {
+        normalized.push_str(name);
+      }
+    }
+    normalized.push_str(&key.to_uppercase());
+    normalized
+  }
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::{Action, Direction, KeyMap, Stride};
+  #[test]
+  fn chords_and_names() {
+    let mut map = KeyMap::default();
+    let jump = Action::Move {
+      direction: Direction::Down,
+      stride: Stride::DataEdge,
+      extend: true,
+    };
+    assert_eq!(map.get("shift+ctrl+arrowdown"), Some(jump));
+    assert_eq!(map.get("Cmd+Shift+ArrowDown"), Some(jump));
+    assert_eq!(map.get("Ctrl+a"), Some(Action::SelectAll));
+    assert_eq!(map.get("Cmd+Shift+z"), Some(Action::Redo));
+    assert_eq!(jump.name(), "extendDownToEdge");
+    assert_eq!(Action::parse("moveUpByPage").map(|action| action.name()), Some("moveUpByPage".to_string()));
+    assert_eq!(Action::parse("advanceRight"), Some(Action::Advance(Direction::Right)));
+    assert_eq!(Action::parse("jump"), None);
+    map.bind("Ctrl++", Action::Edit);
+    assert_eq!(map.get("ctrl++"), Some(Action::Edit));
+    assert_eq!(map.unbind("F2"), Some(Action::Edit));
+    assert_eq!(map.get("F2"), None);
+  }
+}
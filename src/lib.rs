@@ -3,32 +3,79 @@ mod rectangle;
 mod canvas;
 mod cell;
 mod celleditor;
+mod clipboard;
 mod column;
+mod csv;
+mod dependency;
 mod events;
+mod format;
+mod formula;
+mod functions;
+mod history;
+mod keymap;
+mod measure;
+mod merge;
 mod model;
 mod properties;
+mod reference;
 mod row;
 mod scroll;
+mod selection;
+mod sizeindex;
+mod style;
+mod value;
+mod workbook;
+mod xlsx;
+mod zip;
 
+use canvas::CanvasHelper;
 use cell::Cell;
+use celleditor::CellEditor;
 use column::{Column, ColumnManager};
 use events::{CustomEvent, CustomEventDetail, MousePosition};
-use model::DataModel;
+use format::NumberFormat;
+use formula::Operand;
+use history::Command;
+use keymap::{Action, Direction, KeyMap, Stride};
+use measure::TextMeasurer;
+use model::CellSnapshot;
+use properties::HyperSheetProperties;
+use reference::{Axis, CellRef, RangeKind, RangeRef};
 use rectangle::Rectangle;
 use row::{Row, RowManager};
+use selection::Selection;
+use style::{BorderStyle, Style, StyleTarget, VAlign};
+use value::{CellError, CellValue};
+use workbook::{Sheet, Workbook};
+use xlsx::SheetData;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 
+const DEFAULT_ROW_COUNT: u32 = 1_048_576;
+const DEFAULT_COLUMN_COUNT: u32 = 16_384;
+
 #[wasm_bindgen]
 pub struct HyperSheet {
-    row_manager: RowManager,
-    col_manager: ColumnManager,
+    workbook: Workbook,
     canvas: web_sys::HtmlCanvasElement,
     h_scroller: web_sys::HtmlElement,
     v_scroller: web_sys::HtmlElement,
     scroller: web_sys::HtmlElement,
     placeholder: web_sys::HtmlElement,
     active_cell: Option<Cell>,
+    properties: HyperSheetProperties,
+    canvas_bounds: Rectangle,
+    pixel_ratio: f64,
+    editor: CellEditor,
+    selection: Selection,
+    dragging: bool,
+    resizing: Option<Resize>,
+    reordering: Option<Reorder>,
+    measurer: RefCell<TextMeasurer>,
+    keymap: KeyMap,
 }
 
 #[wasm_bindgen]
@@ -41,71 +88,57 @@ impl HyperSheet {
         scroller: web_sys::HtmlElement,
         placeholder: web_sys::HtmlElement,
     ) -> Self {
-        let data_model = DataModel::new();
-        let row_manager = RowManager::new();
-        let col_manager = ColumnManager::new();
+        let properties = HyperSheetProperties::default();
+        let sheet = new_sheet("Sheet1", &properties);
+        let editor = match placeholder.parent_element() {
+            Some(parent) => CellEditor::attach(&parent),
+            None => CellEditor::attach(&scroller),
+        };
         let mut instance = Self {
-            row_manager,
-            col_manager,
+            workbook: Workbook::new(sheet),
             canvas,
             h_scroller,
             v_scroller,
             placeholder,
             scroller,
             active_cell: None,
+            properties,
+            canvas_bounds: Rectangle::new(0.0, 0.0, 0.0, 0.0),
+            pixel_ratio: 1.0,
+            editor,
+            selection: Selection::new(),
+            dragging: false,
+            resizing: None,
+            reordering: None,
+            measurer: RefCell::new(TextMeasurer::new()),
+            keymap: KeyMap::default(),
         };
         let mut row = Row::new(3);
         row.set_height(40);
         let mut col = Column::new(4);
         col.set_width(200);
-        instance.row_manager.set_row(row);
-        instance.col_manager.set_column(col);
-        instance.paint();
+        instance.sheet_mut().row_manager.set_row(row);
+        instance.sheet_mut().col_manager.set_column(col);
+        instance.resize();
         instance
     }
 
-    fn get_row_with_idx(&self, idx: u16) -> Option<&Row> {
-        self.row_manager.get_row(idx)
+    fn sheet(&self) -> &Sheet {
+        self.workbook.sheet()
     }
 
-    fn get_col_with_idx(&self, idx: u8) -> Option<&Column> {
-        self.col_manager.get_column(idx)
+    fn sheet_mut(&mut self) -> &mut Sheet {
+        self.workbook.sheet_mut()
     }
 
-    /// Expensive operation to perform..
-    fn get_last_visible_row(&self, row_offset: usize) -> (u16, usize) {
-        let mut row_count = 0;
-        let mut offset = 0;
-        loop {
-            let new_offset = match self.get_row_with_idx(row_count + 1) {
-                Some(row) => offset + row.get_height() as usize,
-                None => offset + 20,
-            };
-            if new_offset >= row_offset {
-                break;
-            }
-            offset = new_offset;
-            row_count += 1;
-        }
-        (row_count, offset)
+    fn get_last_visible_row(&self, row_offset: usize) -> (u32, usize) {
+        let (row_count, offset) = self.sheet().row_manager.get_rows_before(row_offset as f64);
+        (row_count, offset as usize)
     }
 
-    /// Expensive operation to perform..
-    fn get_last_visible_col(&self, col_offset: usize) -> (u8, usize) {
-        let mut col_count = 0;
-        let mut offset = 0;
-        loop {
-            let new_offset = match self.get_col_with_idx(col_count + 1) {
-                Some(col) => offset + col.get_width() as usize,
-                None => offset + 85,
-            };
-            if new_offset >= col_offset {
-                break;
-            }
-            offset = new_offset;
-            col_count += 1;
-        }
-        (col_count, offset)
+    fn get_last_visible_col(&self, col_offset: usize) -> (u32, usize) {
+        let (col_count, offset) = self.sheet().col_manager.get_cols_before(col_offset as f64);
+        (col_count, offset as usize)
     }
 
     pub fn get_last_visible_row_offset(&self, row_offset: usize) -> JsValue {
@@ -118,300 +151,827 @@ impl HyperSheet {
         JsValue::from(offset as f64)
     }
 
-    /// Derive cell dimension with cell indices.
-    fn get_cell_dimension(&self, col_idx: u8, row_idx: u16) -> (u16, u16) {
-        let row = self.row_manager.get_row(row_idx);
-        let col = self.col_manager.get_column(col_idx);
-        let height: u16 = match row {
-            Some(rw) => rw.get_height(),
-            None => 20,
-        };
-        let width: u16 = match col {
-            Some(cl) => cl.get_width(),
-            None => 85,
-        };
-        (width, height)
+    /// Size of the whole grid, excluding headers.
+    fn get_content_bounds(&self) -> Rectangle {
+        let width = self.sheet().col_manager.get_extent();
+        let height = self.sheet().row_manager.get_extent();
+        Rectangle::new(0.0, 0.0, width, height)
     }
 
-    fn get_scroller_bounds(&self) -> Rectangle {
-        let left = self.scroller.scroll_left() as f64;
-        let top = self.scroller.scroll_top() as f64;
-        Rectangle::new(left, top, 560.0, 380.0)
+    pub fn get_content_width(&self) -> JsValue {
+        JsValue::from(self.get_content_bounds().width())
     }
 
-    fn get_canvas_bounds(&self) -> Rectangle {
-        Rectangle::new(0.0, 0.0, 600.0, 400.0)
-    }
-
-    fn move_placeholder_right(&mut self) {
-        let (next_col_idx, next_row_idx, boundary) = match self.active_cell {
-            Some(mut cell) => {
-                let next_col_idx = cell.get_col_idx() + 1;
-                let next_row_idx = cell.get_row_idx();
-                let left = cell.get_boundary().unwrap().right();
-                let top = cell.get_boundary().unwrap().top();
-                let (width, height) = self.get_cell_dimension(next_col_idx, next_row_idx);
-                let boundary = Rectangle::new(left, top, width as f64, height as f64);
-                (next_col_idx, next_row_idx, boundary)
-            }
-            None => {
-                let (width, height) = self.get_cell_dimension(1, 1);
-                (1, 1, Rectangle::new(0.0, 0.0, width as f64, height as f64))
-            }
-        };
-        if boundary.right() <= 20000.0 {
-            let mut cell = Cell::new(next_col_idx, next_row_idx);
-            self.placeholder
-                .style()
-                .set_property("left", &boundary.x_as_px())
-                .unwrap();
-            self.placeholder
-                .style()
-                .set_property("width", &boundary.width_as_px())
-                .unwrap();
-            self.placeholder
-                .style()
-                .set_property("height", &boundary.height_as_px())
-                .unwrap();
-            cell.set_boundary(boundary);
-            self.active_cell = Some(cell);
-            let diff = boundary.right() - self.get_scroller_bounds().right();
-            if diff > 0.0 {
-                let delta_scroll_left = self.get_scroller_bounds().left() as i32 + diff as i32;
-                self.scroller.set_scroll_left(delta_scroll_left);
-                self.h_scroller.set_scroll_left(delta_scroll_left);
-                self.paint();
-            }
-        }
+    pub fn get_content_height(&self) -> JsValue {
+        JsValue::from(self.get_content_bounds().height())
     }
 
-    fn move_placeholder_left(&mut self) {
-        let (next_col_idx, next_row_idx, boundary) = match self.active_cell {
-            Some(mut cell) => {
-                let next_col_idx = cell.get_col_idx() - 1;
-                let next_row_idx = cell.get_row_idx();
-                let origin = cell.get_boundary().unwrap().get_origin();
-                let (width, height) = self.get_cell_dimension(next_col_idx, next_row_idx);
-                let boundary = Rectangle::new(origin.x() - width as f64, origin.y(), width as f64, height as f64);
-                (next_col_idx, next_row_idx, boundary)
-            }
-            None => {
-                let (width, height) = self.get_cell_dimension(1, 1);
-                (1, 1, Rectangle::new(0.0, 0.0, width as f64, height as f64))
-            }
-        };
-        if boundary.left() >= 0.0 {
-            let mut cell = Cell::new(next_col_idx, next_row_idx);
-            self.placeholder
-                .style()
-                .set_property("left", &boundary.x_as_px())
-                .unwrap();
-            self.placeholder
-                .style()
-                .set_property("width", &boundary.width_as_px())
-                .unwrap();
-            self.placeholder
-                .style()
-                .set_property("height", &boundary.height_as_px())
-                .unwrap();
-            cell.set_boundary(boundary);
-            self.active_cell = Some(cell);
-            let diff = boundary.left() - self.get_scroller_bounds().left();
-            if diff < 0.0 {
-                let delta_scroll_left = self.get_scroller_bounds().left() as i32 + diff as i32;
-                self.scroller.set_scroll_left(delta_scroll_left);
-                self.h_scroller.set_scroll_left(delta_scroll_left);
-                self.paint();
-            }
-        }
+    pub fn set_dimensions(&mut self, col_count: u32, row_count: u32) {
+        self.sheet_mut().col_manager.set_col_count(col_count);
+        self.sheet_mut().row_manager.set_row_count(row_count);
+        self.paint();
     }
 
-    fn move_placeholder_top(&mut self) {
-        let (next_col_idx, next_row_idx, boundary) = match self.active_cell {
-            Some(mut cell) => {
-                let next_col_idx = cell.get_col_idx();
-                let next_row_idx = cell.get_row_idx() - 1;
-                let origin = cell.get_boundary().unwrap().get_origin();
-                let (width, height) = self.get_cell_dimension(next_col_idx, next_row_idx);
-                let boundary = Rectangle::new(origin.x(), origin.y() - height as f64, width as f64, height as f64);
-                (next_col_idx, next_row_idx, boundary)
-            }
-            None => {
-                let (width, height) = self.get_cell_dimension(1, 1);
-                (1, 1, Rectangle::new(0.0, 0.0, width as f64, height as f64))
-            }
-        };
-        if boundary.top() >= 0.0 {
-            let mut cell = Cell::new(next_col_idx, next_row_idx);
-            self.placeholder
-                .style()
-                .set_property("top", &boundary.y_as_px())
-                .unwrap();
-            self.placeholder
-                .style()
-                .set_property("width", &boundary.width_as_px())
-                .unwrap();
-            self.placeholder
-                .style()
-                .set_property("height", &boundary.height_as_px())
-                .unwrap();
-            cell.set_boundary(boundary);
-            self.active_cell = Some(cell);
-            let diff = boundary.top() - self.get_scroller_bounds().top();
-            if diff < 0.0 {
-                let delta_scroll_top = self.get_scroller_bounds().top() as i32 + diff as i32;
-                self.scroller.set_scroll_top(delta_scroll_top);
-                self.v_scroller.set_scroll_top(delta_scroll_top);
-                self.paint();
-            }
-        }
+    fn get_scroller_bounds(&self) -> Rectangle {
+        let left = self.scroller.scroll_left() as f64;
+        let top = self.scroller.scroll_top() as f64;
+        let cb = self.get_canvas_bounds();
+        let width = cb.width() - self.get_row_header_width();
+        let height = cb.height() - self.get_column_header_height();
+        Rectangle::new(left, top, width, height)
     }
 
-    fn move_placeholder_down(&mut self) {
-        let (next_col_idx, next_row_idx, boundary) = match self.active_cell {
-            Some(mut cell) => {
-                let next_col_idx = cell.get_col_idx();
-                let next_row_idx = cell.get_row_idx() + 1;
-                let left = cell.get_boundary().unwrap().left();
-                let bottom = cell.get_boundary().unwrap().bottom();
-                let (width, height) = self.get_cell_dimension(next_col_idx, next_row_idx);
-                let boundary = Rectangle::new(left, bottom, width as f64, height as f64);
-                (next_col_idx, next_row_idx, boundary)
-            }
-            None => {
-                let (width, height) = self.get_cell_dimension(1, 1);
-                (1, 1, Rectangle::new(0.0, 0.0, width as f64, height as f64))
-            }
-        };
-        if boundary.bottom() <= 1245560.0 {
-            let mut cell = Cell::new(next_col_idx, next_row_idx);
-            self.placeholder
-                .style()
-                .set_property("top", &boundary.y_as_px())
-                .unwrap();
-            self.placeholder
-                .style()
-                .set_property("width", &boundary.width_as_px())
-                .unwrap();
-            self.placeholder
-                .style()
-                .set_property("height", &boundary.height_as_px())
-                .unwrap();
-            cell.set_boundary(boundary);
-            self.active_cell = Some(cell);
-            let diff = boundary.bottom() - self.get_scroller_bounds().bottom();
-            if diff > 0.0 {
-                let delta_scroll_top = self.get_scroller_bounds().top() as i32 + diff as i32;
-                self.scroller.set_scroll_top(delta_scroll_top);
-                self.v_scroller.set_scroll_top(delta_scroll_top);
-                self.paint();
-            }
-        }
+    fn get_canvas_bounds(&self) -> Rectangle {
+        self.canvas_bounds
+    }
+
+    /// Re-measure the canvas and repaint. Call whenever the layout around the
+    /// canvas changes; its size is expected to be set through CSS. With
+    /// `use_hi_dpi` the backing store is scaled by the device pixel ratio.
+    pub fn resize(&mut self) {
+        let rect = self.canvas.get_bounding_rect();
+        self.canvas_bounds = Rectangle::new(0.0, 0.0, rect.width(), rect.height());
+        self.pixel_ratio = match web_sys::window() {
+            Some(window) if self.properties.use_hi_dpi => window.device_pixel_ratio(),
+            _ => 1.0,
+        };
+        self.canvas.set_width((rect.width() * self.pixel_ratio).round() as u32);
+        self.canvas.set_height((rect.height() * self.pixel_ratio).round() as u32);
+        self.paint();
     }
 
     fn paint(&self) {
-        let top = self.get_scroller_bounds().top();
-        let left = self.get_scroller_bounds().left();
-        let (row_idx, row_offset) = self.get_last_visible_row(top as usize);
-        let (col_idx, col_offset) = self.get_last_visible_col(left as usize);
+        let props = &self.properties;
+        let header_width = self.get_row_header_width();
+        let header_height = self.get_column_header_height();
         let ctx: web_sys::CanvasRenderingContext2d =
             self.canvas.get_context("2d").unwrap().unwrap().dyn_into().unwrap();
         let cb = self.get_canvas_bounds();
+        ctx.set_transform(self.pixel_ratio, 0.0, 0.0, self.pixel_ratio, 0.0, 0.0).unwrap();
         ctx.clear_rect(cb.left(), cb.top(), cb.right(), cb.bottom());
-        ctx.set_fill_style(&"#000000".into());
-        let mut row_offset = row_offset as f64 - top + 20.0;
-        let mut col_offset = col_offset as f64 - left + 40.0;
-        let mut row_idx = row_idx + 1;
-        let mut col_idx = col_idx + 1;
-        loop {
-            let height = match self.row_manager.get_row(row_idx) {
-                Some(rw) => rw.get_height(),
-                None => 20,
-            };
+        ctx.set_fill_style(&props.background_color.as_str().into());
+        ctx.fill_rect(header_width, header_height, cb.right() - header_width, cb.bottom() - header_height);
+        ctx.set_text_baseline("middle");
+
+        // Offsets at which hidden rows and columns sit, marked in the headers.
+        let (row_bands, hidden_rows) = self.get_bands(Axis::Rows);
+        let (col_bands, hidden_cols) = self.get_bands(Axis::Columns);
+        let visible_rows: Vec<_> = row_bands.iter().flat_map(|band| band.lines.iter()).collect();
+        let visible_cols: Vec<_> = col_bands.iter().flat_map(|band| band.lines.iter()).collect();
+
+        let styles = &self.sheet().data_model.styles;
+        let merges = &self.sheet().data_model.merges;
+        for row_band in row_bands.iter() {
+            for col_band in col_bands.iter() {
+                ctx.save();
+                clip_bands(&ctx, row_band, col_band);
+                for (row_idx, row_offset, height) in row_band.lines.iter() {
+                    for (col_idx, col_offset, width) in col_band.lines.iter() {
+                        if merges.get(*col_idx, *row_idx).is_some() {
+                            continue;
+                        }
+                        let style = styles.resolve(*col_idx, *row_idx);
+                        let rect = Rectangle::new(*col_offset, *row_offset, *width as f64, *height as f64);
+                        if let Some(background) = &style.background {
+                            ctx.set_fill_style(&background.as_str().into());
+                            ctx.fill_rect(rect.left(), rect.top(), rect.width(), rect.height());
+                        }
+                        let (text, color) = self.get_display_text(*col_idx, *row_idx);
+                        if !text.is_empty() {
+                            self.draw_cell_text(&ctx, &text, color, rect, &style);
+                        }
+                    }
+                }
+                ctx.restore();
+            }
+        }
 
-            let new_offset = height as f64 + row_offset;
-            if new_offset <= cb.bottom() {
+        ctx.set_line_width(props.grid_lines_hwidth as f64);
+        ctx.set_stroke_style(&props.grid_lines_hcolor.as_str().into());
+        if props.grid_lines_h {
+            for (_, row_offset, height) in visible_rows.iter() {
                 ctx.begin_path();
-                ctx.rect(0.0, row_offset, 40.0, height as f64);
+                ctx.move_to(header_width, row_offset + *height as f64);
+                ctx.line_to(cb.right(), row_offset + *height as f64);
                 ctx.stroke();
-                ctx.fill_text(&row_idx.to_string(), 5.0, row_offset + 5.0 + (height / 2) as f64)
-                    .unwrap();
+            }
+        }
+        ctx.set_line_width(props.grid_lines_vwidth as f64);
+        ctx.set_stroke_style(&props.grid_lines_vcolor.as_str().into());
+        if props.grid_lines_v {
+            for (_, col_offset, width) in visible_cols.iter() {
                 ctx.begin_path();
-                ctx.set_line_width(0.3);
-                ctx.set_stroke_style(&"#000000".into());
-                ctx.move_to(40.0, new_offset);
-                ctx.line_to(cb.right(), new_offset);
+                ctx.move_to(col_offset + *width as f64, header_height);
+                ctx.line_to(col_offset + *width as f64, cb.bottom());
                 ctx.stroke();
-                row_idx += 1;
-                row_offset = new_offset;
-            } else {
-                break;
+            }
+        }
+        // Merges are drawn over the grid lines within them.
+        if !merges.is_empty() {
+            for row_band in row_bands.iter() {
+                for col_band in col_bands.iter() {
+                    ctx.save();
+                    clip_bands(&ctx, row_band, col_band);
+                    for range in merges.get_ranges() {
+                        let bounds = self.get_range_bounds(range);
+                        let rect = Rectangle::new(
+                            bounds.left() + col_band.shift,
+                            bounds.top() + row_band.shift,
+                            bounds.width(),
+                            bounds.height(),
+                        );
+                        if rect.bottom() < row_band.start || rect.top() > row_band.end {
+                            continue;
+                        }
+                        let runs = self.get_column_runs(range, col_band);
+                        if runs.is_empty() {
+                            continue;
+                        }
+                        let style = styles.resolve(range.start.col, range.start.row);
+                        let background = style.background.as_deref().unwrap_or(&props.background_color);
+                        let inset_x = props.grid_lines_vwidth as f64 / 2.0;
+                        let inset_y = props.grid_lines_hwidth as f64 / 2.0;
+                        ctx.set_fill_style(&background.into());
+                        for (x, right) in runs {
+                            let width = right - x - 2.0 * inset_x;
+                            ctx.fill_rect(x + inset_x, rect.top() + inset_y, width, rect.height() - 2.0 * inset_y);
+                        }
+                        let (text, color) = self.get_display_text(range.start.col, range.start.row);
+                        if !text.is_empty() {
+                            self.draw_cell_text(&ctx, &text, color, rect, &style);
+                        }
+                        draw_borders(&ctx, rect, &style);
+                    }
+                    ctx.restore();
+                }
+            }
+        }
+        if !styles.is_empty() {
+            for row_band in row_bands.iter() {
+                for col_band in col_bands.iter() {
+                    ctx.save();
+                    clip_bands(&ctx, row_band, col_band);
+                    for (row_idx, row_offset, height) in row_band.lines.iter() {
+                        for (col_idx, col_offset, width) in col_band.lines.iter() {
+                            if merges.get(*col_idx, *row_idx).is_some() {
+                                continue;
+                            }
+                            let style = styles.resolve(*col_idx, *row_idx);
+                            let rect = Rectangle::new(*col_offset, *row_offset, *width as f64, *height as f64);
+                            draw_borders(&ctx, rect, &style);
+                        }
+                    }
+                    ctx.restore();
+                }
             }
         }
 
-        loop {
-            let width = match self.col_manager.get_column(col_idx) {
-                Some(rw) => rw.get_width(),
-                None => 85,
-            };
+        if !self.selection.is_single_cell() {
+            ctx.set_fill_style(&props.selection_region_overlay_color.as_str().into());
+            ctx.set_stroke_style(&props.selection_region_outline_color.as_str().into());
+            ctx.set_line_width(1.0);
+            for row_band in row_bands.iter() {
+                for col_band in col_bands.iter() {
+                    ctx.save();
+                    clip_bands(&ctx, row_band, col_band);
+                    for range in self.selection.get_ranges() {
+                        let end_row = range.end.row.min(self.sheet().row_manager.get_row_count());
+                        let top = self.sheet().row_manager.get_offset(range.start.row);
+                        let bottom = self.sheet().row_manager.get_offset(end_row.saturating_add(1));
+                        let y = top + row_band.shift;
+                        for (x, right) in self.get_column_runs(range, col_band) {
+                            ctx.fill_rect(x, y, right - x, bottom - top);
+                            ctx.stroke_rect(x + 0.5, y + 0.5, right - x - 1.0, bottom - top - 1.0);
+                        }
+                    }
+                    ctx.restore();
+                }
+            }
+        }
 
-            let new_offset = width as f64 + col_offset;
+        let (fixed_cols, fixed_rows) = self.get_fixed_counts();
+        if fixed_rows > 0 {
+            let y = row_bands[0].end;
+            ctx.set_line_width(props.fixed_lines_hwidth as f64);
+            ctx.set_stroke_style(&props.fixed_lines_hcolor.as_str().into());
             ctx.begin_path();
-            ctx.rect(col_offset, 0.0, width as f64, 20.0);
+            ctx.move_to(header_width, y);
+            ctx.line_to(cb.right(), y);
             ctx.stroke();
-            ctx.fill_text(&col_idx.to_string(), col_offset + (width / 2) as f64, 15.0)
-                .unwrap();
+        }
+        if fixed_cols > 0 {
+            let x = col_bands[0].end;
+            ctx.set_line_width(props.fixed_lines_vwidth as f64);
+            ctx.set_stroke_style(&props.fixed_lines_vcolor.as_str().into());
             ctx.begin_path();
-            ctx.set_line_width(0.3);
-            ctx.set_stroke_style(&"#000000".into());
-            ctx.move_to(new_offset, 20.0);
-            ctx.line_to(new_offset, cb.bottom());
+            ctx.move_to(x, header_height);
+            ctx.line_to(x, cb.bottom());
             ctx.stroke();
-            if new_offset <= cb.right() {
-                col_idx += 1;
-                col_offset = new_offset;
-            } else {
-                break;
+        }
+
+        ctx.set_line_width(props.grid_lines_hwidth as f64);
+        ctx.set_stroke_style(&props.grid_lines_hcolor.as_str().into());
+        if header_width > 0.0 {
+            ctx.set_fill_style(&props.row_header_background_color.as_str().into());
+            ctx.fill_rect(0.0, header_height, header_width, cb.bottom() - header_height);
+            for band in row_bands.iter() {
+                ctx.save();
+                ctx.begin_path();
+                ctx.rect(0.0, band.start, header_width, band.end - band.start);
+                ctx.clip();
+                for (row_idx, row_offset, height) in band.lines.iter() {
+                    let rect = Rectangle::new(0.0, *row_offset, header_width, *height as f64);
+                    if props.grid_lines_row_header {
+                        ctx.stroke_rect(rect.left(), rect.top(), rect.width(), rect.height());
+                    }
+                    if self.selection.spans_row(*row_idx) {
+                        ctx.set_fill_style(&props.row_header_background_selection_color.as_str().into());
+                        ctx.fill_rect(rect.left(), rect.top(), rect.width(), rect.height());
+                        ctx.set_fill_style(&props.row_header_foreground_selection_color.as_str().into());
+                        ctx.set_font(&props.row_header_foreground_selection_font);
+                    } else {
+                        ctx.set_fill_style(&props.row_header_color.as_str().into());
+                        ctx.set_font(&props.row_header_font);
+                    }
+                    draw_text(&ctx, &row_idx.to_string(), rect, "left", props.cell_padding as f64);
+                }
+                ctx.restore();
             }
+            ctx.set_stroke_style(&props.row_header_color.as_str().into());
+            for offset in hidden_rows.iter() {
+                draw_hidden_marker(&ctx, (0.0, *offset), (header_width, *offset));
+            }
+        }
+        if header_height > 0.0 {
+            ctx.set_fill_style(&props.column_header_background_color.as_str().into());
+            ctx.fill_rect(header_width, 0.0, cb.right() - header_width, header_height);
+            for band in col_bands.iter() {
+                ctx.save();
+                ctx.begin_path();
+                ctx.rect(band.start, 0.0, band.end - band.start, header_height);
+                ctx.clip();
+                for (col_idx, col_offset, width) in band.lines.iter() {
+                    let rect = Rectangle::new(*col_offset, 0.0, *width as f64, header_height);
+                    if props.grid_lines_column_header {
+                        ctx.stroke_rect(rect.left(), rect.top(), rect.width(), rect.height());
+                    }
+                    if self.selection.spans_column(*col_idx) {
+                        ctx.set_fill_style(&props.column_header_background_selection_color.as_str().into());
+                        ctx.fill_rect(rect.left(), rect.top(), rect.width(), rect.height());
+                        ctx.set_fill_style(&props.column_header_foreground_selection_color.as_str().into());
+                        ctx.set_font(&props.column_header_foreground_selection_font);
+                    } else {
+                        ctx.set_fill_style(&props.column_header_color.as_str().into());
+                        ctx.set_font(&props.column_header_font);
+                    }
+                    let text = self.get_column_header_text(*col_idx);
+                    draw_text(&ctx, &text, rect, &props.column_header_halign, props.cell_padding as f64);
+                }
+                ctx.restore();
+            }
+            ctx.set_stroke_style(&props.column_header_color.as_str().into());
+            for offset in hidden_cols.iter() {
+                draw_hidden_marker(&ctx, (*offset, 0.0), (*offset, header_height));
+            }
+        }
+
+        ctx.set_fill_style(&props.column_header_background_color.as_str().into());
+        ctx.fill_rect(0.0, 0.0, header_width, header_height);
+
+        if let Some(reorder) = &self.reordering {
+            let width = self.sheet().col_manager.get_width(reorder.col_idx) as f64;
+            let x = reorder.left + reorder.x - reorder.origin;
+            ctx.set_global_alpha(0.6);
+            ctx.set_fill_style(&props.column_header_background_selection_color.as_str().into());
+            ctx.fill_rect(x, 0.0, width, header_height);
+            ctx.set_fill_style(&props.selection_region_overlay_color.as_str().into());
+            ctx.fill_rect(x, header_height, width, cb.bottom() - header_height);
+            ctx.set_global_alpha(1.0);
+            ctx.set_fill_style(&props.column_header_foreground_selection_color.as_str().into());
+            ctx.set_font(&props.column_header_foreground_selection_font);
+            let rect = Rectangle::new(x, 0.0, width, header_height);
+            let text = self.get_column_header_text(reorder.col_idx);
+            draw_text(&ctx, &text, rect, &props.column_header_halign, props.cell_padding as f64);
+            let (_, drop_x) = self.get_drop_position(reorder.x);
+            ctx.set_line_width(props.fixed_lines_vwidth as f64);
+            ctx.set_stroke_style(&props.fixed_lines_vcolor.as_str().into());
+            ctx.begin_path();
+            ctx.move_to(drop_x, 0.0);
+            ctx.line_to(drop_x, cb.bottom());
+            ctx.stroke();
+        }
+    }
+
+    /// Text of a cell as shown, through its number format, with the colour
+    /// the format gives it if any. Invalid formats show the plain value.
+    fn get_display_text(&self, col_idx: u32, row_idx: u32) -> (String, Option<&'static str>) {
+        let model = &self.sheet().data_model;
+        let value = model.get_value(col_idx, row_idx);
+        match model.styles.get_number_format(col_idx, row_idx) {
+            Some(format) => format.format(value),
+            None => (value.to_string(), None),
+        }
+    }
+
+    /// Label of a column header: its letters with the `header` format,
+    /// otherwise the column number through `column_header_format`.
+    fn get_column_header_text(&self, col_idx: u32) -> String {
+        match self.properties.column_header_format.as_str() {
+            "header" => reference::column_name(col_idx),
+            code => match NumberFormat::parse(code) {
+                Ok(format) => format.format(&CellValue::Number(col_idx as f64)).0,
+                Err(_) => col_idx.to_string(),
+            },
         }
+    }
 
+    /// Draw a cell's text as its style lays it out, within the cell: aligned,
+    /// indented, and with `wrap` broken into lines. `color` overrides the
+    /// style's.
+    fn draw_cell_text(
+        &self,
+        ctx: &web_sys::CanvasRenderingContext2d,
+        text: &str,
+        color: Option<&str>,
+        rect: Rectangle,
+        style: &Style,
+    ) {
+        let props = &self.properties;
+        let font = style.get_font(&props.font);
+        let padding = props.cell_padding as f64 + style.indent.unwrap_or(0) as f64 * style::INDENT_WIDTH;
+        let lines = if style.wrap == Some(true) {
+            style::wrap_words(text, rect.width() - 2.0 * padding, |line| self.measure_text(&font, line))
+        } else {
+            vec![text.to_string()]
+        };
+        let line_height = style.get_font_size(&props.font) * 1.2;
+        let height = line_height * lines.len() as f64;
+        let top = match style.valign.unwrap_or(VAlign::Middle) {
+            VAlign::Top => rect.top(),
+            VAlign::Middle => rect.top() + (rect.height() - height) / 2.0,
+            VAlign::Bottom => rect.bottom() - height,
+        };
+        let halign = style.halign.map(|halign| halign.as_str()).unwrap_or(&props.halign);
+        ctx.set_font(&font);
+        ctx.set_fill_style(&color.or(style.color.as_deref()).unwrap_or(&props.color).into());
+        ctx.save();
         ctx.begin_path();
-        ctx.set_fill_style(&"#dbdbdb".into());
-        ctx.fill_rect(0.0, 0.0, 40.0, 20.0);
+        ctx.rect(rect.left(), rect.top(), rect.width(), rect.height());
+        ctx.clip();
+        for (i, line) in lines.iter().enumerate() {
+            let line_rect = Rectangle::new(rect.left(), top + i as f64 * line_height, rect.width(), line_height);
+            draw_text(ctx, line, line_rect, halign, padding);
+        }
+        ctx.restore();
+    }
+
+    /// Number of frozen columns and rows, limited to the grid.
+    fn get_fixed_counts(&self) -> (u32, u32) {
+        let sheet = self.sheet();
+        (
+            self.properties.fixed_column_count.min(sheet.col_manager.get_col_count()),
+            self.properties.fixed_row_count.min(sheet.row_manager.get_row_count()),
+        )
+    }
+
+    /// Width and height taken up by the frozen columns and rows.
+    fn get_frozen_extent(&self) -> (f64, f64) {
+        let (fixed_cols, fixed_rows) = self.get_fixed_counts();
+        (
+            self.sheet().col_manager.get_offset(fixed_cols + 1),
+            self.sheet().row_manager.get_offset(fixed_rows + 1),
+        )
     }
 
-    pub fn on_right_arrow_keydown(&mut self, _: web_sys::KeyboardEvent) {
-        self.move_placeholder_right();
+    /// Lines to draw along an axis, as the band of frozen lines followed by
+    /// the band of scrolled ones, with the canvas offsets of hidden lines.
+    fn get_bands(&self, axis: Axis) -> (Vec<Band>, Vec<f64>) {
+        let sheet = self.sheet();
+        let view = self.get_scroller_bounds();
+        let cb = self.get_canvas_bounds();
+        let (fixed_cols, fixed_rows) = self.get_fixed_counts();
+        let (count, fixed, start, scroll, end) = match axis {
+            Axis::Rows => (
+                sheet.row_manager.get_row_count(),
+                fixed_rows,
+                self.get_column_header_height(),
+                view.top(),
+                cb.bottom(),
+            ),
+            Axis::Columns => (
+                sheet.col_manager.get_col_count(),
+                fixed_cols,
+                self.get_row_header_width(),
+                view.left(),
+                cb.right(),
+            ),
+        };
+        // Columns may be shown out of order, so lines go by position.
+        let get_line = |pos: u32| match axis {
+            Axis::Rows => pos,
+            Axis::Columns => sheet.col_manager.get_column_at(pos),
+        };
+        let get_size = |idx: u32| match axis {
+            Axis::Rows => sheet.row_manager.get_height(idx),
+            Axis::Columns => sheet.col_manager.get_width(idx),
+        };
+        let get_offset = |idx: u32| match axis {
+            Axis::Rows => sheet.row_manager.get_offset(idx),
+            Axis::Columns => sheet.col_manager.get_offset(idx),
+        };
+        let get_before = |offset: f64| match axis {
+            Axis::Rows => sheet.row_manager.get_rows_before(offset).0,
+            Axis::Columns => sheet.col_manager.get_cols_before(offset).0,
+        };
+        let frozen = get_offset(fixed + 1);
+        let before = get_before(frozen + scroll);
+        let first = (before + 1).max(fixed + 1);
+        let split = (start + frozen).min(end);
+        let mut bands = vec![
+            Band {
+                start,
+                end: split,
+                shift: start,
+                lines: vec![],
+            },
+            Band {
+                start: split,
+                end,
+                shift: start - scroll,
+                lines: vec![],
+            },
+        ];
+        let mut hidden = vec![];
+        for (band, from, last) in [(0, 1, fixed), (1, first, count)].iter() {
+            let band = &mut bands[*band];
+            let mut pos = *from;
+            let mut offset = get_offset(pos) + band.shift;
+            while pos <= *last && offset < band.end {
+                let idx = get_line(pos);
+                let size = get_size(idx);
+                if size == 0 {
+                    if hidden.last() != Some(&offset) {
+                        hidden.push(offset);
+                    }
+                    // Sizes are whole pixels, so the lines ending at or before
+                    // this one's offset are the hidden run it starts.
+                    pos = get_before(offset - band.shift + 0.5) + 1;
+                } else {
+                    band.lines.push((idx, offset, size));
+                    offset += size as f64;
+                    pos += 1;
+                }
+            }
+        }
+        (bands, hidden)
     }
 
-    pub fn on_left_arrow_keydown(&mut self, _: web_sys::KeyboardEvent) {
-        self.move_placeholder_left();
+    /// Width of the row header, `row_header_width` or with
+    /// `row_number_autosizing` wide enough for the widest row number in view.
+    fn get_row_header_width(&self) -> f64 {
+        let props = &self.properties;
+        if !props.row_header_numbers {
+            return 0.0;
+        }
+        let width = props.row_header_width as f64;
+        if !props.row_number_autosizing {
+            return width;
+        }
+        let rows = &self.sheet().row_manager;
+        let (_, fixed_rows) = self.get_fixed_counts();
+        let scroll = self.scroller.scroll_top() as f64;
+        let bottom = rows.get_offset(fixed_rows + 1) + scroll + self.get_canvas_bounds().height();
+        let (before, _) = rows.get_rows_before(bottom);
+        let text = (before + 1).min(rows.get_row_count()).to_string();
+        let widest = [&props.row_header_font, &props.row_header_foreground_selection_font]
+            .iter()
+            .map(|font| self.measure_text(font, &text))
+            .fold(0.0, f64::max);
+        width.max((widest + 2.0 * props.cell_padding as f64).ceil())
     }
 
-    pub fn on_up_arrow_keydown(&mut self, _: web_sys::KeyboardEvent) {
-        self.move_placeholder_top();
+    fn get_column_header_height(&self) -> f64 {
+        if self.properties.show_header_row {
+            self.properties.column_header_height as f64
+        } else {
+            0.0
+        }
     }
 
-    pub fn on_down_arrow_keydown(&mut self, _: web_sys::KeyboardEvent) {
-        self.move_placeholder_down();
+    /// Push sizing properties down to the row and column managers.
+    fn apply_properties(&mut self) {
+        let default_height = self.properties.default_row_height as u16;
+        let default_width = self.properties.default_column_width as u16;
+        for sheet in self.workbook.get_sheets_mut() {
+            if sheet.row_manager.get_default_height() != default_height {
+                sheet.row_manager.set_default_height(default_height);
+            }
+            if sheet.col_manager.get_default_width() != default_width {
+                sheet.col_manager.set_default_width(default_width);
+            }
+            sheet.history.set_limit(self.properties.undo_limit as usize);
+        }
     }
 
-    pub fn on_h_scroll(&mut self, _: web_sys::Event) {
-        self.scroller.set_scroll_left(self.h_scroller.scroll_left());
+    /// Override any subset of the sheet properties from a JS object using the
+    /// camelCase property names, e.g. `{ defaultRowHeight: 24 }`, and repaint.
+    pub fn set_properties(&mut self, properties: JsValue) -> Result<(), JsValue> {
+        let overrides: serde_json::Value = properties
+            .into_serde()
+            .map_err(|err| JsValue::from(err.to_string()))?;
+        self.properties
+            .merge(overrides)
+            .map_err(|err| JsValue::from(err.to_string()))?;
+        self.apply_properties();
+        let (col_idx, row_idx) = self.selection.get_active();
+        self.activate_cell(col_idx, row_idx);
+        self.resize();
+        Ok(())
+    }
+
+    pub fn get_properties(&self) -> JsValue {
+        JsValue::from_serde(&self.properties).unwrap()
+    }
+
+    /// Set a cell from JS. Strings starting with `=` are stored as formulas.
+    pub fn set_value(&mut self, col_idx: u32, row_idx: u32, value: JsValue) {
+        let content = match CellValue::from_js(&value) {
+            CellValue::Text(ref text) if text.starts_with('=') => CellSnapshot::formula(text),
+            value => CellSnapshot::value(value),
+        };
+        self.set_cell_content(col_idx, row_idx, content);
+        self.refresh();
+    }
+
+    pub fn set_formula(&mut self, col_idx: u32, row_idx: u32, formula: &str) {
+        self.set_cell_content(col_idx, row_idx, CellSnapshot::formula(formula));
+        self.refresh();
+    }
+
+    pub fn get_formula(&self, col_idx: u32, row_idx: u32) -> Option<String> {
+        self.sheet().data_model.get_formula(col_idx, row_idx).cloned()
+    }
+
+    /// Register a formula function implemented in JS. The callback receives one
+    /// argument per formula argument, ranges arriving as arrays of rows, and may
+    /// return an error string such as `#N/A` to signal an error.
+    pub fn register_function(&mut self, name: &str, callback: js_sys::Function) {
+        let function = move |args: &[Operand]| {
+            let js_args = js_sys::Array::new();
+            for arg in args {
+                match arg {
+                    Operand::Value(value) => js_args.push(&value.to_js()),
+                    Operand::Range(rows) => {
+                        let js_rows = js_sys::Array::new();
+                        for row in rows {
+                            js_rows.push(&row.iter().map(|value| value.to_js()).collect::<js_sys::Array>());
+                        }
+                        js_args.push(&js_rows)
+                    }
+                };
+            }
+            match callback.apply(&JsValue::NULL, &js_args) {
+                Ok(result) => match CellValue::from_js(&result) {
+                    CellValue::Text(text) => match CellError::parse(&text) {
+                        Some(error) => CellValue::Error(error),
+                        None => CellValue::Text(text),
+                    },
+                    value => value,
+                },
+                Err(_) => CellValue::Error(CellError::Value),
+            }
+        };
+        self.workbook.register_function(name, Rc::new(function));
         self.paint();
     }
 
-    pub fn on_v_scroll(&mut self, _: web_sys::Event) {
-        self.scroller.set_scroll_top(self.v_scroller.scroll_top());
+    pub fn get_value(&self, col_idx: u32, row_idx: u32) -> JsValue {
+        self.sheet().data_model.get_value(col_idx, row_idx).to_js()
+    }
+
+    /// Text of a cell as painted, after its number format.
+    pub fn get_text(&self, col_idx: u32, row_idx: u32) -> String {
+        self.get_display_text(col_idx, row_idx).0
+    }
+
+    /// Make the cell named by a reference such as `B12` active and scroll it
+    /// into the top-left corner. A range activates its top-left cell. R1C1
+    /// references such as `R[1]C` are relative to the active cell.
+    pub fn go_to(&mut self, reference: &str) -> bool {
+        let range = match RangeRef::parse_any(reference, self.selection.get_active()) {
+            Some(range) => range,
+            None => return false,
+        };
+        let (col_idx, row_idx) = (range.start.col, range.start.row);
+        if col_idx > self.sheet().col_manager.get_col_count() || row_idx > self.sheet().row_manager.get_row_count() {
+            return false;
+        }
+        self.selection.select_cell(col_idx, row_idx, false);
+        if !range.is_cell() {
+            self.selection.extend_to(range.end.col, range.end.row);
+        }
+        let (frozen_width, frozen_height) = self.get_frozen_extent();
+        let cell = self.get_cell_bounds(col_idx, row_idx);
+        let left = cell.left() - frozen_width;
+        let top = cell.top() - frozen_height;
+        self.scroll_to(left.max(0.0), top.max(0.0));
+        self.activate_cell(col_idx, row_idx);
         self.paint();
+        true
     }
 
-    pub fn on_click(&mut self, event: web_sys::MouseEvent) {
-        let (row_idx, row_offset) = self.get_last_visible_row(event.offset_y() as usize);
-        let (col_idx, col_offset) = self.get_last_visible_col(event.offset_x() as usize);
-        let mut cell = Cell::new(col_idx + 1, row_idx + 1);
-        let (width, height) = self.get_cell_dimension(col_idx + 1, row_idx + 1);
-        let boundary = Rectangle::new(col_offset as f64, row_offset as f64, width as f64, height as f64);
+    // The arrow handlers are kept for hosts that do not forward every key to
+    // `on_keydown`; hosts that do should not wire them as well.
+    pub fn on_right_arrow_keydown(&mut self, event: web_sys::KeyboardEvent) {
+        if !self.editor.is_editing() {
+            self.perform(Action::Move {
+                direction: Direction::Right,
+                stride: Stride::Cell,
+                extend: event.shift_key(),
+            });
+        }
+    }
+
+    pub fn on_left_arrow_keydown(&mut self, event: web_sys::KeyboardEvent) {
+        if !self.editor.is_editing() {
+            self.perform(Action::Move {
+                direction: Direction::Left,
+                stride: Stride::Cell,
+                extend: event.shift_key(),
+            });
+        }
+    }
+
+    pub fn on_up_arrow_keydown(&mut self, event: web_sys::KeyboardEvent) {
+        if !self.editor.is_editing() {
+            self.perform(Action::Move {
+                direction: Direction::Up,
+                stride: Stride::Cell,
+                extend: event.shift_key(),
+            });
+        }
+    }
+
+    pub fn on_down_arrow_keydown(&mut self, event: web_sys::KeyboardEvent) {
+        if !self.editor.is_editing() {
+            self.perform(Action::Move {
+                direction: Direction::Down,
+                stride: Stride::Cell,
+                extend: event.shift_key(),
+            });
+        }
+    }
+
+    /// Carry out a key map action. Returns whether it did anything.
+    fn perform(&mut self, action: Action) -> bool {
+        let col_count = self.sheet().col_manager.get_col_count();
+        let row_count = self.sheet().row_manager.get_row_count();
+        match action {
+            Action::Move {
+                direction,
+                stride,
+                extend,
+            } => {
+                let from = if extend {
+                    self.selection.get_focus()
+                } else {
+                    self.selection.get_active()
+                };
+                let (col_idx, row_idx) = self.get_move_target(from, direction, stride);
+                if stride == Stride::Page {
+                    let view = self.get_scroller_bounds();
+                    let target = self.get_cell_bounds(col_idx, row_idx);
+                    let origin = self.get_cell_bounds(from.0, from.1);
+                    let left = view.left() + target.left() - origin.left();
+                    let top = view.top() + target.top() - origin.top();
+                    self.scroll_to(left.max(0.0), top.max(0.0));
+                }
+                self.move_selection(col_idx, row_idx, extend);
+            }
+            Action::GoToStart { extend } => self.move_selection(1, 1, extend),
+            Action::GoToEnd { extend } => {
+                let (col_idx, row_idx) = self.sheet().data_model.get_data_extent();
+                self.move_selection(col_idx.max(1), row_idx.max(1), extend);
+            }
+            Action::Advance(direction) => {
+                if self.selection.is_single_cell() {
+                    let (col_idx, row_idx) = self.get_move_target(self.selection.get_active(), direction, Stride::Cell);
+                    self.selection.select_cell(col_idx, row_idx, false);
+                } else {
+                    let (delta_col, delta_row) = direction.delta();
+                    self.selection.advance(delta_col, delta_row, col_count, row_count);
+                }
+                let (col_idx, row_idx) = self.selection.get_active();
+                self.scroll_into_view(col_idx, row_idx);
+                self.activate_cell(col_idx, row_idx);
+                self.paint();
+            }
+            Action::SelectAll => {
+                self.selection.select_column(1, false);
+                self.selection.extend_to(col_count, 1);
+                self.activate_cell(1, 1);
+                self.paint();
+            }
+            Action::Edit => return self.start_editing(None),
+            Action::Undo => return self.undo(),
+            Action::Redo => return self.redo(),
+        }
+        true
+    }
+
+    /// Cell a move from `from` ends on. Steps leave a merge from its far side,
+    /// and a move onto a merge ends on its anchor.
+    fn get_move_target(&self, from: (u32, u32), direction: Direction, stride: Stride) -> (u32, u32) {
+        let col_count = self.sheet().col_manager.get_col_count();
+        let row_count = self.sheet().row_manager.get_row_count();
+        let (delta_col, delta_row) = direction.delta();
+        let merges = &self.sheet().data_model.merges;
+        let edge = match merges.get(from.0, from.1) {
+            Some(range) => match direction {
+                Direction::Left => (self.get_edge_column(range, false), from.1),
+                Direction::Right => (self.get_edge_column(range, true), from.1),
+                Direction::Up => (from.0, range.start.row),
+                Direction::Down => (from.0, range.end.row),
+            },
+            None => from,
+        };
+        let step = |distance: i64| {
+            (
+                self.step_visible(Axis::Columns, edge.0, delta_col * distance),
+                self.step_visible(Axis::Rows, edge.1, delta_row * distance),
+            )
+        };
+        let (col_idx, row_idx) = match stride {
+            Stride::Cell => step(1),
+            Stride::DataEdge => {
+                self.sheet().data_model
+                    .get_data_edge(from.0, from.1, delta_col, delta_row, col_count, row_count)
+            }
+            Stride::Page => {
+                let (page_cols, page_rows) = self.get_page_size();
+                let (col_idx, _) = step(page_cols as i64);
+                let (_, row_idx) = step(page_rows as i64);
+                (col_idx, row_idx)
+            }
+            Stride::Line => {
+                let (max_col, max_row) = self.sheet().data_model.get_data_extent();
+                match direction {
+                    Direction::Left => (1, from.1),
+                    Direction::Right => (max_col.max(1), from.1),
+                    Direction::Up => (from.0, 1),
+                    Direction::Down => (from.0, max_row.max(1)),
+                }
+            }
+        };
+        merges.get_anchor(col_idx, row_idx)
+    }
+
+    /// First or last column of a range in the order shown.
+    fn get_edge_column(&self, range: &RangeRef, last: bool) -> u32 {
+        let cols = &self.sheet().col_manager;
+        let positions = (range.start.col..=range.end.col).map(|col_idx| cols.get_position(col_idx));
+        let position = if last { positions.max() } else { positions.min() };
+        cols.get_column_at(position.unwrap_or(1))
+    }
+
+    /// Index `delta` shown lines away from `idx` along an axis, skipping
+    /// hidden ones and stopping at the last shown line before an edge.
+    fn step_visible(&self, axis: Axis, idx: u32, delta: i64) -> u32 {
+        let sheet = self.sheet();
+        match axis {
+            Axis::Rows => sheet.row_manager.step_visible(idx, delta),
+            Axis::Columns => sheet.col_manager.step_visible(idx, delta),
+        }
+    }
+
+    /// Number of scrolled columns and rows fully visible, at least one each.
+    fn get_page_size(&self) -> (u32, u32) {
+        let view = self.get_scroller_bounds();
+        let (frozen_width, frozen_height) = self.get_frozen_extent();
+        let (first_col, _) = self.sheet().col_manager.get_cols_before(view.left() + frozen_width + 1.0);
+        let (last_col, _) = self.sheet().col_manager.get_cols_before(view.right() + 1.0);
+        let (first_row, _) = self.sheet().row_manager.get_rows_before(view.top() + frozen_height + 1.0);
+        let (last_row, _) = self.sheet().row_manager.get_rows_before(view.bottom() + 1.0);
+        ((last_col - first_col).max(1), (last_row - first_row).max(1))
+    }
+
+    /// Select the given cell, or with `extend` stretch the selection to it,
+    /// and bring it into view.
+    fn move_selection(&mut self, col_idx: u32, row_idx: u32, extend: bool) {
+        if extend {
+            self.selection.extend_to(col_idx, row_idx);
+        } else {
+            self.selection.select_cell(col_idx, row_idx, false);
+        }
+        self.scroll_into_view(col_idx, row_idx);
+        let (active_col, active_row) = self.selection.get_active();
+        self.activate_cell(active_col, active_row);
+        self.paint();
+    }
+
+    /// Make the given cell the active one and move the placeholder over it.
+    fn activate_cell(&mut self, col_idx: u32, row_idx: u32) {
+        let boundary = self.get_placeholder_bounds(col_idx, row_idx);
         self.placeholder
             .style()
             .set_property("top", &boundary.y_as_px())
@@ -428,7 +988,1745 @@ impl HyperSheet {
             .style()
             .set_property("height", &boundary.height_as_px())
             .unwrap();
+        let mut cell = Cell::new(col_idx, row_idx);
         cell.set_boundary(boundary);
         self.active_cell = Some(cell);
     }
+
+    /// Where the placeholder goes over a cell, in the scroller's coordinates.
+    /// Frozen cells stay put on screen, so they move along with the scroll.
+    fn get_placeholder_bounds(&self, col_idx: u32, row_idx: u32) -> Rectangle {
+        let cell = self.get_cell_bounds(col_idx, row_idx);
+        let view = self.get_scroller_bounds();
+        let (fixed_cols, fixed_rows) = self.get_fixed_counts();
+        let col_pos = self.sheet().col_manager.get_position(col_idx);
+        let x = if col_pos <= fixed_cols { cell.left() + view.left() } else { cell.left() };
+        let y = if row_idx <= fixed_rows { cell.top() + view.top() } else { cell.top() };
+        Rectangle::new(x, y, cell.width(), cell.height())
+    }
+
+    /// Scroll just enough for the given cell to be fully visible past the
+    /// frozen rows and columns. Frozen cells are always visible.
+    fn scroll_into_view(&mut self, col_idx: u32, row_idx: u32) {
+        let cell = self.get_cell_bounds(col_idx, row_idx);
+        let view = self.get_scroller_bounds();
+        let (fixed_cols, fixed_rows) = self.get_fixed_counts();
+        let (frozen_width, frozen_height) = self.get_frozen_extent();
+        let mut left = view.left();
+        let mut top = view.top();
+        if self.sheet().col_manager.get_position(col_idx) > fixed_cols {
+            if cell.right() > view.right() {
+                left += cell.right() - view.right();
+            }
+            if cell.left() - frozen_width < left {
+                left = cell.left() - frozen_width;
+            }
+        }
+        if row_idx > fixed_rows {
+            if cell.bottom() > view.bottom() {
+                top += cell.bottom() - view.bottom();
+            }
+            if cell.top() - frozen_height < top {
+                top = cell.top() - frozen_height;
+            }
+        }
+        if left != view.left() || top != view.top() {
+            self.scroll_to(left, top);
+        }
+    }
+
+    fn scroll_to(&mut self, left: f64, top: f64) {
+        self.scroller.set_scroll_left(left as i32);
+        self.h_scroller.set_scroll_left(left as i32);
+        self.scroller.set_scroll_top(top as i32);
+        self.v_scroller.set_scroll_top(top as i32);
+    }
+
+    /// Content coordinates of a cell, or of the whole merge it is part of.
+    fn get_cell_bounds(&self, col_idx: u32, row_idx: u32) -> Rectangle {
+        match self.sheet().data_model.merges.get(col_idx, row_idx) {
+            Some(range) => self.get_range_bounds(range),
+            None => self.get_range_bounds(&RangeRef::cell(col_idx, row_idx)),
+        }
+    }
+
+    /// Content coordinates of a range of cells. With columns moved, it spans
+    /// from its first column shown to its last.
+    fn get_range_bounds(&self, range: &RangeRef) -> Rectangle {
+        let sheet = self.sheet();
+        let (first, last) = (self.get_edge_column(range, false), self.get_edge_column(range, true));
+        let left = sheet.col_manager.get_offset(sheet.col_manager.get_position(first));
+        let right = sheet.col_manager.get_offset(sheet.col_manager.get_position(last))
+            + sheet.col_manager.get_width(last) as f64;
+        let top = sheet.row_manager.get_offset(range.start.row);
+        let bottom = sheet.row_manager.get_offset(range.end.row) + sheet.row_manager.get_height(range.end.row) as f64;
+        Rectangle::new(left, top, right - left, bottom - top)
+    }
+
+    /// Canvas spans of the runs of side-by-side columns in a band that fall
+    /// within a range; with columns moved, a range may be shown in pieces.
+    /// Runs carrying on past the band reach just beyond it.
+    fn get_column_runs(&self, range: &RangeRef, band: &Band) -> Vec<(f64, f64)> {
+        let cols = &self.sheet().col_manager;
+        let within = |pos: u32| {
+            pos >= 1 && pos <= cols.get_col_count() && range.contains(cols.get_column_at(pos), range.start.row)
+        };
+        let mut runs: Vec<(f64, f64)> = vec![];
+        let mut open = false;
+        for (i, (col_idx, offset, width)) in band.lines.iter().enumerate() {
+            if !within(cols.get_position(*col_idx)) {
+                open = false;
+                continue;
+            }
+            let mut left = *offset;
+            let mut right = offset + *width as f64;
+            if i == 0 && within(cols.get_position(*col_idx) - 1) {
+                left = band.start - 1.0;
+            }
+            if i + 1 == band.lines.len() && within(cols.get_position(*col_idx) + 1) {
+                right = band.end + 1.0;
+            }
+            match runs.last_mut() {
+                Some(run) if open => run.1 = right,
+                _ => runs.push((left, right)),
+            }
+            open = true;
+        }
+        runs
+    }
+
+    /// Find the cell or header under the mouse; within a merge, its anchor.
+    fn hit_test(&self, event: &web_sys::MouseEvent) -> Hit {
+        let point = event.location(self.canvas.get_bounding_rect(), 1.0);
+        let header_width = self.get_row_header_width();
+        let header_height = self.get_column_header_height();
+        let view = self.get_scroller_bounds();
+        let (frozen_width, frozen_height) = self.get_frozen_extent();
+        let x = (point.x() - header_width).max(0.0);
+        let y = (point.y() - header_height).max(0.0);
+        let x = if x < frozen_width { x } else { x + view.left() };
+        let y = if y < frozen_height { y } else { y + view.top() };
+        let (col_count, _) = self.get_last_visible_col(x as usize);
+        let (row_count, _) = self.get_last_visible_row(y as usize);
+        let col_pos = (col_count + 1).min(self.sheet().col_manager.get_col_count());
+        let col_idx = self.sheet().col_manager.get_column_at(col_pos);
+        let row_idx = (row_count + 1).min(self.sheet().row_manager.get_row_count());
+        match (point.x() < header_width, point.y() < header_height) {
+            (true, true) => Hit::Corner,
+            (false, true) => Hit::ColumnHeader(col_idx),
+            (true, false) => Hit::RowHeader(row_idx),
+            (false, false) => {
+                let (col_idx, row_idx) = self.sheet().data_model.merges.get_anchor(col_idx, row_idx);
+                Hit::Cell(col_idx, row_idx)
+            }
+        }
+    }
+
+    /// The selected ranges in A1 notation, comma separated, e.g. `A1:B3,D5`.
+    pub fn get_selection(&self) -> String {
+        self.selection.to_a1()
+    }
+
+    /// The selected ranges in R1C1 notation relative to the active cell,
+    /// e.g. `RC:R[2]C[1]`.
+    pub fn get_selection_r1c1(&self) -> String {
+        self.selection.to_r1c1()
+    }
+
+    /// Clamp the current selection range to the data in the sheet.
+    fn get_copy_range(&self) -> Option<RangeRef> {
+        let mut range = *self.selection.get_ranges().last()?;
+        let (max_col, max_row) = self.sheet().data_model.get_data_extent();
+        range.end.col = range.end.col.min(max_col);
+        range.end.row = range.end.row.min(max_row);
+        if range.end.col < range.start.col || range.end.row < range.start.row {
+            return None;
+        }
+        Some(range)
+    }
+
+    /// Display texts of the current selection range, row by row.
+    fn get_copy_rows(&self) -> Vec<Vec<String>> {
+        let range = match self.get_copy_range() {
+            Some(range) => range,
+            None => return vec![],
+        };
+        self.sheet().data_model
+            .get_texts((range.start.col, range.start.row), (range.end.col, range.end.row))
+    }
+
+    /// The current selection range as tab separated text.
+    pub fn copy_selection(&self) -> String {
+        clipboard::to_tsv(&self.get_copy_rows())
+    }
+
+    /// Copy the current selection range and clear it. Returns the copied text,
+    /// or `None` when the sheet is read only.
+    pub fn cut_selection(&mut self) -> Option<String> {
+        if !self.is_editable() {
+            return None;
+        }
+        let text = self.copy_selection();
+        let range = match self.get_copy_range() {
+            Some(range) => range,
+            None => return Some(text),
+        };
+        let cleared = self.sheet_mut().data_model.clear_range(&range);
+        if cleared.is_empty() {
+            return Some(text);
+        }
+        let commands = cleared
+            .iter()
+            .map(|(col_idx, row_idx, before)| Command::SetCell {
+                col_idx: *col_idx,
+                row_idx: *row_idx,
+                before: before.clone(),
+                after: CellSnapshot::value(CellValue::Empty),
+            })
+            .collect();
+        self.sheet_mut().history.record(Command::Group(commands));
+        for (col_idx, row_idx, before) in cleared.iter() {
+            self.dispatch_change(*col_idx, *row_idx, &before.value);
+        }
+        self.refresh();
+        Some(text)
+    }
+
+    /// Paste tab separated text or an HTML table at the active cell. When the
+    /// selection is a block the pasted data tiles exactly, it fills the block.
+    /// Texts starting with `=` become formulas. Returns false when nothing was
+    /// pasted.
+    pub fn paste(&mut self, text: &str) -> bool {
+        let rows = match clipboard::parse_html(text) {
+            Some(rows) => rows,
+            None => clipboard::parse_tsv(text),
+        };
+        self.paste_rows(rows)
+    }
+
+    fn paste_rows(&mut self, rows: Vec<Vec<String>>) -> bool {
+        let data_cols = rows.iter().map(|row| row.len()).max().unwrap_or(0) as u32;
+        let data_rows = rows.len() as u32;
+        if !self.is_editable() || data_cols == 0 {
+            return false;
+        }
+        let target = match self.selection.get_ranges() {
+            [range] if range.kind == RangeKind::Cells => {
+                let size = (range.end.col - range.start.col + 1, range.end.row - range.start.row + 1);
+                Some((range.start.col, range.start.row, size))
+            }
+            _ => None,
+        };
+        let (start_col, start_row, (cols, rows_count)) = match target {
+            Some((col_idx, row_idx, size)) if clipboard::paste_extent((data_cols, data_rows), size) == size => {
+                (col_idx, row_idx, size)
+            }
+            _ => {
+                let (col_idx, row_idx) = self.selection.get_active();
+                (col_idx, row_idx, (data_cols, data_rows))
+            }
+        };
+        let end_col = (start_col as u64 + cols as u64 - 1).min(self.sheet().col_manager.get_col_count() as u64) as u32;
+        let row_count = self.sheet().row_manager.get_row_count();
+        let end_row = (start_row as u64 + rows_count as u64 - 1).min(row_count as u64) as u32;
+        self.sheet_mut().history.begin_group();
+        for row_idx in start_row..=end_row {
+            let row = &rows[((row_idx - start_row) % data_rows) as usize];
+            for col_idx in start_col..=end_col {
+                let text = match row.get(((col_idx - start_col) % data_cols) as usize) {
+                    Some(text) => text,
+                    None => "",
+                };
+                if let Some(old_value) = self.set_cell_content(col_idx, row_idx, CellSnapshot::from_text(text)) {
+                    self.dispatch_change(col_idx, row_idx, &old_value);
+                }
+            }
+        }
+        self.sheet_mut().history.end_group();
+        self.selection.select_cell(start_col, start_row, false);
+        self.selection.extend_to(end_col, end_row);
+        self.activate_cell(start_col, start_row);
+        self.refresh();
+        true
+    }
+
+    /// Replace the sheet's contents with CSV text. The delimiter (comma,
+    /// semicolon, tab or pipe) is sniffed and a byte order mark dropped; fields
+    /// are typed as when entered by hand. Sizes, hidden lines and the column
+    /// order are reset and the grid grows to fit the data, with
+    /// `column_autosizing` columns are fitted to it, and the load cannot be
+    /// undone. Returns the number of rows read.
+    pub fn load_csv(&mut self, text: &str) -> u32 {
+        let rows = csv::parse_sniffed(text);
+        let col_count = rows.iter().map(|row| row.len()).max().unwrap_or(0) as u32;
+        let row_count = rows.len() as u32;
+        self.cancel_editing();
+        self.sheet_mut().data_model.clear();
+        self.sheet_mut().data_model.styles.clear();
+        self.sheet_mut().data_model.merges.clear();
+        self.sheet_mut().data_model.load_rows(1, 1, &rows);
+        let props = &self.properties;
+        let sheet = self.workbook.sheet_mut();
+        let width = props.default_column_width as u16;
+        let height = props.default_row_height as u16;
+        sheet.col_manager = ColumnManager::new(col_count.max(sheet.col_manager.get_col_count()), width);
+        sheet.row_manager = RowManager::new(row_count.max(sheet.row_manager.get_row_count()), height);
+        if self.properties.column_autosizing {
+            self.autosize_columns();
+        }
+        self.sheet_mut().history.clear();
+        self.selection.select_cell(1, 1, false);
+        self.activate_cell(1, 1);
+        self.scroll_to(0.0, 0.0);
+        self.refresh();
+        row_count
+    }
+
+    /// Load an .xlsx workbook, replacing every sheet with its worksheets and
+    /// making the first active.
+    pub fn load_xlsx(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        let data = xlsx::read(bytes).map_err(JsValue::from)?;
+        if data.is_empty() {
+            return Err(JsValue::from("workbook has no worksheets"));
+        }
+        self.cancel_editing();
+        let sheets = data
+            .iter()
+            .map(|data| {
+                let mut sheet = new_sheet(&data.name, &self.properties);
+                data.load(&mut sheet.data_model, &mut sheet.row_manager, &mut sheet.col_manager);
+                sheet
+            })
+            .collect();
+        self.workbook.replace(sheets);
+        self.show_sheet();
+        self.dispatch_sheets();
+        Ok(())
+    }
+
+    /// Save every sheet as a worksheet of an .xlsx workbook.
+    pub fn save_xlsx(&self) -> Vec<u8> {
+        let sheets: Vec<SheetData> = self
+            .workbook
+            .get_sheets()
+            .iter()
+            .map(|sheet| SheetData::capture(&sheet.name, &sheet.data_model, &sheet.row_manager, &sheet.col_manager))
+            .collect();
+        xlsx::write(&sheets)
+    }
+
+    /// Add an empty sheet after the last one, named `SheetN` unless a name is
+    /// given. Returns its position.
+    pub fn add_sheet(&mut self, name: Option<String>) -> Result<u32, JsValue> {
+        let name = name.unwrap_or_else(|| self.workbook.next_name());
+        let sheet = new_sheet(&name, &self.properties);
+        let idx = self.workbook.add(self.workbook.len(), sheet).map_err(JsValue::from)?;
+        self.dispatch_sheets();
+        Ok(idx as u32)
+    }
+
+    /// Rename a sheet. Formulas reading it follow the new name.
+    pub fn rename_sheet(&mut self, idx: u32, name: &str) -> Result<(), JsValue> {
+        self.commit_editing();
+        self.workbook.rename(idx as usize, name).map_err(JsValue::from)?;
+        self.dispatch_sheets();
+        self.paint();
+        Ok(())
+    }
+
+    pub fn move_sheet(&mut self, from: u32, to: u32) -> bool {
+        if !self.workbook.move_sheet(from as usize, to as usize) {
+            return false;
+        }
+        self.dispatch_sheets();
+        true
+    }
+
+    /// Remove a sheet; formulas reading it show `#REF!`. The last sheet
+    /// cannot be removed.
+    pub fn delete_sheet(&mut self, idx: u32) -> Result<(), JsValue> {
+        self.commit_editing();
+        let active = self.workbook.get_active();
+        self.workbook.remove(idx as usize).map_err(JsValue::from)?;
+        if idx as usize == active {
+            self.show_sheet();
+        } else {
+            self.paint();
+        }
+        self.dispatch_sheets();
+        Ok(())
+    }
+
+    /// Copy a sheet, placing the copy after it. Returns the copy's position.
+    pub fn duplicate_sheet(&mut self, idx: u32) -> Result<u32, JsValue> {
+        self.commit_editing();
+        let idx = self.workbook.duplicate(idx as usize).map_err(JsValue::from)?;
+        self.dispatch_sheets();
+        Ok(idx as u32)
+    }
+
+    pub fn get_sheet_names(&self) -> js_sys::Array {
+        self.workbook.get_names().iter().map(|name| JsValue::from(name.as_str())).collect()
+    }
+
+    pub fn get_active_sheet(&self) -> u32 {
+        self.workbook.get_active() as u32
+    }
+
+    /// Switch to another sheet, committing any edit in progress.
+    pub fn set_active_sheet(&mut self, idx: u32) -> bool {
+        self.commit_editing();
+        if !self.workbook.set_active(idx as usize) {
+            return false;
+        }
+        self.show_sheet();
+        self.dispatch_sheets();
+        true
+    }
+
+    /// Reset the selection and scroll position after the active sheet changed.
+    fn show_sheet(&mut self) {
+        self.selection.select_cell(1, 1, false);
+        self.activate_cell(1, 1);
+        self.scroll_to(0.0, 0.0);
+        self.paint();
+    }
+
+    /// Re-evaluate volatile formulas and carry values across sheets after a
+    /// change, then repaint.
+    fn refresh(&mut self) {
+        self.workbook.recalculate_volatile();
+        self.workbook.sync();
+        self.paint();
+    }
+
+    /// Export a range such as `A1:D20` as RFC 4180 CSV, or the whole of the
+    /// data when no range is given. Whole columns and rows stop at the data.
+    /// Returns `None` for a range that does not parse.
+    pub fn export_csv(&self, range: Option<String>) -> Option<String> {
+        let (max_col, max_row) = self.sheet().data_model.get_data_extent();
+        let range = match range {
+            Some(range) => RangeRef::parse(&range)?,
+            None if max_col == 0 => return Some(String::new()),
+            None => RangeRef::new(CellRef::new(1, 1), CellRef::new(max_col, max_row)),
+        };
+        let (end_col, end_row) = match range.kind {
+            RangeKind::Cells => (range.end.col, range.end.row),
+            _ => (range.end.col.min(max_col), range.end.row.min(max_row)),
+        };
+        if end_col < range.start.col || end_row < range.start.row {
+            return Some(String::new());
+        }
+        let rows = self
+            .sheet()
+            .data_model
+            .get_texts((range.start.col, range.start.row), (end_col, end_row));
+        Some(csv::write(&rows, ','))
+    }
+
+    /// Put the selection on the clipboard as both tab separated text and an
+    /// HTML table.
+    pub fn on_copy(&mut self, event: web_sys::Event) {
+        if self.editor.is_editing() {
+            return;
+        }
+        if let Some(data) = clipboard_data(&event) {
+            let rows = self.get_copy_rows();
+            data.set_data("text/plain", &clipboard::to_tsv(&rows)).unwrap();
+            data.set_data("text/html", &clipboard::to_html(&rows)).unwrap();
+            event.prevent_default();
+        }
+    }
+
+    pub fn on_cut(&mut self, event: web_sys::Event) {
+        if self.editor.is_editing() || !self.is_editable() {
+            return;
+        }
+        self.on_copy(event);
+        self.cut_selection();
+    }
+
+    /// Paste from the clipboard, preferring an HTML table over plain text.
+    pub fn on_paste(&mut self, event: web_sys::Event) {
+        if self.editor.is_editing() {
+            return;
+        }
+        let data = match clipboard_data(&event) {
+            Some(data) => data,
+            None => return,
+        };
+        event.prevent_default();
+        let rows = match data.get_data("text/html").ok().and_then(|html| clipboard::parse_html(&html)) {
+            Some(rows) => rows,
+            None => clipboard::parse_tsv(&data.get_data("text/plain").unwrap_or_default()),
+        };
+        self.paste_rows(rows);
+    }
+
+    /// Start a selection: a plain press selects the cell or header under the
+    /// mouse, Shift extends the current range to it and Ctrl/Cmd adds a new
+    /// range when `multiple_selections` is set. Dragging extends the range.
+    /// Pressing on a header border starts resizing that column or row, and
+    /// with `columns_reorderable` pressing on the header of the one selected
+    /// column starts dragging it to another position.
+    pub fn on_mousedown(&mut self, event: web_sys::MouseEvent) {
+        if event.button() != 0 {
+            return;
+        }
+        self.commit_editing();
+        if let Some((axis, idx)) = self.hit_border(&event) {
+            self.start_resize(axis, idx, &event);
+            return;
+        }
+        if let Hit::ColumnHeader(col_idx) = self.hit_test(&event) {
+            let selected = self.selection.get_ranges() == [RangeRef::columns(col_idx, col_idx)];
+            if self.properties.columns_reorderable && selected && !event.shift_key() {
+                self.start_reorder(col_idx, &event);
+                return;
+            }
+        }
+        let props = &self.properties;
+        let add = (event.ctrl_key() || event.meta_key()) && props.multiple_selections;
+        let extend = event.shift_key();
+        let col_count = self.sheet().col_manager.get_col_count();
+        let row_count = self.sheet().row_manager.get_row_count();
+        let handled = match self.hit_test(&event) {
+            Hit::Cell(col_idx, row_idx) if props.cell_selection => {
+                if extend {
+                    self.selection.extend_to(col_idx, row_idx);
+                } else {
+                    self.selection.select_cell(col_idx, row_idx, add);
+                }
+                true
+            }
+            Hit::ColumnHeader(col_idx) if props.column_selection => {
+                if extend {
+                    self.selection.extend_to(col_idx, row_count);
+                } else {
+                    self.selection.select_column(col_idx, add);
+                }
+                true
+            }
+            Hit::RowHeader(row_idx) if props.row_selection => {
+                if extend {
+                    self.selection.extend_to(col_count, row_idx);
+                } else {
+                    self.selection.select_row(row_idx, add);
+                }
+                true
+            }
+            Hit::Corner if props.column_selection => {
+                self.selection.select_column(1, false);
+                self.selection.extend_to(col_count, 1);
+                true
+            }
+            _ => false,
+        };
+        if handled {
+            let (col_idx, row_idx) = self.selection.get_active();
+            self.activate_cell(col_idx, row_idx);
+            self.dragging = true;
+            self.paint();
+        }
+    }
+
+    pub fn on_mousemove(&mut self, event: web_sys::MouseEvent) {
+        if let Some(reorder) = self.reordering.as_mut() {
+            if event.buttons() & 1 == 0 {
+                self.finish_reorder();
+            } else {
+                reorder.x = event.location(self.canvas.get_bounding_rect(), 1.0).x();
+                self.paint();
+            }
+            return;
+        }
+        if self.resizing.is_some() {
+            if event.buttons() & 1 == 0 {
+                self.finish_resize();
+            } else {
+                self.update_resize(&event);
+            }
+            return;
+        }
+        if !self.dragging || event.buttons() & 1 == 0 {
+            self.dragging = false;
+            let cursor = match self.hit_border(&event) {
+                Some((Axis::Columns, _)) => "col-resize",
+                Some((Axis::Rows, _)) => "row-resize",
+                None => "",
+            };
+            self.canvas.style().set_property("cursor", cursor).unwrap();
+            return;
+        }
+        let (focus_col, focus_row) = self.selection.get_focus();
+        let (col_idx, row_idx) = match self.hit_test(&event) {
+            Hit::Cell(col_idx, row_idx) => (col_idx, row_idx),
+            Hit::ColumnHeader(col_idx) => (col_idx, focus_row),
+            Hit::RowHeader(row_idx) => (focus_col, row_idx),
+            Hit::Corner => (focus_col, focus_row),
+        };
+        if (col_idx, row_idx) != (focus_col, focus_row) {
+            self.selection.extend_to(col_idx, row_idx);
+            self.paint();
+        }
+    }
+
+    pub fn on_mouseup(&mut self, _: web_sys::MouseEvent) {
+        self.dragging = false;
+        self.finish_resize();
+        self.finish_reorder();
+    }
+
+    fn start_reorder(&mut self, col_idx: u32, event: &web_sys::MouseEvent) {
+        let x = event.location(self.canvas.get_bounding_rect(), 1.0).x();
+        let (bands, _) = self.get_bands(Axis::Columns);
+        let left = bands
+            .iter()
+            .flat_map(|band| band.lines.iter())
+            .find(|(idx, _, _)| *idx == col_idx)
+            .map(|(_, offset, _)| *offset)
+            .unwrap_or(x);
+        self.reordering = Some(Reorder {
+            col_idx,
+            left,
+            origin: x,
+            x,
+        });
+    }
+
+    /// Position a column dragged to canvas `x` is dropped before, and the
+    /// canvas offset of that spot.
+    fn get_drop_position(&self, x: f64) -> (u32, f64) {
+        let cols = &self.sheet().col_manager;
+        let (bands, _) = self.get_bands(Axis::Columns);
+        let mut drop = (1, self.get_row_header_width());
+        for (col_idx, offset, width) in bands.iter().flat_map(|band| band.lines.iter()) {
+            if x < offset + *width as f64 / 2.0 {
+                return (cols.get_position(*col_idx), *offset);
+            }
+            drop = (cols.get_position(*col_idx) + 1, offset + *width as f64);
+        }
+        drop
+    }
+
+    /// Drop a dragged column where the mouse is, as an undoable step.
+    fn finish_reorder(&mut self) {
+        let reorder = match self.reordering.take() {
+            Some(reorder) => reorder,
+            None => return,
+        };
+        let (target, _) = self.get_drop_position(reorder.x);
+        let from = self.sheet().col_manager.get_position(reorder.col_idx);
+        let to = if target > from { target - 1 } else { target };
+        if to != from {
+            self.execute(Command::MoveColumn { from, to });
+        }
+        let (col_idx, row_idx) = self.selection.get_active();
+        self.activate_cell(col_idx, row_idx);
+        self.paint();
+    }
+
+    /// Columns of the active sheet in the order shown, leaving off those at
+    /// the end that are in their own place. Pass it to `set_column_order` to
+    /// restore the order.
+    pub fn get_column_order(&self) -> Vec<u32> {
+        self.sheet().col_manager.get_order()
+    }
+
+    /// Show the columns of the active sheet in the given order, followed by
+    /// the rest in their own place. This cannot be undone.
+    pub fn set_column_order(&mut self, order: Vec<u32>) -> Result<(), JsValue> {
+        self.sheet_mut().col_manager.set_order(&order).map_err(JsValue::from)?;
+        let (col_idx, row_idx) = self.selection.get_active();
+        self.activate_cell(col_idx, row_idx);
+        self.paint();
+        Ok(())
+    }
+
+    /// Apply style properties to comma-separated ranges such as `A1:C3`,
+    /// `B:B` or `2:4`, over what each already has, as one undoable step.
+    /// Whole columns and rows are styled as such; cell styles win over row
+    /// styles, which win over column styles. An invalid `numberFormat` is
+    /// refused.
+    pub fn set_style(&mut self, range: &str, style: JsValue) -> Result<(), JsValue> {
+        let style: serde_json::Value = style.into_serde().map_err(|err| JsValue::from(err.to_string()))?;
+        let style: Style = serde_json::from_value(style).map_err(|err| JsValue::from(err.to_string()))?;
+        if let Some(code) = &style.number_format {
+            NumberFormat::parse(code).map_err(JsValue::from)?;
+        }
+        self.update_styles(range, |before| before.overlay(&style))
+    }
+
+    /// Remove the styles set on comma-separated ranges, as one undoable step.
+    pub fn clear_style(&mut self, range: &str) -> Result<(), JsValue> {
+        self.update_styles(range, |_| Style::default())
+    }
+
+    /// Style a cell is painted with, after the cascade.
+    pub fn get_style(&self, col_idx: u32, row_idx: u32) -> JsValue {
+        JsValue::from_serde(&self.sheet().data_model.styles.resolve(col_idx, row_idx)).unwrap()
+    }
+
+    fn update_styles<F: Fn(&Style) -> Style>(&mut self, range: &str, update: F) -> Result<(), JsValue> {
+        let styles = &self.sheet().data_model.styles;
+        let commands: Vec<Command> = get_style_targets(range)
+            .map_err(JsValue::from)?
+            .into_iter()
+            .filter_map(|target| {
+                let before = styles.get(target);
+                let after = update(&before);
+                if before == after {
+                    None
+                } else {
+                    Some(Command::SetStyle {
+                        target,
+                        before: Box::new(before),
+                        after: Box::new(after),
+                    })
+                }
+            })
+            .collect();
+        if !commands.is_empty() {
+            self.execute(Command::Group(commands));
+            self.paint();
+        }
+        Ok(())
+    }
+
+    /// Merge each of the comma-separated cell ranges into one cell, shown with
+    /// the value and style of its top-left cell, as one undoable step. Merges
+    /// overlapping a range are replaced by it.
+    pub fn merge_cells(&mut self, range: &str) -> Result<(), JsValue> {
+        let ranges = self.get_merge_ranges(range).map_err(JsValue::from)?;
+        self.update_merges(ranges, true)
+    }
+
+    /// Merge each row of the comma-separated cell ranges on its own.
+    pub fn merge_across(&mut self, range: &str) -> Result<(), JsValue> {
+        let ranges = self
+            .get_merge_ranges(range)
+            .map_err(JsValue::from)?
+            .into_iter()
+            .flat_map(|range| {
+                (range.start.row..=range.end.row).map(move |row_idx| {
+                    RangeRef::new(CellRef::new(range.start.col, row_idx), CellRef::new(range.end.col, row_idx))
+                })
+            })
+            .collect();
+        self.update_merges(ranges, true)
+    }
+
+    /// Split the merges overlapping the comma-separated ranges back into
+    /// cells, as one undoable step.
+    pub fn unmerge_cells(&mut self, range: &str) -> Result<(), JsValue> {
+        let ranges = self.get_merge_ranges(range).map_err(JsValue::from)?;
+        self.update_merges(ranges, false)
+    }
+
+    /// The merges of the active sheet in A1 notation, comma separated.
+    pub fn get_merges(&self) -> String {
+        let merges = self.sheet().data_model.merges.get_ranges();
+        merges.iter().map(|range| range.to_a1()).collect::<Vec<_>>().join(",")
+    }
+
+    /// Cell ranges named by comma-separated references, limited to the grid.
+    fn get_merge_ranges(&self, range: &str) -> Result<Vec<RangeRef>, String> {
+        let col_count = self.sheet().col_manager.get_col_count();
+        let row_count = self.sheet().row_manager.get_row_count();
+        let mut ranges = vec![];
+        for part in range.split(',') {
+            let mut range = RangeRef::parse(part).ok_or_else(|| format!("Invalid range: {}", part.trim()))?;
+            if range.kind != RangeKind::Cells {
+                return Err(format!("Only ranges of cells can be merged: {}", part.trim()));
+            }
+            range.end.col = range.end.col.min(col_count);
+            range.end.row = range.end.row.min(row_count);
+            if range.start.col <= range.end.col && range.start.row <= range.end.row {
+                ranges.push(range);
+            }
+        }
+        Ok(ranges)
+    }
+
+    /// Remove the merges overlapping `ranges` and, with `merge`, add the
+    /// ranges as merges, as one undoable step. A single selected cell left
+    /// under a merge moves to its anchor.
+    fn update_merges(&mut self, ranges: Vec<RangeRef>, merge: bool) -> Result<(), JsValue> {
+        let mut merges = self.sheet().data_model.merges.clone();
+        let mut before = vec![];
+        let mut after: Vec<RangeRef> = vec![];
+        for range in ranges {
+            for old in merges.get_overlapping(&range) {
+                merges.remove(&old);
+                match after.iter().position(|added| *added == old) {
+                    Some(i) => {
+                        after.remove(i);
+                    }
+                    None => before.push(old),
+                }
+            }
+            if merge && !range.is_cell() {
+                merges.add(range);
+                after.push(range);
+            }
+        }
+        if before.is_empty() && after.is_empty() {
+            return Ok(());
+        }
+        self.execute(Command::SetMerges { before, after });
+        if self.selection.is_single_cell() {
+            let (col_idx, row_idx) = self.selection.get_active();
+            let (col_idx, row_idx) = self.sheet().data_model.merges.get_anchor(col_idx, row_idx);
+            self.selection.select_cell(col_idx, row_idx, false);
+        }
+        let (col_idx, row_idx) = self.selection.get_active();
+        self.activate_cell(col_idx, row_idx);
+        self.paint();
+        Ok(())
+    }
+
+    /// Column or row whose far border in the header lies within
+    /// `column_grab_margin` of the mouse. Rows only with `row_resize`.
+    fn hit_border(&self, event: &web_sys::MouseEvent) -> Option<(Axis, u32)> {
+        let point = event.location(self.canvas.get_bounding_rect(), 1.0);
+        let header_width = self.get_row_header_width();
+        let header_height = self.get_column_header_height();
+        let (axis, position) = if point.y() < header_height && point.x() >= header_width {
+            (Axis::Columns, point.x())
+        } else if point.x() < header_width && point.y() >= header_height && self.properties.row_resize {
+            (Axis::Rows, point.y())
+        } else {
+            return None;
+        };
+        let margin = self.properties.column_grab_margin as f64;
+        let (bands, _) = self.get_bands(axis);
+        let mut nearest: Option<(u32, f64)> = None;
+        for (idx, offset, size) in bands.iter().flat_map(|band| band.lines.iter()) {
+            let distance = (offset + *size as f64 - position).abs();
+            if distance <= margin && nearest.map(|(_, nearest)| distance < nearest).unwrap_or(true) {
+                nearest = Some((*idx, distance));
+            }
+        }
+        nearest.map(|(idx, _)| (axis, idx))
+    }
+
+    fn start_resize(&mut self, axis: Axis, idx: u32, event: &web_sys::MouseEvent) {
+        let point = event.location(self.canvas.get_bounding_rect(), 1.0);
+        let sheet = self.sheet();
+        let (origin, before) = match axis {
+            Axis::Rows => (point.y(), sheet.row_manager.get_shown_height(idx)),
+            Axis::Columns => (point.x(), sheet.col_manager.get_shown_width(idx)),
+        };
+        let next = self.step_visible(Axis::Columns, idx, 1);
+        let neighbour = if axis == Axis::Columns && self.properties.resize_column_in_place && next != idx {
+            Some((next, sheet.col_manager.get_shown_width(next)))
+        } else {
+            None
+        };
+        self.resizing = Some(Resize {
+            axis,
+            idx,
+            origin,
+            before,
+            neighbour,
+            after: before,
+        });
+    }
+
+    /// Live-resize to follow the mouse, without recording history yet.
+    fn update_resize(&mut self, event: &web_sys::MouseEvent) {
+        let point = event.location(self.canvas.get_bounding_rect(), 1.0);
+        let minimum_width = self.properties.minimum_column_width as f64;
+        let resize = match self.resizing.as_mut() {
+            Some(resize) => resize,
+            None => return,
+        };
+        let position = match resize.axis {
+            Axis::Rows => point.y(),
+            Axis::Columns => point.x(),
+        };
+        let mut size = resize.before as f64 + position - resize.origin;
+        let minimum = match resize.axis {
+            Axis::Rows => 1.0,
+            Axis::Columns => minimum_width,
+        };
+        size = size.max(minimum);
+        if let Some((_, next_width)) = resize.neighbour {
+            size = size.min((resize.before as i32 + next_width as i32) as f64 - minimum);
+        }
+        resize.after = size.max(0.0).min(u16::MAX as f64) as u16;
+        let command = resize.to_command();
+        let sheet = self.workbook.sheet_mut();
+        command.apply(&mut sheet.data_model, &mut sheet.row_manager, &mut sheet.col_manager);
+        let (col_idx, row_idx) = self.selection.get_active();
+        self.activate_cell(col_idx, row_idx);
+        self.paint();
+    }
+
+    /// End a resize, recording it for undo and telling the host.
+    fn finish_resize(&mut self) {
+        let resize = match self.resizing.take() {
+            Some(resize) => resize,
+            None => return,
+        };
+        if resize.after != resize.before {
+            let command = resize.to_command();
+            self.dispatch_command_changes(&command, false);
+            self.execute(command);
+        }
+    }
+
+    /// Size a column to fit its widest value and header, or a row to fit its
+    /// tallest value.
+    fn autofit(&mut self, axis: Axis, idx: u32) {
+        match axis {
+            Axis::Rows => {
+                let before = self.sheet().row_manager.get_shown_height(idx);
+                let after = self.get_fit_height(idx);
+                self.set_row_height(idx, after);
+                if before != after {
+                    self.dispatch_resize(Axis::Rows, idx, before, after);
+                }
+                let (col_idx, row_idx) = self.selection.get_active();
+                self.activate_cell(col_idx, row_idx);
+            }
+            Axis::Columns => {
+                self.autosize_column(idx);
+            }
+        }
+    }
+
+    /// Fit a column to its widest value and its header, within
+    /// `minimum_column_width` and `column_autosizing_max`. Returns whether the
+    /// width changed.
+    pub fn autosize_column(&mut self, col_idx: u32) -> bool {
+        if col_idx == 0 || col_idx > self.sheet().col_manager.get_col_count() {
+            return false;
+        }
+        let widths = self.get_fit_widths(&[RangeRef::columns(col_idx, col_idx)], &[col_idx]);
+        self.apply_fit_widths(widths)
+    }
+
+    /// Fit the columns holding values within the selection to those values,
+    /// as one undoable step.
+    pub fn autosize_selection(&mut self) -> bool {
+        let ranges = self.selection.get_ranges().to_vec();
+        let widths = self.get_fit_widths(&ranges, &[]);
+        self.apply_fit_widths(widths)
+    }
+
+    /// Fit every column holding a value, as one undoable step.
+    pub fn autosize_columns(&mut self) -> bool {
+        let (max_col, _) = self.sheet().data_model.get_data_extent();
+        if max_col == 0 {
+            return false;
+        }
+        let widths = self.get_fit_widths(&[RangeRef::columns(1, max_col)], &[]);
+        self.apply_fit_widths(widths)
+    }
+
+    /// Widths fitting the values within `ranges` and the column headers, for
+    /// the columns holding such values along with `cols`.
+    fn get_fit_widths(&self, ranges: &[RangeRef], cols: &[u32]) -> BTreeMap<u32, u16> {
+        let props = &self.properties;
+        let mut widest: BTreeMap<u32, f64> = cols.iter().map(|col_idx| (*col_idx, 0.0)).collect();
+        for ((col_idx, row_idx), _) in self.sheet().data_model.get_values_within(ranges) {
+            let style = self.sheet().data_model.styles.resolve(col_idx, row_idx);
+            let (text, _) = self.get_display_text(col_idx, row_idx);
+            let width = self.measure_text(&style.get_font(&props.font), &text);
+            let entry = widest.entry(col_idx).or_insert(0.0);
+            *entry = entry.max(width);
+        }
+        widest
+            .into_iter()
+            .map(|(col_idx, width)| {
+                let header = self.measure_text(&props.column_header_font, &self.get_column_header_text(col_idx));
+                let width = measure::fit_width(
+                    [width, header].iter().copied(),
+                    props.cell_padding as f64,
+                    props.minimum_column_width,
+                    props.column_autosizing_max,
+                );
+                (col_idx, width)
+            })
+            .collect()
+    }
+
+    /// Height fitting the values of a row in their fonts, with wrapped values
+    /// broken into lines at their column's width, and no less than
+    /// `default_row_height`.
+    fn get_fit_height(&self, row_idx: u32) -> u16 {
+        let props = &self.properties;
+        let sheet = self.sheet();
+        let heights = sheet
+            .data_model
+            .get_values_within(&[RangeRef::rows(row_idx, row_idx)])
+            .into_iter()
+            .map(|((col_idx, row_idx), _)| {
+                let style = sheet.data_model.styles.resolve(col_idx, row_idx);
+                let line_count = if style.wrap == Some(true) {
+                    let font = style.get_font(&props.font);
+                    let padding = props.cell_padding as f64 + style.indent.unwrap_or(0) as f64 * style::INDENT_WIDTH;
+                    let width = sheet.col_manager.get_shown_width(col_idx) as f64 - 2.0 * padding;
+                    let (text, _) = self.get_display_text(col_idx, row_idx);
+                    style::wrap_words(&text, width, |line| self.measure_text(&font, line)).len()
+                } else {
+                    1
+                };
+                style.get_font_size(&props.font) * 1.2 * line_count as f64
+            })
+            .collect::<Vec<_>>();
+        measure::fit_height(heights.into_iter(), props.default_row_height)
+    }
+
+    /// Set column widths as one undoable step and tell the host. Returns
+    /// whether any changed.
+    fn apply_fit_widths(&mut self, widths: BTreeMap<u32, u16>) -> bool {
+        let commands: Vec<Command> = widths
+            .into_iter()
+            .map(|(col_idx, after)| Command::SetColumnWidth {
+                col_idx,
+                before: self.sheet().col_manager.get_shown_width(col_idx),
+                after,
+            })
+            .filter(|command| !matches!(command, Command::SetColumnWidth { before, after, .. } if before == after))
+            .collect();
+        if commands.is_empty() {
+            return false;
+        }
+        let command = Command::Group(commands);
+        self.dispatch_command_changes(&command, false);
+        self.execute(command);
+        let (col_idx, row_idx) = self.selection.get_active();
+        self.activate_cell(col_idx, row_idx);
+        self.paint();
+        true
+    }
+
+    /// Width of `text` drawn in `font`, measured once per font and text.
+    fn measure_text(&self, font: &str, text: &str) -> f64 {
+        self.measurer.borrow_mut().measure(font, text, |font, text| {
+            let ctx: web_sys::CanvasRenderingContext2d =
+                self.canvas.get_context("2d").unwrap().unwrap().dyn_into().unwrap();
+            ctx.set_font(font);
+            ctx.measure_text(text).map(|metrics| metrics.width()).unwrap_or(0.0)
+        })
+    }
+
+    fn is_editable(&self) -> bool {
+        self.properties.editable && !self.properties.read_only
+    }
+
+    /// Open the editor over the active cell, seeded with `text` or else with
+    /// the cell's formula or value.
+    fn start_editing(&mut self, text: Option<String>) -> bool {
+        if !self.is_editable() || self.editor.is_editing() {
+            return false;
+        }
+        let mut cell = match self.active_cell.clone() {
+            Some(cell) => cell,
+            None => return false,
+        };
+        let boundary = match cell.get_boundary() {
+            Some(boundary) => boundary,
+            None => return false,
+        };
+        let (col_idx, row_idx) = (cell.get_col_idx(), cell.get_row_idx());
+        let text = match text {
+            Some(text) => text,
+            None => match self.sheet().data_model.get_formula(col_idx, row_idx) {
+                Some(formula) => formula.clone(),
+                None => self.sheet().data_model.get_value(col_idx, row_idx).to_string(),
+            },
+        };
+        self.editor.open(col_idx, row_idx, boundary, &text);
+        true
+    }
+
+    /// Store the editor's content in the edited cell and close the editor.
+    fn commit_editing(&mut self) {
+        let text = self.editor.get_value();
+        let (col_idx, row_idx) = match self.editor.close() {
+            Some(cell) => cell,
+            None => return,
+        };
+        if let Some(old_value) = self.set_cell_content(col_idx, row_idx, CellSnapshot::from_text(&text)) {
+            self.dispatch_change(col_idx, row_idx, &old_value);
+        }
+        self.refresh();
+    }
+
+    /// Change a cell, recording the change so it can be undone. Returns the
+    /// value the cell held before, or `None` when nothing changed.
+    fn set_cell_content(&mut self, col_idx: u32, row_idx: u32, after: CellSnapshot) -> Option<CellValue> {
+        let before = self.sheet().data_model.snapshot(col_idx, row_idx);
+        if before.formula == after.formula && (after.formula.is_some() || before.value == after.value) {
+            return None;
+        }
+        self.sheet_mut().data_model.restore(col_idx, row_idx, &after);
+        let old_value = before.value.clone();
+        self.sheet_mut().history.record(Command::SetCell {
+            col_idx,
+            row_idx,
+            before,
+            after,
+        });
+        Some(old_value)
+    }
+
+    /// Apply a command and record it so it can be undone.
+    fn execute(&mut self, command: Command) {
+        let sheet = self.sheet_mut();
+        command.apply(&mut sheet.data_model, &mut sheet.row_manager, &mut sheet.col_manager);
+        let shifts = command.get_shifts();
+        sheet.history.record(command);
+        self.apply_shifts(shifts);
+        self.workbook.sync();
+    }
+
+    /// Follow rows or columns of the active sheet inserted or deleted, as
+    /// `(axis, at, delta)`, in references from other sheets and in the active
+    /// cell's position on screen.
+    fn apply_shifts(&mut self, shifts: Vec<(Axis, u32, i64)>) {
+        if shifts.is_empty() {
+            return;
+        }
+        let active = self.workbook.get_active();
+        for (axis, at, delta) in shifts {
+            self.workbook.shift_references(active, axis, at, delta);
+        }
+        let (col_idx, row_idx) = self.selection.get_active();
+        self.activate_cell(col_idx, row_idx);
+    }
+
+    /// Insert `count` empty rows before `row_idx`, moving the rows below it
+    /// down. References follow the cells they point at, on every sheet.
+    /// Returns false when that would push data off the end of the grid.
+    pub fn insert_rows(&mut self, row_idx: u32, count: u32) -> bool {
+        self.insert_lines(Axis::Rows, row_idx, count)
+    }
+
+    /// Delete `count` rows starting at `row_idx`, moving the rows below up.
+    /// References to deleted cells become `#REF!`, and ranges shrink. Undo
+    /// restores the formulas on every sheet.
+    pub fn delete_rows(&mut self, row_idx: u32, count: u32) -> bool {
+        self.delete_lines(Axis::Rows, row_idx, count)
+    }
+
+    /// Insert `count` empty columns before `col_idx`, as `insert_rows` does.
+    pub fn insert_columns(&mut self, col_idx: u32, count: u32) -> bool {
+        self.insert_lines(Axis::Columns, col_idx, count)
+    }
+
+    /// Delete `count` columns starting at `col_idx`, as `delete_rows` does.
+    pub fn delete_columns(&mut self, col_idx: u32, count: u32) -> bool {
+        self.delete_lines(Axis::Columns, col_idx, count)
+    }
+
+    /// Number of rows or columns of the active sheet, and the last one
+    /// holding data.
+    fn get_line_extent(&self, axis: Axis) -> (u32, u32) {
+        let sheet = self.sheet();
+        let (max_col, max_row) = sheet.data_model.get_data_extent();
+        match axis {
+            Axis::Rows => (sheet.row_manager.get_row_count(), max_row),
+            Axis::Columns => (sheet.col_manager.get_col_count(), max_col),
+        }
+    }
+
+    fn insert_lines(&mut self, axis: Axis, at: u32, count: u32) -> bool {
+        let (line_count, data_end) = self.get_line_extent(axis);
+        if !self.is_editable() || count == 0 || at == 0 || at > line_count {
+            return false;
+        }
+        if data_end >= at && data_end as u64 + count as u64 > line_count as u64 {
+            return false;
+        }
+        self.commit_editing();
+        self.execute(Command::InsertLines { axis, at, count });
+        self.paint();
+        true
+    }
+
+    fn delete_lines(&mut self, axis: Axis, at: u32, count: u32) -> bool {
+        let (line_count, _) = self.get_line_extent(axis);
+        if !self.is_editable() || count == 0 || at == 0 || at > line_count {
+            return false;
+        }
+        let count = count.min(line_count - at + 1);
+        self.commit_editing();
+        let sheet = self.sheet_mut();
+        let delta = -(count as i64);
+        let (cells, sizes) = history::shift_lines(
+            &mut sheet.data_model,
+            &mut sheet.row_manager,
+            &mut sheet.col_manager,
+            axis,
+            at,
+            delta,
+        );
+        let active = self.workbook.get_active();
+        let external = self.workbook.shift_references(active, axis, at, delta);
+        self.sheet_mut().history.record(Command::DeleteLines {
+            axis,
+            at,
+            count,
+            cells,
+            sizes,
+            external,
+        });
+        let (col_idx, row_idx) = self.selection.get_active();
+        self.activate_cell(col_idx, row_idx);
+        self.refresh();
+        true
+    }
+
+    /// Send change events for the cells an undone or redone command touched.
+    fn dispatch_command_changes(&self, command: &Command, undone: bool) {
+        match command {
+            Command::SetCell {
+                col_idx,
+                row_idx,
+                before,
+                after,
+            } => {
+                let old_value = if undone { &after.value } else { &before.value };
+                self.dispatch_change(*col_idx, *row_idx, old_value);
+            }
+            Command::SetRowHeight { row_idx, before, after } => {
+                let (before, after) = if undone { (after, before) } else { (before, after) };
+                self.dispatch_resize(Axis::Rows, *row_idx, *before, *after);
+            }
+            Command::SetColumnWidth { col_idx, before, after } => {
+                let (before, after) = if undone { (after, before) } else { (before, after) };
+                self.dispatch_resize(Axis::Columns, *col_idx, *before, *after);
+            }
+            Command::Group(commands) => {
+                for command in commands.iter() {
+                    self.dispatch_command_changes(command, undone);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Revert the most recent change. Returns false when there is nothing to
+    /// undo.
+    pub fn undo(&mut self) -> bool {
+        let sheet = self.sheet_mut();
+        let command = sheet
+            .history
+            .undo(&mut sheet.data_model, &mut sheet.row_manager, &mut sheet.col_manager)
+            .cloned();
+        match command {
+            Some(command) => {
+                let shifts = command.get_shifts();
+                self.apply_shifts(shifts.into_iter().rev().map(|(axis, at, delta)| (axis, at, -delta)).collect());
+                self.workbook.restore_external_formulas(&command);
+                self.dispatch_command_changes(&command, true);
+                self.paint();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Apply the most recently undone change again.
+    pub fn redo(&mut self) -> bool {
+        let sheet = self.sheet_mut();
+        let command = sheet
+            .history
+            .redo(&mut sheet.data_model, &mut sheet.row_manager, &mut sheet.col_manager)
+            .cloned();
+        match command {
+            Some(command) => {
+                self.apply_shifts(command.get_shifts());
+                self.workbook.sync();
+                self.dispatch_command_changes(&command, false);
+                self.paint();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.sheet().history.can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.sheet().history.can_redo()
+    }
+
+    /// Group the changes made until `end_batch` so that they are undone as
+    /// one. Batches nest.
+    pub fn begin_batch(&mut self) {
+        self.sheet_mut().history.begin_group();
+    }
+
+    pub fn end_batch(&mut self) {
+        self.sheet_mut().history.end_group();
+    }
+
+    pub fn set_row_height(&mut self, row_idx: u32, height: u16) {
+        let before = self.sheet().row_manager.get_shown_height(row_idx);
+        if before != height {
+            self.execute(Command::SetRowHeight {
+                row_idx,
+                before,
+                after: height,
+            });
+            self.paint();
+        }
+    }
+
+    pub fn set_column_width(&mut self, col_idx: u32, width: u16) {
+        let before = self.sheet().col_manager.get_shown_width(col_idx);
+        if before != width {
+            self.execute(Command::SetColumnWidth {
+                col_idx,
+                before,
+                after: width,
+            });
+            self.paint();
+        }
+    }
+
+    /// Hide `count` rows starting at `row_idx`. Hidden rows keep their
+    /// contents and height, and are skipped when painting and navigating.
+    pub fn hide_rows(&mut self, row_idx: u32, count: u32) -> bool {
+        self.set_hidden(Axis::Rows, row_idx, count, true)
+    }
+
+    pub fn show_rows(&mut self, row_idx: u32, count: u32) -> bool {
+        self.set_hidden(Axis::Rows, row_idx, count, false)
+    }
+
+    /// Hide `count` columns starting at `col_idx`, as `hide_rows` does.
+    pub fn hide_columns(&mut self, col_idx: u32, count: u32) -> bool {
+        self.set_hidden(Axis::Columns, col_idx, count, true)
+    }
+
+    pub fn show_columns(&mut self, col_idx: u32, count: u32) -> bool {
+        self.set_hidden(Axis::Columns, col_idx, count, false)
+    }
+
+    pub fn is_row_hidden(&self, row_idx: u32) -> bool {
+        self.sheet().row_manager.is_hidden(row_idx)
+    }
+
+    pub fn is_column_hidden(&self, col_idx: u32) -> bool {
+        self.sheet().col_manager.is_hidden(col_idx)
+    }
+
+    /// Hide or show lines, recording those that changed so it can be undone.
+    /// Returns false when none did.
+    fn set_hidden(&mut self, axis: Axis, at: u32, count: u32, hidden: bool) -> bool {
+        let (line_count, _) = self.get_line_extent(axis);
+        let end = (at as u64 + count as u64).min(line_count as u64 + 1) as u32;
+        let sheet = self.sheet();
+        let indices: Vec<u32> = (at.max(1)..end)
+            .filter(|idx| match axis {
+                Axis::Rows => sheet.row_manager.is_hidden(*idx) != hidden,
+                Axis::Columns => sheet.col_manager.is_hidden(*idx) != hidden,
+            })
+            .collect();
+        if indices.is_empty() {
+            return false;
+        }
+        self.execute(Command::SetHidden { axis, indices, hidden });
+        let (col_idx, row_idx) = self.selection.get_active();
+        self.activate_cell(col_idx, row_idx);
+        self.paint();
+        true
+    }
+
+    fn cancel_editing(&mut self) {
+        self.editor.close();
+    }
+
+    /// Notify the host that a cell changed through a `hypersheet-change` event
+    /// on the canvas.
+    fn dispatch_change(&self, col_idx: u32, row_idx: u32, old_value: &CellValue) {
+        let detail = js_sys::Object::new();
+        let formula = match self.sheet().data_model.get_formula(col_idx, row_idx) {
+            Some(formula) => JsValue::from(formula.as_str()),
+            None => JsValue::NULL,
+        };
+        js_sys::Reflect::set(&detail, &"col".into(), &col_idx.into()).unwrap();
+        js_sys::Reflect::set(&detail, &"row".into(), &row_idx.into()).unwrap();
+        js_sys::Reflect::set(&detail, &"oldValue".into(), &old_value.to_js()).unwrap();
+        let value = self.sheet().data_model.get_value(col_idx, row_idx).to_js();
+        js_sys::Reflect::set(&detail, &"value".into(), &value).unwrap();
+        js_sys::Reflect::set(&detail, &"formula".into(), &formula).unwrap();
+        let event = events::create_detail_event("hypersheet-change", &detail);
+        self.canvas.dispatch_event(&event).unwrap();
+    }
+
+    /// Tell the host a row or column was resized through a `hypersheet-resize`
+    /// event on the canvas.
+    fn dispatch_resize(&self, axis: Axis, idx: u32, before: u16, after: u16) {
+        let detail = js_sys::Object::new();
+        let axis = match axis {
+            Axis::Rows => "row",
+            Axis::Columns => "column",
+        };
+        js_sys::Reflect::set(&detail, &"axis".into(), &axis.into()).unwrap();
+        js_sys::Reflect::set(&detail, &"index".into(), &idx.into()).unwrap();
+        js_sys::Reflect::set(&detail, &"oldSize".into(), &before.into()).unwrap();
+        js_sys::Reflect::set(&detail, &"size".into(), &after.into()).unwrap();
+        let event = events::create_detail_event("hypersheet-resize", &detail);
+        self.canvas.dispatch_event(&event).unwrap();
+    }
+
+    /// Tell the host the sheet names or the active sheet changed through a
+    /// `hypersheet-sheets` event on the canvas, so it can redraw its tabs.
+    fn dispatch_sheets(&self) {
+        let detail = js_sys::Object::new();
+        js_sys::Reflect::set(&detail, &"names".into(), &self.get_sheet_names()).unwrap();
+        js_sys::Reflect::set(&detail, &"active".into(), &self.get_active_sheet().into()).unwrap();
+        let event = events::create_detail_event("hypersheet-sheets", &detail);
+        self.canvas.dispatch_event(&event).unwrap();
+    }
+
+    /// Single entry point for keys. While editing, Enter/Tab commit and then
+    /// step through the selection and Escape cancels. Otherwise the chord is
+    /// looked up in the key map, and printable keys start editing.
+    pub fn on_keydown(&mut self, event: web_sys::KeyboardEvent) {
+        let key = event.key();
+        let chord = KeyMap::chord(&event);
+        if self.editor.is_editing() {
+            match key.as_str() {
+                "Enter" | "Tab" => {
+                    event.prevent_default();
+                    self.commit_editing();
+                    if let Some(action @ Action::Advance(_)) = self.keymap.get(&chord) {
+                        self.perform(action);
+                        if self.properties.edit_on_next_cell {
+                            self.start_editing(None);
+                        }
+                    }
+                }
+                "Escape" => {
+                    event.prevent_default();
+                    self.cancel_editing();
+                }
+                _ => (),
+            }
+            return;
+        }
+        let printable = key.chars().count() == 1 && !event.ctrl_key() && !event.meta_key() && !event.alt_key();
+        let handled = match self.keymap.get(&chord) {
+            Some(action) => self.perform(action),
+            None if printable && self.properties.edit_on_keydown => self.start_editing(Some(key)),
+            None => false,
+        };
+        if handled {
+            event.prevent_default();
+        }
+    }
+
+    /// Bind a key chord such as `Ctrl+Shift+ArrowDown` to a named action such
+    /// as `extendDownToEdge`. Returns false for an unknown action name.
+    pub fn set_key_binding(&mut self, chord: &str, action: &str) -> bool {
+        match Action::parse(action) {
+            Some(action) => {
+                self.keymap.bind(chord, action);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn remove_key_binding(&mut self, chord: &str) -> bool {
+        self.keymap.unbind(chord).is_some()
+    }
+
+    /// Double-clicking a header border autofits that column or row.
+    pub fn on_dblclick(&mut self, event: web_sys::MouseEvent) {
+        if let Some((axis, idx)) = self.hit_border(&event) {
+            self.autofit(axis, idx);
+            return;
+        }
+        if self.properties.edit_on_double_click && matches!(self.hit_test(&event), Hit::Cell(_, _)) {
+            self.start_editing(None);
+        }
+    }
+
+    pub fn on_h_scroll(&mut self, _: web_sys::Event) {
+        self.scroller.set_scroll_left(self.h_scroller.scroll_left());
+        self.follow_scroll();
+        self.paint();
+    }
+
+    pub fn on_v_scroll(&mut self, _: web_sys::Event) {
+        self.scroller.set_scroll_top(self.v_scroller.scroll_top());
+        self.follow_scroll();
+        self.paint();
+    }
+
+    /// Keep the placeholder over the active cell when it is frozen in place.
+    fn follow_scroll(&mut self) {
+        let (fixed_cols, fixed_rows) = self.get_fixed_counts();
+        let (col_idx, row_idx) = match &self.active_cell {
+            Some(cell) => (cell.get_col_idx(), cell.get_row_idx()),
+            None => return,
+        };
+        if self.sheet().col_manager.get_position(col_idx) <= fixed_cols || row_idx <= fixed_rows {
+            self.activate_cell(col_idx, row_idx);
+        }
+    }
+
+    /// For hosts that forward clicks rather than mousedown/mouseup; those
+    /// forwarding mousedown should not forward clicks as well.
+    pub fn on_click(&mut self, event: web_sys::MouseEvent) {
+        self.on_mousedown(event);
+        self.dragging = false;
+    }
+}
+
+/// An empty sheet sized by the sheet properties.
+fn new_sheet(name: &str, properties: &HyperSheetProperties) -> Sheet {
+    Sheet::new(
+        name,
+        ColumnManager::new(DEFAULT_COLUMN_COUNT, properties.default_column_width as u16),
+        RowManager::new(DEFAULT_ROW_COUNT, properties.default_row_height as u16),
+        properties.undo_limit as usize,
+    )
+}
+
+/// What lies under the mouse.
+enum Hit {
+    Cell(u32, u32),
+    ColumnHeader(u32),
+    RowHeader(u32),
+    Corner,
+}
+
+/// A stretch of the canvas along one axis showing either the frozen lines or
+/// the scrolled ones.
+struct Band {
+    start: f64,
+    end: f64,
+    /// Added to a content offset to get the canvas offset within the band.
+    shift: f64,
+    /// Index, canvas offset and size of each line drawn.
+    lines: Vec<(u32, f64, u16)>,
+}
+
+/// Clip drawing to where a row band and a column band cross.
+fn clip_bands(ctx: &web_sys::CanvasRenderingContext2d, rows: &Band, cols: &Band) {
+    ctx.begin_path();
+    ctx.rect(cols.start, rows.start, cols.end - cols.start, rows.end - rows.start);
+    ctx.clip();
+}
+
+/// A header border being dragged.
+struct Resize {
+    axis: Axis,
+    idx: u32,
+    /// Mouse position along the axis when the drag started.
+    origin: f64,
+    before: u16,
+    after: u16,
+    /// With `resize_column_in_place`, the next column and its width; it gives
+    /// up or takes the space so that the columns after it stay put.
+    neighbour: Option<(u32, u16)>,
+}
+
+impl Resize {
+    fn to_command(&self) -> Command {
+        let command = match self.axis {
+            Axis::Rows => Command::SetRowHeight {
+                row_idx: self.idx,
+                before: self.before,
+                after: self.after,
+            },
+            Axis::Columns => Command::SetColumnWidth {
+                col_idx: self.idx,
+                before: self.before,
+                after: self.after,
+            },
+        };
+        match self.neighbour {
+            Some((next, width)) => Command::Group(vec![
+                command,
+                Command::SetColumnWidth {
+                    col_idx: next,
+                    before: width,
+                    after: (width as i32 + self.before as i32 - self.after as i32).max(0).min(u16::MAX as i32) as u16,
+                },
+            ]),
+            None => command,
+        }
+    }
+}
+
+/// A column header being dragged to another position.
+struct Reorder {
+    col_idx: u32,
+    /// Canvas offset of the column when the drag started.
+    left: f64,
+    /// Canvas x of the mouse when the drag started, and now.
+    origin: f64,
+    x: f64,
+}
+
+/// Mark where hidden rows or columns sit with a pair of lines either side of
+/// the header border running from `from` to `to`.
+fn draw_hidden_marker(ctx: &web_sys::CanvasRenderingContext2d, from: (f64, f64), to: (f64, f64)) {
+    let (dx, dy) = if from.0 == to.0 { (1.5, 0.0) } else { (0.0, 1.5) };
+    ctx.set_line_width(1.0);
+    for side in [-1.0, 1.0].iter() {
+        ctx.begin_path();
+        ctx.move_to(from.0 + dx * side, from.1 + dy * side);
+        ctx.line_to(to.0 + dx * side, to.1 + dy * side);
+        ctx.stroke();
+    }
+}
+
+/// Cells styled on their own before a range is refused as too large.
+const MAX_STYLED_CELLS: u64 = 1_000_000;
+
+/// What styling comma-separated ranges sets a style on.
+fn get_style_targets(range: &str) -> Result<Vec<StyleTarget>, String> {
+    let mut targets = Vec::new();
+    for part in range.split(',') {
+        let range = RangeRef::parse(part).ok_or_else(|| format!("Invalid range: {}", part.trim()))?;
+        match range.kind {
+            RangeKind::Columns => targets.extend((range.start.col..=range.end.col).map(StyleTarget::Column)),
+            RangeKind::Rows => targets.extend((range.start.row..=range.end.row).map(StyleTarget::Row)),
+            RangeKind::Cells => {
+                let cols = (range.end.col - range.start.col + 1) as u64;
+                let rows = (range.end.row - range.start.row + 1) as u64;
+                if cols * rows > MAX_STYLED_CELLS {
+                    return Err(format!("Range too large to style by cell: {}", part.trim()));
+                }
+                for row_idx in range.start.row..=range.end.row {
+                    let cells = (range.start.col..=range.end.col).map(|col_idx| StyleTarget::Cell(col_idx, row_idx));
+                    targets.extend(cells);
+                }
+            }
+        }
+    }
+    Ok(targets)
+}
+
+/// Draw the border edges a style sets around `rect`.
+fn draw_borders(ctx: &web_sys::CanvasRenderingContext2d, rect: Rectangle, style: &Style) {
+    let edges = [
+        ((rect.left(), rect.top()), (rect.right(), rect.top()), (0.0, 1.0)),
+        ((rect.right(), rect.top()), (rect.right(), rect.bottom()), (-1.0, 0.0)),
+        ((rect.left(), rect.bottom()), (rect.right(), rect.bottom()), (0.0, -1.0)),
+        ((rect.left(), rect.top()), (rect.left(), rect.bottom()), (1.0, 0.0)),
+    ];
+    for (border, (from, to, inward)) in style.get_borders().iter().zip(edges.iter()) {
+        let border = match border {
+            Some(border) => border,
+            None => continue,
+        };
+        let width = border.width as f64;
+        let dash = js_sys::Array::new();
+        let (lines, line_width) = match border.style {
+            BorderStyle::Solid => (vec![0.0], width),
+            BorderStyle::Dashed => {
+                dash.push(&(width * 4.0).into());
+                dash.push(&(width * 2.0).into());
+                (vec![0.0], width)
+            }
+            BorderStyle::Dotted => {
+                dash.push(&width.into());
+                dash.push(&width.into());
+                (vec![0.0], width)
+            }
+            BorderStyle::Double => (vec![0.0, width + 1.0], 1.0),
+        };
+        ctx.set_line_dash(&dash).unwrap();
+        ctx.set_line_width(line_width);
+        ctx.set_stroke_style(&border.color.as_str().into());
+        for offset in lines {
+            ctx.begin_path();
+            ctx.move_to(from.0 + inward.0 * offset, from.1 + inward.1 * offset);
+            ctx.line_to(to.0 + inward.0 * offset, to.1 + inward.1 * offset);
+            ctx.stroke();
+        }
+    }
+    ctx.set_line_dash(&js_sys::Array::new()).unwrap();
+}
+
+/// Draw text clipped to `rect`, aligned by `halign` and vertically centred.
+/// The `clipboardData` of a copy, cut or paste event, read by name since
+/// `web_sys::ClipboardEvent` needs unstable web-sys APIs.
+fn clipboard_data(event: &web_sys::Event) -> Option<web_sys::DataTransfer> {
+    js_sys::Reflect::get(event, &"clipboardData".into()).ok()?.dyn_into().ok()
+}
+
+fn draw_text(ctx: &web_sys::CanvasRenderingContext2d, text: &str, rect: Rectangle, halign: &str, padding: f64) {
+    let x = match halign {
+        "center" => rect.left() + rect.width() / 2.0,
+        "right" => rect.right() - padding,
+        _ => rect.left() + padding,
+    };
+    ctx.save();
+    ctx.begin_path();
+    ctx.rect(rect.left(), rect.top(), rect.width(), rect.height());
+    ctx.clip();
+    ctx.set_text_align(match halign {
+        "center" => "center",
+        "right" => "right",
+        _ => "left",
+    });
+    ctx.fill_text(text, x, rect.top() + rect.height() / 2.0).unwrap();
+    ctx.restore();
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::history::Command;
+    use crate::reference::Axis;
+    use crate::Resize;
+    #[test]
+    fn resize_commands() {
+        let resize = Resize {
+            axis: Axis::Rows,
+            idx: 3,
+            origin: 0.0,
+            before: 20,
+            after: 35,
+            neighbour: None,
+        };
+        assert!(matches!(
+            resize.to_command(),
+            Command::SetRowHeight { row_idx: 3, before: 20, after: 35 }
+        ));
+
+        let resize = Resize {
+            axis: Axis::Columns,
+            idx: 2,
+            origin: 0.0,
+            before: 100,
+            after: 130,
+            neighbour: Some((3, 80)),
+        };
+        let commands = match resize.to_command() {
+            Command::Group(commands) => commands,
+            _ => panic!("expected a group"),
+        };
+        assert!(matches!(commands[0], Command::SetColumnWidth { col_idx: 2, before: 100, after: 130 }));
+        assert!(matches!(commands[1], Command::SetColumnWidth { col_idx: 3, before: 80, after: 50 }));
+
+        // Growing past the neighbour's width gives it none rather than
+        // wrapping around.
+        let resize = Resize {
+            after: 200,
+            neighbour: Some((3, 80)),
+            ..resize
+        };
+        let commands = match resize.to_command() {
+            Command::Group(commands) => commands,
+            _ => panic!("expected a group"),
+        };
+        assert!(matches!(commands[1], Command::SetColumnWidth { after: 0, .. }));
+    }
 }
@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+
+/// Entries kept before the cache starts over.
+const CACHE_LIMIT: usize = 10_000;
+
+/// Widths of text already measured, keyed by font and string, since measuring
+/// through the canvas is slow.
+pub struct TextMeasurer {
+  cache: HashMap<(String, String), f64>,
+}
+
+impl TextMeasurer {
+  pub fn new() -> Self {
+    Self { cache: HashMap::new() }
+  }
+
+  /// Width of `text` in `font`, calling `measure` only when not cached.
+  pub fn measure<F>(&mut self, font: &str, text: &str, measure: F) -> f64
+  where
+    F: FnOnce(&str, &str) -> f64,
+  {
+    let key = (font.to_string(), text.to_string());
+    if let Some(width) = self.cache.get(&key) {
+      return *width;
+    }
+    if self.cache.len() >= CACHE_LIMIT {
+      self.cache.clear();
+    }
+    let width = measure(font, text);
+    self.cache.insert(key, width);
+    width
+  }
+}
+
+/// Column width fitting the widest text, with `padding` either side, within
+/// `min` and `max`.
+pub fn fit_width<I: Iterator<Item = f64>>(widths: I, padding: f64, min: u32, max: u32) -> u16 {
+  let widest = widths.fold(0.0, f64::max);
+  let width = (widest + 2.0 * padding).ceil();
+  width.min(max as f64).max(min as f64).min(u16::MAX as f64) as u16
+}
+
+/// Row height fitting the tallest text, no less than `min`.
+pub fn fit_height<I: Iterator<Item = f64>>(heights: I, min: u32) -> u16 {
+  let tallest = heights.fold(0.0, f64::max).ceil();
+  tallest.max(min as f64).min(u16::MAX as f64) as u16
+}
+
+#[cfg(test)]
+mod tests {
+
+  use crate::measure::{fit_height, fit_width, TextMeasurer};
+  #[test]
+  fn measures_once() {
+    let mut measurer = TextMeasurer::new();
+    let mut calls = 0;
+    let mut measure = |font: &str, text: &str| {
+      measurer.measure(font, text, |_, text| {
+        calls += 1;
+        text.len() as f64 * 7.0
+      })
+    };
+    assert_eq!(measure("12px Arial", "abc"), 21.0);
+    assert_eq!(measure("12px Arial", "abc"), 21.0);
+    assert_eq!(measure("bold 12px Arial", "abc"), 21.0);
+    assert_eq!(measure("12px Arial", "abcd"), 28.0);
+    assert_eq!(calls, 3);
+
+    assert_eq!(fit_width(vec![21.0, 40.2].into_iter(), 5.0, 5, 400), 51);
+    assert_eq!(fit_width(vec![].into_iter(), 5.0, 20, 400), 20);
+    assert_eq!(fit_width(vec![900.0].into_iter(), 5.0, 5, 400), 400);
+    assert_eq!(fit_height(vec![14.4, 28.8].into_iter(), 20), 29);
+    assert_eq!(fit_height(vec![14.4].into_iter(), 20), 20);
+  }
+}
@@ -0,0 +1,103 @@
+use super::reference::{Axis, RangeRef};
+
+/// Rectangular ranges of a sheet each shown as one cell, its top-left or
+/// anchor cell. Merges never overlap; cells under a merge other than the
+/// anchor keep their contents but are not shown.
+#[derive(Clone, Default)]
+pub struct MergedCells {
+  ranges: Vec<RangeRef>,
+}
+
+impl MergedCells {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn get_ranges(&self) -> &[RangeRef] {
+    &self.ranges
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.ranges.is_empty()
+  }
+
+  pub fn clear(&mut self) {
+    self.ranges.clear();
+  }
+
+  /// The merge a cell is part of.
+  pub fn get(&self, col_idx: u32, row_idx: u32) -> Option<&RangeRef> {
+    self.ranges.iter().find(|range| range.contains(col_idx, row_idx))
+  }
+
+  /// The cell shown for a cell: the anchor of its merge, or itself.
+  pub fn get_anchor(&self, col_idx: u32, row_idx: u32) -> (u32, u32) {
+    match self.get(col_idx, row_idx) {
+      Some(range) => (range.start.col, range.start.row),
+      None => (col_idx, row_idx),
+    }
+  }
+
+  /// Merges sharing at least one cell with `range`.
+  pub fn get_overlapping(&self, range: &RangeRef) -> Vec<RangeRef> {
+    self.ranges.iter().filter(|merge| overlaps(merge, range)).copied().collect()
+  }
+
+  /// Add a merge, which must not overlap another. Single cells are not merged.
+  pub fn add(&mut self, range: RangeRef) {
+    if !range.is_cell() {
+      self.ranges.push(range);
+    }
+  }
+
+  /// Remove the merge exactly matching `range`, if any.
+  pub fn remove(&mut self, range: &RangeRef) {
+    self.ranges.retain(|merge| merge != range);
+  }
+
+  /// Move merges as lines are inserted or deleted, see `RangeRef::shift`;
+  /// merges shrink over deleted lines and are dropped once down to one cell.
+  /// Returns the merges a deletion changed, as they were.
+  pub fn shift(&mut self, axis: Axis, at: u32, delta: i64) -> Vec<RangeRef> {
+    let mut changed = vec![];
+    for merge in std::mem::take(&mut self.ranges) {
+      let shifted = merge.shift(axis, at, delta);
+      if delta < 0 && shifted != Some(merge) {
+        changed.push(merge);
+      }
+      if let Some(range) = shifted {
+        self.add(range);
+      }
+    }
+    changed
+  }
+}
+
+fn overlaps(a: &RangeRef, b: &RangeRef) -> bool {
+  a.start.col <= b.end.col && b.start.col <= a.end.col && a.start.row <= b.end.row && b.start.row <= a.end.row
+}
+
+#[cfg(test)]
+mod tests {
+
+  use crate::merge::MergedCells;
+  use crate::reference::{Axis, RangeRef};
+  #[test]
+  fn anchors_and_shift() {
+    let mut merges = MergedCells::new();
+    merges.add(RangeRef::parse("B2:C4").unwrap());
+    merges.add(RangeRef::parse("E1").unwrap());
+    assert_eq!(merges.get_ranges().len(), 1);
+    assert_eq!(merges.get_anchor(3, 4), (2, 2));
+    assert_eq!(merges.get_anchor(4, 4), (4, 4));
+    assert_eq!(merges.get_overlapping(&RangeRef::parse("A1:B2").unwrap()).len(), 1);
+    assert!(merges.get_overlapping(&RangeRef::parse("D1:D9").unwrap()).is_empty());
+
+    assert!(merges.shift(Axis::Rows, 1, 2).is_empty());
+    assert_eq!(merges.get_ranges(), [RangeRef::parse("B4:C6").unwrap()]);
+    assert_eq!(merges.shift(Axis::Columns, 3, -1), [RangeRef::parse("B4:C6").unwrap()]);
+    assert_eq!(merges.get_ranges(), [RangeRef::parse("B4:B6").unwrap()]);
+    merges.shift(Axis::Rows, 5, -2);
+    assert!(merges.is_empty());
+  }
+}
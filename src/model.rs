@@ -1,34 +1,677 @@
 use std::collections::BTreeMap;
 
 use super::cell::Cell;
+use super::dependency::DependencyGraph;
+use super::formula::{self, Expr};
+use super::reference::{shift_index, Axis, RangeKind, RangeRef};
+use super::functions::{Function, FunctionRegistry};
+use super::merge::MergedCells;
+use super::style::{SheetStyles, Style, StyleTarget};
+use super::value::{CellError, CellValue};
+
+/// What a cell holds: a formula, or else a plain value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellSnapshot {
+  pub value: CellValue,
+  pub formula: Option<String>,
+}
+
+impl CellSnapshot {
+  pub fn value(value: CellValue) -> Self {
+    Self { value, formula: None }
+  }
+
+  pub fn formula(source: &str) -> Self {
+    Self {
+      value: CellValue::Empty,
+      formula: Some(source.to_string()),
+    }
+  }
+
+  /// Content for text typed, pasted or imported into a cell: a formula when
+  /// it starts with `=`, else a value as `CellValue::parse` reads it.
+  pub fn from_text(text: &str) -> Self {
+    if text.starts_with('=') {
+      Self::formula(text)
+    } else {
+      Self::value(CellValue::parse(text))
+    }
+  }
+}
+
+/// Formulas as `(col_idx, row_idx, source)`, with their sources from before
+/// a rewrite.
+pub type RewrittenFormulas = Vec<(u32, u32, String)>;
+
+/// What `DataModel::shift_lines` deleted and rewrote.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShiftedCells {
+  pub removed: Vec<(u32, u32, CellSnapshot)>,
+  pub rewritten: RewrittenFormulas,
+  pub styles: Vec<(StyleTarget, Style)>,
+  pub merges: Vec<RangeRef>,
+}
 
 pub struct DataModel {
-  pub items: BTreeMap<String, Cell>,
+  items: BTreeMap<String, Cell>,
+  /// Number of cells in each column and row, for `get_data_extent`.
+  col_counts: BTreeMap<u32, usize>,
+  row_counts: BTreeMap<u32, usize>,
+  formulas: BTreeMap<String, Expr>,
+  graph: DependencyGraph,
+  functions: FunctionRegistry,
+  /// Values of other sheets' cells read by formulas here, by upper-case sheet
+  /// name. Kept up to date by the workbook; a missing sheet has no entry.
+  externals: BTreeMap<String, BTreeMap<(u32, u32), CellValue>>,
+  /// Values stored along with formulas reading other sheets, by cell name,
+  /// shown instead of `#REF!` while a sheet they read is missing, as when a
+  /// file refers to sheets that are not in the workbook.
+  cached: BTreeMap<String, CellValue>,
+  /// Name of the sheet the model belongs to, for formulas naming it.
+  sheet_name: String,
+  pub styles: SheetStyles,
+  pub merges: MergedCells,
 }
 
 impl DataModel {
   pub fn new() -> Self {
-    Self { items: BTreeMap::new() }
+    Self {
+      items: BTreeMap::new(),
+      col_counts: BTreeMap::new(),
+      row_counts: BTreeMap::new(),
+      formulas: BTreeMap::new(),
+      graph: DependencyGraph::new(),
+      functions: FunctionRegistry::new(),
+      externals: BTreeMap::new(),
+      cached: BTreeMap::new(),
+      sheet_name: String::new(),
+      styles: SheetStyles::new(),
+      merges: MergedCells::new(),
+    }
+  }
+
+  pub fn set_sheet_name(&mut self, name: &str) {
+    self.sheet_name = name.to_string();
+  }
+
+  /// Drop every cell and formula, keeping styles and merges.
+  pub fn clear(&mut self) {
+    self.items.clear();
+    self.col_counts.clear();
+    self.row_counts.clear();
+    self.formulas.clear();
+    self.cached.clear();
+    self.graph = DependencyGraph::new();
+  }
+
+  /// Fill cells from rows of texts starting at the given cell, then evaluate
+  /// the formulas among them. Empty texts leave cells empty.
+  pub fn load_rows(&mut self, col_idx: u32, row_idx: u32, rows: &[Vec<String>]) {
+    for (row_offset, row) in rows.iter().enumerate() {
+      for (col_offset, text) in row.iter().enumerate() {
+        let (col, row) = (col_idx + col_offset as u32, row_idx + row_offset as u32);
+        self.insert_cell(col, row, CellSnapshot::from_text(text));
+      }
+    }
+    self.recalculate_all();
+  }
+
+  /// Store a cell without evaluating anything, for bulk loads that finish
+  /// with `recalculate_all`. A formula keeps the value given with it until
+  /// then; one that does not parse keeps it for good, or shows the parse error
+  /// when there is none.
+  pub fn insert_cell(&mut self, col_idx: u32, row_idx: u32, snapshot: CellSnapshot) {
+    let mut cell = Cell::new(col_idx, row_idx);
+    self.formulas.remove(&cell.name());
+    self.cached.remove(&cell.name());
+    self.graph.remove(col_idx, row_idx);
+    match snapshot {
+      CellSnapshot {
+        value,
+        formula: Some(source),
+      } => {
+        match formula::parse(&source) {
+          Ok(expr) => {
+            self.graph.set_precedents(col_idx, row_idx, expr.references());
+            self.formulas.insert(cell.name(), expr);
+            cell.set_value(value);
+          }
+          Err(error) if value.is_empty() => cell.set_value(CellValue::Error(error)),
+          Err(_) => cell.set_value(value),
+        }
+        cell.set_formula(Some(source));
+      }
+      CellSnapshot {
+        value: CellValue::Empty, ..
+      } => {
+        self.remove_cell(col_idx, row_idx);
+        return;
+      }
+      CellSnapshot { value, .. } => cell.set_value(value),
+    }
+    self.set_cell(cell);
+  }
+
+  /// Fill cells as stored in a file, then evaluate the formulas among them.
+  /// Formulas reading other sheets keep the value stored with them while a
+  /// sheet they read is missing.
+  pub fn load_cells(&mut self, cells: &[(u32, u32, CellSnapshot)]) {
+    for (col_idx, row_idx, snapshot) in cells.iter() {
+      self.insert_cell(*col_idx, *row_idx, snapshot.clone());
+      let name = Cell::new(*col_idx, *row_idx).name();
+      let external = match self.formulas.get(&name) {
+        Some(expr) => !expr.external_references().is_empty(),
+        None => false,
+      };
+      if external && !snapshot.value.is_empty() {
+        self.cached.insert(name, snapshot.value.clone());
+      }
+    }
+    self.recalculate_all();
+  }
+
+  /// Display texts of the cells in the given block, row by row.
+  pub fn get_texts(&self, start: (u32, u32), end: (u32, u32)) -> Vec<Vec<String>> {
+    (start.1..=end.1)
+      .map(|row_idx| {
+        (start.0..=end.0)
+          .map(|col_idx| self.get_value(col_idx, row_idx).to_string())
+          .collect()
+      })
+      .collect()
   }
 
   pub fn set_cell(&mut self, cell: Cell) {
-    self.items.insert(cell.name(), cell);
+    let (col_idx, row_idx) = (cell.get_col_idx(), cell.get_row_idx());
+    if self.items.insert(cell.name(), cell).is_none() {
+      *self.col_counts.entry(col_idx).or_insert(0) += 1;
+      *self.row_counts.entry(row_idx).or_insert(0) += 1;
+    }
+  }
+
+  fn remove_cell(&mut self, col_idx: u32, row_idx: u32) {
+    if self.items.remove(&Cell::new(col_idx, row_idx).name()).is_some() {
+      uncount(&mut self.col_counts, col_idx);
+      uncount(&mut self.row_counts, row_idx);
+    }
+  }
+
+  /// Every cell holding a value or formula.
+  pub fn get_cells(&self) -> impl Iterator<Item = &Cell> {
+    self.items.values()
   }
 
-  pub fn get_cell(&self, col_idx: u8, row_idx: u16) -> Option<&Cell> {
+  pub fn get_cell(&self, col_idx: u32, row_idx: u32) -> Option<&Cell> {
     let cell = Cell::new(col_idx, row_idx);
     self.items.get(&cell.name())
   }
 
-  pub fn get_cells_within(&self, col_idx: u8, row_idx: u16) -> Vec<&Cell> {
+  /// Store a plain value, dropping any formula the cell held, and recalculate
+  /// the formulas depending on it.
+  pub fn set_value(&mut self, col_idx: u32, row_idx: u32, value: CellValue) {
+    let mut cell = Cell::new(col_idx, row_idx);
+    self.formulas.remove(&cell.name());
+    self.cached.remove(&cell.name());
+    self.graph.remove(col_idx, row_idx);
+    if value.is_empty() {
+      self.remove_cell(col_idx, row_idx);
+    } else {
+      cell.set_value(value);
+      self.set_cell(cell);
+    }
+    self.recalculate(col_idx, row_idx, false);
+  }
+
+  pub fn get_value(&self, col_idx: u32, row_idx: u32) -> &CellValue {
+    match self.get_cell(col_idx, row_idx) {
+      Some(cell) => cell.get_value(),
+      None => &CellValue::Empty,
+    }
+  }
+
+  /// Store a formula such as `=SUM(A1:B3)*2`, evaluate it and recalculate the
+  /// formulas depending on the cell. A formula that fails to parse is kept so
+  /// it can be edited, and the cell shows the parse error.
+  pub fn set_formula(&mut self, col_idx: u32, row_idx: u32, source: &str) {
+    let mut cell = Cell::new(col_idx, row_idx);
+    let name = cell.name();
+    self.cached.remove(&name);
+    cell.set_formula(Some(source.to_string()));
+    match formula::parse(source) {
+      Ok(expr) => {
+        self.graph.set_precedents(col_idx, row_idx, expr.references());
+        self.formulas.insert(name, expr);
+      }
+      Err(error) => {
+        self.graph.remove(col_idx, row_idx);
+        self.formulas.remove(&name);
+        cell.set_value(CellValue::Error(error));
+      }
+    }
+    self.set_cell(cell);
+    self.recalculate(col_idx, row_idx, true);
+  }
+
+  pub fn get_formula(&self, col_idx: u32, row_idx: u32) -> Option<&String> {
+    self.get_cell(col_idx, row_idx).and_then(|cell| cell.get_formula())
+  }
+
+  pub fn snapshot(&self, col_idx: u32, row_idx: u32) -> CellSnapshot {
+    CellSnapshot {
+      value: self.get_value(col_idx, row_idx).clone(),
+      formula: self.get_formula(col_idx, row_idx).cloned(),
+    }
+  }
+
+  /// Put back what a snapshot holds. Formulas are re-evaluated rather than
+  /// taking the captured value.
+  pub fn restore(&mut self, col_idx: u32, row_idx: u32, snapshot: &CellSnapshot) {
+    match &snapshot.formula {
+      Some(source) => self.set_formula(col_idx, row_idx, source),
+      None => self.set_value(col_idx, row_idx, snapshot.value.clone()),
+    }
+  }
+
+  /// Empty every occupied cell within a range and recalculate once. Returns
+  /// what the emptied cells held.
+  pub fn clear_range(&mut self, range: &RangeRef) -> Vec<(u32, u32, CellSnapshot)> {
+    let cells: Vec<(u32, u32, CellSnapshot)> = self
+      .items
+      .values()
+      .filter(|cell| range.contains(cell.get_col_idx(), cell.get_row_idx()))
+      .map(|cell| (cell.get_col_idx(), cell.get_row_idx()))
+      .collect::<Vec<_>>()
+      .into_iter()
+      .map(|(col_idx, row_idx)| (col_idx, row_idx, self.snapshot(col_idx, row_idx)))
+      .collect();
+    for (col_idx, row_idx, _) in cells.iter() {
+      self.insert_cell(*col_idx, *row_idx, CellSnapshot::value(CellValue::Empty));
+    }
+    if !cells.is_empty() {
+      self.recalculate_all();
+    }
+    cells
+  }
+
+  pub fn get_functions(&self) -> &FunctionRegistry {
+    &self.functions
+  }
+
+  /// Add or replace a formula function and re-evaluate every formula so that
+  /// cells already calling it pick it up.
+  pub fn register_function(&mut self, name: &str, function: Function) {
+    self.functions.register(name, function, false);
+    self.recalculate_all();
+  }
+
+  /// Sheets and ranges the formulas here read from other sheets.
+  pub fn get_external_references(&self) -> Vec<(String, RangeRef)> {
+    self.formulas.values().flat_map(|expr| expr.external_references()).collect()
+  }
+
+  /// Values of the cells holding something within any of the ranges.
+  pub fn get_values_within(&self, ranges: &[RangeRef]) -> BTreeMap<(u32, u32), CellValue> {
+    self
+      .items
+      .values()
+      .filter(|cell| {
+        ranges
+          .iter()
+          .any(|range| range.contains(cell.get_col_idx(), cell.get_row_idx()))
+      })
+      .map(|cell| ((cell.get_col_idx(), cell.get_row_idx()), cell.get_value().clone()))
+      .collect()
+  }
+
+  /// Take the values read from another sheet, or `None` when there is no such
+  /// sheet, and re-evaluate the formulas reading them if they changed. Returns
+  /// whether they did.
+  pub fn set_external_values(&mut self, sheet: &str, values: Option<BTreeMap<(u32, u32), CellValue>>) -> bool {
+    let key = sheet.to_uppercase();
+    if self.externals.get(&key) == values.as_ref() {
+      return false;
+    }
+    match values {
+      Some(values) => self.externals.insert(key.clone(), values),
+      None => self.externals.remove(&key),
+    };
+    let readers: Vec<(u32, u32)> = self
+      .formulas
+      .iter()
+      .filter(|(_, expr)| {
+        expr
+          .external_references()
+          .iter()
+          .any(|(name, _)| name.to_uppercase() == key)
+      })
+      .filter_map(|(name, _)| self.items.get(name))
+      .map(|cell| (cell.get_col_idx(), cell.get_row_idx()))
+      .collect();
+    for (col_idx, row_idx) in readers {
+      self.recalculate(col_idx, row_idx, true);
+    }
+    true
+  }
+
+  /// Value of a cell on another sheet, `#REF!` when there is no such sheet.
+  pub fn get_external_value(&self, sheet: &str, col_idx: u32, row_idx: u32) -> CellValue {
+    match self.externals.get(&sheet.to_uppercase()) {
+      Some(values) => values.get(&(col_idx, row_idx)).cloned().unwrap_or(CellValue::Empty),
+      None => CellValue::Error(CellError::Ref),
+    }
+  }
+
+  /// Values of a range on another sheet row by row, stopping whole columns
+  /// and rows at the last value held.
+  pub fn get_external_range(&self, sheet: &str, range: RangeRef) -> Result<Vec<Vec<CellValue>>, CellError> {
+    let values = self.externals.get(&sheet.to_uppercase()).ok_or(CellError::Ref)?;
+    let (max_col, max_row) = values
+      .keys()
+      .fold((0, 0), |(max_col, max_row), (col, row)| (max_col.max(*col), max_row.max(*row)));
+    let (mut end_col, mut end_row) = (range.end.col, range.end.row);
+    match range.kind {
+      RangeKind::Columns => end_row = max_row.max(range.start.row),
+      RangeKind::Rows => end_col = max_col.max(range.start.col),
+      RangeKind::Cells => (),
+    }
+    Ok(
+      (range.start.row..=end_row)
+        .map(|row| {
+          (range.start.col..=end_col)
+            .map(|col| values.get(&(col, row)).cloned().unwrap_or(CellValue::Empty))
+            .collect()
+        })
+        .collect(),
+    )
+  }
+
+  /// Rewrite the source of every formula, re-evaluating them all. Returns
+  /// the formulas changed, with their sources as they were.
+  pub fn rewrite_formulas<F: FnMut(&str) -> String>(&mut self, mut f: F) -> RewrittenFormulas {
+    let mut changed = vec![];
+    let sources: Vec<(u32, u32, String)> = self
+      .items
+      .values()
+      .filter_map(|cell| Some((cell.get_col_idx(), cell.get_row_idx(), cell.get_formula()?.clone())))
+      .collect();
+    for (col_idx, row_idx, source) in sources {
+      let rewritten = f(&source);
+      if rewritten != source {
+        let value = self.get_value(col_idx, row_idx).clone();
+        self.insert_cell(col_idx, row_idx, CellSnapshot { value, formula: Some(rewritten) });
+        changed.push((col_idx, row_idx, source));
+      }
+    }
+    self.recalculate_all();
+    changed
+  }
+
+  /// Insert `delta` rows or columns before `at`, or with a negative `delta`
+  /// delete them starting at `at`, moving the cells after them. References
+  /// to this sheet follow the cells they point at, and those to deleted cells
+  /// become `#REF!`, and styles and merges move along. Returns the deleted
+  /// cells and styles, the merges changed, and the formulas rewritten with
+  /// their sources as they were, all at their positions before.
+  pub fn shift_lines(&mut self, axis: Axis, at: u32, delta: i64) -> ShiftedCells {
+    let sheet_name = self.sheet_name.to_uppercase();
+    let mut removed = vec![];
+    let mut rewritten = vec![];
+    let mut cells = vec![];
+    let mut cached = std::mem::take(&mut self.cached);
+    for cell in std::mem::take(&mut self.items).into_values() {
+      let (col_idx, row_idx) = (cell.get_col_idx(), cell.get_row_idx());
+      let mut snapshot = CellSnapshot {
+        value: cell.get_value().clone(),
+        formula: cell.get_formula().cloned(),
+      };
+      let moved = match axis {
+        Axis::Rows => shift_index(row_idx, at, delta).map(|row| (col_idx, row)),
+        Axis::Columns => shift_index(col_idx, at, delta).map(|col| (col, row_idx)),
+      };
+      let (col, row) = match moved {
+        Some(position) => position,
+        None => {
+          removed.push((col_idx, row_idx, snapshot));
+          continue;
+        }
+      };
+      if let Some(source) = snapshot.formula.take() {
+        let shifted = formula::rewrite_references(&source, |sheet, range| match sheet {
+          Some(sheet) if sheet.to_uppercase() != sheet_name => Some(range),
+          _ => range.shift(axis, at, delta),
+        });
+        if shifted != source {
+          rewritten.push((col_idx, row_idx, source));
+        }
+        snapshot.formula = Some(shifted);
+      }
+      cells.push((col, row, snapshot, cached.remove(&cell.name())));
+    }
+    self.clear();
+    for (col_idx, row_idx, snapshot, kept) in cells {
+      self.insert_cell(col_idx, row_idx, snapshot);
+      if let Some(value) = kept {
+        self.cached.insert(Cell::new(col_idx, row_idx).name(), value);
+      }
+    }
+    self.recalculate_all();
+    let styles = self.styles.shift(axis, at, delta);
+    let merges = self.merges.shift(axis, at, delta);
+    ShiftedCells {
+      removed,
+      rewritten,
+      styles,
+      merges,
+    }
+  }
+
+  pub fn recalculate_all(&mut self) {
+    let (order, cyclic) = self.graph.full_order();
+    self.apply_order(order, cyclic);
+  }
+
+  /// Re-evaluate the formulas calling volatile functions such as `NOW`, and
+  /// the formulas depending on them. Returns whether there were any.
+  pub fn recalculate_volatile(&mut self) -> bool {
+    let functions = &self.functions;
+    let cells: Vec<(u32, u32)> = self
+      .formulas
+      .iter()
+      .filter(|(_, expr)| expr.is_volatile(functions))
+      .filter_map(|(name, _)| self.items.get(name))
+      .map(|cell| (cell.get_col_idx(), cell.get_row_idx()))
+      .collect();
+    if cells.is_empty() {
+      return false;
+    }
+    let (order, cyclic) = self.graph.recalc_order_from(&cells, true);
+    self.apply_order(order, cyclic);
+    true
+  }
+
+  /// Re-evaluate the formulas affected by a change to the given cell in
+  /// dependency order. Formulas caught in a reference cycle evaluate to `#REF!`.
+  fn recalculate(&mut self, col_idx: u32, row_idx: u32, include_self: bool) {
+    let (order, cyclic) = self.graph.recalc_order(col_idx, row_idx, include_self);
+    self.apply_order(order, cyclic);
+  }
+
+  fn apply_order(&mut self, order: Vec<(u32, u32)>, cyclic: Vec<(u32, u32)>) {
+    for (col, row) in order {
+      let name = Cell::new(col, row).name();
+      let value = match (self.formulas.get(&name), self.cached.get(&name)) {
+        (Some(expr), Some(value)) if self.reads_missing_sheet(expr) => value.clone(),
+        (Some(expr), _) => formula::evaluate(expr, self),
+        (None, _) => continue,
+      };
+      if let Some(cell) = self.items.get_mut(&name) {
+        cell.set_value(value);
+      }
+    }
+    for (col, row) in cyclic {
+      if let Some(cell) = self.items.get_mut(&Cell::new(col, row).name()) {
+        cell.set_value(CellValue::Error(CellError::Ref));
+      }
+    }
+  }
+
+  fn reads_missing_sheet(&self, expr: &Expr) -> bool {
+    expr
+      .external_references()
+      .iter()
+      .any(|(sheet, _)| !self.externals.contains_key(&sheet.to_uppercase()))
+  }
+
+  /// Largest column and row index holding a cell.
+  pub fn get_data_extent(&self) -> (u32, u32) {
+    let last = |counts: &BTreeMap<u32, usize>| counts.keys().next_back().copied().unwrap_or(0);
+    (last(&self.col_counts), last(&self.row_counts))
+  }
+
+  /// Cell reached by moving from the given one in the direction of the delta
+  /// as Ctrl+Arrow does: to the end of the block of filled cells it is in, or
+  /// else to the start of the next block, or else to the edge of the grid.
+  pub fn get_data_edge(
+    &self,
+    col_idx: u32,
+    row_idx: u32,
+    delta_col: i64,
+    delta_row: i64,
+    col_count: u32,
+    row_count: u32,
+  ) -> (u32, u32) {
+    let step = |(col, row): (u32, u32)| {
+      let col = col as i64 + delta_col;
+      let row = row as i64 + delta_row;
+      if col < 1 || row < 1 || col > col_count as i64 || row > row_count as i64 {
+        None
+      } else {
+        Some((col as u32, row as u32))
+      }
+    };
+    let filled = |(col, row): (u32, u32)| !self.get_value(col, row).is_empty();
+    let mut current = (col_idx, row_idx);
+    let mut next = match step(current) {
+      Some(next) => next,
+      None => return current,
+    };
+    if filled(current) && filled(next) {
+      while filled(next) {
+        current = next;
+        next = match step(current) {
+          Some(next) => next,
+          None => break,
+        };
+      }
+      return current;
+    }
+    let (max_col, max_row) = self.get_data_extent();
+    current = next;
+    while !filled(current) {
+      if (delta_col > 0 && current.0 >= max_col) || (delta_row > 0 && current.1 >= max_row) {
+        let col = if delta_col > 0 { col_count } else { current.0 };
+        let row = if delta_row > 0 { row_count } else { current.1 };
+        return (col, row);
+      }
+      current = match step(current) {
+        Some(next) => next,
+        None => break,
+      };
+    }
+    current
+  }
+
+  pub fn get_cells_within(&self, col_idx: u32, row_idx: u32) -> Vec<&Cell> {
     self.items.values().filter(|x| x.get_col_idx() <= col_idx && x.get_row_idx() <= row_idx).collect()
   }
 
-  pub fn get_cells_within_rows(&self, row_idx: u16) -> Vec<&Cell> {
+  pub fn get_cells_within_rows(&self, row_idx: u32) -> Vec<&Cell> {
     self.items.values().filter(|x| x.get_row_idx() <= row_idx).collect()
   }
 
-  pub fn get_cells_within_cols(&self, col_idx: u8) -> Vec<&Cell> {
+  pub fn get_cells_within_cols(&self, col_idx: u32) -> Vec<&Cell> {
     self.items.values().filter(|x| x.get_col_idx() <= col_idx).collect()
   }
 }
+
+fn uncount(counts: &mut BTreeMap<u32, usize>, idx: u32) {
+  if let Some(count) = counts.get_mut(&idx) {
+    *count -= 1;
+    if *count == 0 {
+      counts.remove(&idx);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::DataModel;
+  use crate::value::CellValue;
+  use std::cell::Cell;
+  use std::rc::Rc;
+  #[test]
+  fn data_edges() {
+    let mut model = DataModel::new();
+    for row in 2..=4 {
+      model.set_value(1, row, CellValue::Number(row as f64));
+    }
+    model.set_value(1, 8, CellValue::Number(8.0));
+    assert_eq!(model.get_data_edge(1, 1, 0, 1, 10, 100), (1, 2));
+    assert_eq!(model.get_data_edge(1, 2, 0, 1, 10, 100), (1, 4));
+    assert_eq!(model.get_data_edge(1, 4, 0, 1, 10, 100), (1, 8));
+    assert_eq!(model.get_data_edge(1, 8, 0, 1, 10, 100), (1, 100));
+    assert_eq!(model.get_data_edge(1, 8, 0, -1, 10, 100), (1, 4));
+    assert_eq!(model.get_data_edge(1, 3, -1, 0, 10, 100), (1, 3));
+    assert_eq!(model.get_data_edge(1, 3, 1, 0, 10, 100), (10, 3));
+    assert_eq!(model.get_data_edge(5, 3, -1, 0, 10, 100), (1, 3));
+
+    model.set_value(3, 4, CellValue::Number(1.0));
+    assert_eq!(model.get_data_extent(), (3, 8));
+    model.set_value(3, 4, CellValue::Number(2.0));
+    model.set_value(1, 8, CellValue::Empty);
+    assert_eq!(model.get_data_extent(), (3, 4));
+    model.set_value(3, 4, CellValue::Empty);
+    assert_eq!(model.get_data_extent(), (1, 4));
+    model.clear();
+    assert_eq!(model.get_data_extent(), (0, 0));
+  }
+
+  #[test]
+  fn load_rows() {
+    let mut model = DataModel::new();
+    let rows = vec![
+      vec!["=B2*2".to_string(), "TRUE".to_string()],
+      vec!["".to_string(), "1.5".to_string(), "2020-10-18".to_string()],
+    ];
+    model.load_rows(1, 1, &rows);
+    assert_eq!(model.get_value(1, 1), &CellValue::Number(3.0));
+    assert_eq!(model.get_value(2, 1), &CellValue::Boolean(true));
+    assert_eq!(model.get_value(3, 2), &CellValue::DateTime(44122.0));
+    assert!(model.get_cell(1, 2).is_none());
+    assert_eq!(model.get_texts((1, 1), (3, 2)), vec![vec!["3", "TRUE", ""], vec!["", "1.5", "2020-10-18"]]);
+  }
+
+  #[test]
+  fn volatile_formulas() {
+    let mut model = DataModel::new();
+    let ticks = Rc::new(Cell::new(0.0));
+    let counter = ticks.clone();
+    let tick = move |_: &[crate::formula::Operand]| {
+      counter.set(counter.get() + 1.0);
+      CellValue::Number(counter.get())
+    };
+    model.functions.register("TICK", Rc::new(tick), true);
+    model.set_formula(1, 1, "=TICK()");
+    model.set_formula(1, 2, "=A1*10");
+    model.set_formula(1, 3, "=5");
+    assert_eq!(model.get_value(1, 2), &CellValue::Number(10.0));
+    assert!(model.recalculate_volatile());
+    assert_eq!(model.get_value(1, 1), &CellValue::Number(2.0));
+    assert_eq!(model.get_value(1, 2), &CellValue::Number(20.0));
+    assert_eq!(ticks.get(), 2.0);
+
+    model.set_value(1, 1, CellValue::Empty);
+    assert!(!model.recalculate_volatile());
+  }
+}
@@ -1,3 +1,7 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct HyperSheetProperties {
   pub no_date_message: String,
   pub wheel_h_factor: f32,
@@ -70,6 +74,8 @@ pub struct HyperSheetProperties {
   pub fixed_lines_vwidth: u32,
   pub fixed_lines_vcolor: String,
   pub box_sizing: String,
+  pub row_header_width: u32,
+  pub column_header_height: u32,
   pub default_row_height: u32,
   pub default_column_width: u32,
   pub minimum_column_width: u32,
@@ -129,6 +135,7 @@ pub struct HyperSheetProperties {
   pub restore_row_selections: bool,
   pub restore_column_selections: bool,
   pub truncate_text_with_ellipsis: bool,
+  pub undo_limit: u32,
 }
 
 impl HyperSheetProperties {
@@ -205,8 +212,10 @@ impl HyperSheetProperties {
       fixed_lines_vwidth: 2,
       fixed_lines_vcolor: "rgb(164,164,164)".to_string(),
       box_sizing: "content-box".to_string(),
-      default_row_height: 14,
-      default_column_width: 100,
+      row_header_width: 40,
+      column_header_height: 20,
+      default_row_height: 20,
+      default_column_width: 85,
       minimum_column_width: 5,
       resize_column_in_place: false,
       repaint_interval_rate: 60,
@@ -264,6 +273,40 @@ impl HyperSheetProperties {
       restore_row_selections: true,
       restore_column_selections: true,
       truncate_text_with_ellipsis: true,
+      undo_limit: 100,
+    }
+  }
+
+  /// Override the properties present in `overrides`, a JSON object using the
+  /// camelCase property names. Everything else keeps its current value.
+  pub fn merge(&mut self, overrides: serde_json::Value) -> Result<(), serde_json::Error> {
+    let mut current = serde_json::to_value(&*self)?;
+    match (current.as_object_mut(), overrides) {
+      (Some(current), serde_json::Value::Object(overrides)) => {
+        for (key, value) in overrides {
+          current.insert(key, value);
+        }
+      }
+      _ => return Err(serde::de::Error::custom("properties must be an object")),
     }
+    *self = serde_json::from_value(current)?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::HyperSheetProperties;
+  #[test]
+  fn merge_partial() {
+    let mut props = HyperSheetProperties::default();
+    props.merge(serde_json::json!({ "defaultRowHeight": 24, "gridLinesHcolor": "#ccc" })).unwrap();
+    assert_eq!(props.default_row_height, 24);
+    assert_eq!(props.grid_lines_hcolor, "#ccc");
+    assert_eq!(props.default_column_width, 85);
+    assert!(props.merge(serde_json::json!({ "defaultRowHeigth": 24 })).is_err());
+    assert!(props.merge(serde_json::json!({ "defaultRowHeight": "tall" })).is_err());
+    assert_eq!(props.default_row_height, 24);
   }
 }
@@ -0,0 +1,468 @@
+/// Largest index a whole-column or whole-row range extends to.
+pub const MAX_INDEX: u32 = u32::MAX;
+
+/// Convert a 1-based column index to its letters, e.g. `28` to `AB`.
+pub fn column_name(col_idx: u32) -> String {
+  let mut dividend = col_idx;
+  let mut column_name = String::new();
+  let mut modulo: u32;
+
+  while dividend > 0 {
+    modulo = (dividend - 1) % 26;
+    column_name = [((65 + modulo) as u8 as char).to_string(), column_name].concat();
+    dividend = (dividend - modulo) / 26;
+  }
+  column_name
+}
+
+/// Convert column letters to a 1-based index, e.g. `AB` to `28`.
+pub fn column_index(name: &str) -> Option<u32> {
+  if name.is_empty() {
+    return None;
+  }
+  let mut col: u32 = 0;
+  for ch in name.chars() {
+    if !ch.is_ascii_alphabetic() {
+      return None;
+    }
+    col = col.checked_mul(26)?.checked_add(ch.to_ascii_uppercase() as u32 - 64)?;
+  }
+  Some(col)
+}
+
+fn row_index(digits: &str) -> Option<u32> {
+  if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+    return None;
+  }
+  match digits.parse::<u32>() {
+    Ok(0) | Err(_) => None,
+    Ok(row) => Some(row),
+  }
+}
+
+fn strip_dollar(text: &str) -> (bool, &str) {
+  match text.strip_prefix('$') {
+    Some(rest) => (true, rest),
+    None => (false, text),
+  }
+}
+
+fn offset(base: u32, delta: i64) -> Option<u32> {
+  let idx = base as i64 + delta;
+  if idx < 1 || idx > MAX_INDEX as i64 {
+    None
+  } else {
+    Some(idx as u32)
+  }
+}
+
+/// Parse one R1C1 axis such as `R`, `R3` or `R[-2]` following its letter.
+/// Returns the resolved index and whether it was absolute.
+fn parse_r1c1_axis(text: &str, base: u32) -> Option<(u32, bool)> {
+  if text.is_empty() {
+    Some((base, false))
+  } else if text.starts_with('[') && text.ends_with(']') {
+    let delta = text[1..text.len() - 1].parse::<i64>().ok()?;
+    Some((offset(base, delta)?, false))
+  } else {
+    Some((row_index(text)?, true))
+  }
+}
+
+fn format_r1c1_axis(letter: char, idx: u32, absolute: bool, base: u32) -> String {
+  if absolute {
+    format!("{}{}", letter, idx)
+  } else if idx == base {
+    letter.to_string()
+  } else {
+    format!("{}[{}]", letter, idx as i64 - base as i64)
+  }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CellRef {
+  pub col: u32,
+  pub row: u32,
+  pub col_absolute: bool,
+  pub row_absolute: bool,
+}
+
+impl CellRef {
+  pub fn new(col: u32, row: u32) -> Self {
+    Self {
+      col,
+      row,
+      col_absolute: false,
+      row_absolute: false,
+    }
+  }
+
+  /// Parse `A1`, `$A1`, `A$1` or `$A$1`.
+  pub fn parse(text: &str) -> Option<Self> {
+    let (col_absolute, rest) = strip_dollar(text.trim());
+    let split = rest.find(|c: char| !c.is_ascii_alphabetic())?;
+    let (letters, rest) = rest.split_at(split);
+    let (row_absolute, digits) = strip_dollar(rest);
+    Some(Self {
+      col: column_index(letters)?,
+      row: row_index(digits)?,
+      col_absolute,
+      row_absolute,
+    })
+  }
+
+  /// Parse `R2C3`, `R[-1]C[2]`, `RC[1]` etc. Relative parts resolve against
+  /// `base`, given as (column, row).
+  pub fn parse_r1c1(text: &str, base: (u32, u32)) -> Option<Self> {
+    let text = text.trim().to_ascii_uppercase();
+    if !text.starts_with('R') {
+      return None;
+    }
+    let split = text.find('C')?;
+    let (row, row_absolute) = parse_r1c1_axis(&text[1..split], base.1)?;
+    let (col, col_absolute) = parse_r1c1_axis(&text[split + 1..], base.0)?;
+    Some(Self {
+      col,
+      row,
+      col_absolute,
+      row_absolute,
+    })
+  }
+
+  pub fn to_a1(self) -> String {
+    format!(
+      "{}{}{}{}",
+      if self.col_absolute { "$" } else { "" },
+      column_name(self.col),
+      if self.row_absolute { "$" } else { "" },
+      self.row
+    )
+  }
+
+  pub fn to_r1c1(self, base: (u32, u32)) -> String {
+    [
+      format_r1c1_axis('R', self.row, self.row_absolute, base.1),
+      format_r1c1_axis('C', self.col, self.col_absolute, base.0),
+    ]
+    .concat()
+  }
+}
+
+/// Rows or columns, for inserting and deleting lines of the grid.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Axis {
+  Rows,
+  Columns,
+}
+
+/// Where an index ends up after `delta` lines are inserted before `at`, or
+/// with a negative `delta` deleted starting at `at`. `None` when deleted.
+pub fn shift_index(idx: u32, at: u32, delta: i64) -> Option<u32> {
+  let removed_end = at as i64 - delta;
+  if (idx as i64) < at as i64 {
+    Some(idx)
+  } else if delta >= 0 {
+    Some(idx.saturating_add(delta as u32))
+  } else if (idx as i64) < removed_end {
+    None
+  } else {
+    Some((idx as i64 + delta) as u32)
+  }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RangeKind {
+  Cells,
+  Columns,
+  Rows,
+}
+
+/// A rectangular block of cells. Whole-column ranges span every row and
+/// whole-row ranges span every column. `start` is always the top-left corner.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RangeRef {
+  pub start: CellRef,
+  pub end: CellRef,
+  pub kind: RangeKind,
+}
+
+impl RangeRef {
+  pub fn new(start: CellRef, end: CellRef) -> Self {
+    Self::with_kind(start, end, RangeKind::Cells)
+  }
+
+  pub fn cell(col: u32, row: u32) -> Self {
+    Self::new(CellRef::new(col, row), CellRef::new(col, row))
+  }
+
+  /// Whole columns `a` through `b`, in either order.
+  pub fn columns(a: u32, b: u32) -> Self {
+    Self::with_kind(CellRef::new(a, 1), CellRef::new(b, MAX_INDEX), RangeKind::Columns)
+  }
+
+  /// Whole rows `a` through `b`, in either order.
+  pub fn rows(a: u32, b: u32) -> Self {
+    Self::with_kind(CellRef::new(1, a), CellRef::new(MAX_INDEX, b), RangeKind::Rows)
+  }
+
+  fn with_kind(a: CellRef, b: CellRef, kind: RangeKind) -> Self {
+    let (left, right) = if a.col <= b.col { (a, b) } else { (b, a) };
+    let (top, bottom) = if a.row <= b.row { (a, b) } else { (b, a) };
+    Self {
+      start: CellRef {
+        col: left.col,
+        row: top.row,
+        col_absolute: left.col_absolute,
+        row_absolute: top.row_absolute,
+      },
+      end: CellRef {
+        col: right.col,
+        row: bottom.row,
+        col_absolute: right.col_absolute,
+        row_absolute: bottom.row_absolute,
+      },
+      kind,
+    }
+  }
+
+  /// Parse `A1`, `AA10:AC20`, `C:C`, `$B:$D`, `3:3` or `$2:5`.
+  pub fn parse(text: &str) -> Option<Self> {
+    let text = text.trim();
+    let (lhs, rhs) = match text.find(':') {
+      Some(split) => (&text[..split], &text[split + 1..]),
+      None => {
+        let cell = CellRef::parse(text)?;
+        return Some(Self::new(cell, cell));
+      }
+    };
+    if let (Some(a), Some(b)) = (CellRef::parse(lhs), CellRef::parse(rhs)) {
+      return Some(Self::new(a, b));
+    }
+    let (lhs_absolute, lhs) = strip_dollar(lhs.trim());
+    let (rhs_absolute, rhs) = strip_dollar(rhs.trim());
+    if let (Some(a), Some(b)) = (column_index(lhs), column_index(rhs)) {
+      let start = CellRef {
+        col: a,
+        row: 1,
+        col_absolute: lhs_absolute,
+        row_absolute: false,
+      };
+      let end = CellRef {
+        col: b,
+        row: MAX_INDEX,
+        col_absolute: rhs_absolute,
+        row_absolute: false,
+      };
+      return Some(Self::with_kind(start, end, RangeKind::Columns));
+    }
+    if let (Some(a), Some(b)) = (row_index(lhs), row_index(rhs)) {
+      let start = CellRef {
+        col: 1,
+        row: a,
+        col_absolute: false,
+        row_absolute: lhs_absolute,
+      };
+      let end = CellRef {
+        col: MAX_INDEX,
+        row: b,
+        col_absolute: false,
+        row_absolute: rhs_absolute,
+      };
+      return Some(Self::with_kind(start, end, RangeKind::Rows));
+    }
+    None
+  }
+
+  /// Parse A1 notation, or else R1C1 notation relative to `base`.
+  pub fn parse_any(text: &str, base: (u32, u32)) -> Option<Self> {
+    Self::parse(text).or_else(|| Self::parse_r1c1(text, base))
+  }
+
+  /// Parse `R1C1:R2C2`, a single `R1C1` cell, whole column `C3` / `C[1]` or
+  /// whole row `R3` / `R[-1]`, resolving relative parts against `base`.
+  pub fn parse_r1c1(text: &str, base: (u32, u32)) -> Option<Self> {
+    let text = text.trim().to_ascii_uppercase();
+    if let Some(split) = text.find(':') {
+      let (a, b) = (&text[..split], &text[split + 1..]);
+      if let (Some(a), Some(b)) = (CellRef::parse_r1c1(a, base), CellRef::parse_r1c1(b, base)) {
+        return Some(Self::new(a, b));
+      }
+      let a = Self::parse_r1c1(a, base)?;
+      let b = Self::parse_r1c1(b, base)?;
+      if a.kind != b.kind || a.kind == RangeKind::Cells {
+        return None;
+      }
+      return Some(Self::with_kind(a.start, b.end, a.kind));
+    }
+    if let Some(cell) = CellRef::parse_r1c1(&text, base) {
+      return Some(Self::new(cell, cell));
+    }
+    if let Some(rest) = text.strip_prefix('C') {
+      let (col, absolute) = parse_r1c1_axis(rest, base.0)?;
+      let start = CellRef {
+        col,
+        row: 1,
+        col_absolute: absolute,
+        row_absolute: false,
+      };
+      let end = CellRef { row: MAX_INDEX, ..start };
+      return Some(Self::with_kind(start, end, RangeKind::Columns));
+    }
+    if let Some(rest) = text.strip_prefix('R') {
+      let (row, absolute) = parse_r1c1_axis(rest, base.1)?;
+      let start = CellRef {
+        col: 1,
+        row,
+        col_absolute: false,
+        row_absolute: absolute,
+      };
+      let end = CellRef { col: MAX_INDEX, ..start };
+      return Some(Self::with_kind(start, end, RangeKind::Rows));
+    }
+    None
+  }
+
+  pub fn is_cell(&self) -> bool {
+    self.kind == RangeKind::Cells && self.start.col == self.end.col && self.start.row == self.end.row
+  }
+
+  pub fn contains(&self, col_idx: u32, row_idx: u32) -> bool {
+    self.start.col <= col_idx && col_idx <= self.end.col && self.start.row <= row_idx && row_idx <= self.end.row
+  }
+
+  /// The range after lines are inserted or deleted as for `shift_index`.
+  /// Ranges grow over lines inserted inside them and shrink over deleted
+  /// ones; `None` when every line of the range is deleted. Whole columns are
+  /// unaffected by rows, and whole rows by columns.
+  pub fn shift(&self, axis: Axis, at: u32, delta: i64) -> Option<Self> {
+    let (start, end) = match (axis, self.kind) {
+      (Axis::Rows, RangeKind::Columns) | (Axis::Columns, RangeKind::Rows) => return Some(*self),
+      (Axis::Rows, _) => (self.start.row, self.end.row),
+      (Axis::Columns, _) => (self.start.col, self.end.col),
+    };
+    let (new_start, new_end) = match (shift_index(start, at, delta), shift_index(end, at, delta)) {
+      (Some(start), Some(end)) => (start, end),
+      (None, Some(end)) => (at, end),
+      (Some(start), None) => (start, at - 1),
+      (None, None) => return None,
+    };
+    if new_end < new_start {
+      return None;
+    }
+    let mut range = *self;
+    match axis {
+      Axis::Rows => {
+        range.start.row = new_start;
+        range.end.row = new_end;
+      }
+      Axis::Columns => {
+        range.start.col = new_start;
+        range.end.col = new_end;
+      }
+    }
+    Some(range)
+  }
+
+  pub fn to_a1(self) -> String {
+    let dollar = |absolute: bool| if absolute { "$" } else { "" };
+    match self.kind {
+      RangeKind::Cells if self.is_cell() => self.start.to_a1(),
+      RangeKind::Cells => [self.start.to_a1(), self.end.to_a1()].join(":"),
+      RangeKind::Columns => format!(
+        "{}{}:{}{}",
+        dollar(self.start.col_absolute),
+        column_name(self.start.col),
+        dollar(self.end.col_absolute),
+        column_name(self.end.col)
+      ),
+      RangeKind::Rows => format!(
+        "{}{}:{}{}",
+        dollar(self.start.row_absolute),
+        self.start.row,
+        dollar(self.end.row_absolute),
+        self.end.row
+      ),
+    }
+  }
+
+  pub fn to_r1c1(self, base: (u32, u32)) -> String {
+    let (start, end) = match self.kind {
+      RangeKind::Cells if self.is_cell() => return self.start.to_r1c1(base),
+      RangeKind::Cells => (self.start.to_r1c1(base), self.end.to_r1c1(base)),
+      RangeKind::Columns => (
+        format_r1c1_axis('C', self.start.col, self.start.col_absolute, base.0),
+        format_r1c1_axis('C', self.end.col, self.end.col_absolute, base.0),
+      ),
+      RangeKind::Rows => (
+        format_r1c1_axis('R', self.start.row, self.start.row_absolute, base.1),
+        format_r1c1_axis('R', self.end.row, self.end.row_absolute, base.1),
+      ),
+    };
+    if start == end {
+      start
+    } else {
+      [start, end].join(":")
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::{column_index, column_name, Axis, CellRef, RangeKind, RangeRef, MAX_INDEX};
+  #[test]
+  fn a1_round_trip() {
+    assert_eq!(column_index("IW"), Some(257));
+    assert_eq!(column_name(16384), "XFD");
+    let cell = CellRef::parse("$B$2").unwrap();
+    assert_eq!((cell.col, cell.row, cell.col_absolute, cell.row_absolute), (2, 2, true, true));
+    for text in &["A1", "$B$2", "AA10:AC20", "C:C", "$B:D", "3:3", "$2:5"] {
+      assert_eq!(RangeRef::parse(text).unwrap().to_a1(), *text);
+    }
+    let range = RangeRef::parse("AC20:AA10").unwrap();
+    assert_eq!(range.to_a1(), "AA10:AC20");
+    let column = RangeRef::parse("C:C").unwrap();
+    assert_eq!((column.kind, column.end.row), (RangeKind::Columns, MAX_INDEX));
+    assert!(column.contains(3, 1_000_000));
+    assert_eq!(RangeRef::parse("A0"), None);
+    assert_eq!(RangeRef::parse("1A"), None);
+  }
+
+  #[test]
+  fn r1c1_round_trip() {
+    let base = (3, 5);
+    let cell = CellRef::parse_r1c1("R[-1]C[2]", base).unwrap();
+    assert_eq!((cell.col, cell.row), (5, 4));
+    assert_eq!(cell.to_r1c1(base), "R[-1]C[2]");
+    assert_eq!(CellRef::parse_r1c1("RC", base).unwrap().to_a1(), "C5");
+    assert_eq!(CellRef::parse_r1c1("R2C3", base).unwrap().to_a1(), "$C$2");
+    for text in &["R1C1:R2C2", "RC[1]", "C3", "R[-1]", "C1:C[2]"] {
+      assert_eq!(RangeRef::parse_r1c1(text, base).unwrap().to_r1c1(base), *text);
+    }
+    assert_eq!(RangeRef::parse_r1c1("R1C1:R2C2", base).unwrap().to_a1(), "$A$1:$B$2");
+    assert_eq!(RangeRef::parse_r1c1("R[-5]C", base), None);
+    assert_eq!(RangeRef::parse_any("B2:C3", base).unwrap().to_a1(), "B2:C3");
+    assert_eq!(RangeRef::parse_any("R[1]C[-1]", base).unwrap().to_a1(), "B6");
+    assert_eq!(RangeRef::parse_any("R2C3:R[1]C", base).unwrap().to_a1(), "$C$2:C6");
+    assert_eq!(RangeRef::parse_any("nowhere", base), None);
+  }
+
+  #[test]
+  fn shifts() {
+    let shift = |text: &str, axis: Axis, at: u32, delta: i64| {
+      RangeRef::parse(text).unwrap().shift(axis, at, delta).map(|range| range.to_a1())
+    };
+    assert_eq!(shift("$B$2:C5", Axis::Rows, 3, 2), Some("$B$2:C7".to_string()));
+    assert_eq!(shift("B2:C5", Axis::Rows, 2, 2), Some("B4:C7".to_string()));
+    assert_eq!(shift("B2:C5", Axis::Rows, 6, 2), Some("B2:C5".to_string()));
+    assert_eq!(shift("B2:C5", Axis::Columns, 1, 1), Some("C2:D5".to_string()));
+    assert_eq!(shift("B:B", Axis::Rows, 1, 5), Some("B:B".to_string()));
+    assert_eq!(shift("2:4", Axis::Rows, 1, 1), Some("3:5".to_string()));
+    assert_eq!(shift("B2:C5", Axis::Rows, 3, -2), Some("B2:C3".to_string()));
+    assert_eq!(shift("B2:C5", Axis::Rows, 1, -3), Some("B1:C2".to_string()));
+    assert_eq!(shift("B2:C5", Axis::Rows, 4, -5), Some("B2:C3".to_string()));
+    assert_eq!(shift("B3", Axis::Rows, 3, -1), None);
+    assert_eq!(shift("B2:C5", Axis::Columns, 2, -2), None);
+    assert_eq!(shift("B7", Axis::Rows, 3, -2), Some("B5".to_string()));
+  }
+}
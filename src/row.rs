@@ -1,13 +1,25 @@
 use std::collections::BTreeMap;
 
+use super::reference::shift_index;
+use super::sizeindex::{step_shown, SizeIndex};
+
 pub struct Row {
-  idx: u16,
+  idx: u32,
   height: u16,
+  hidden: bool,
 }
 
 impl Row {
-  pub fn new(idx: u16) -> Self {
-    Self { idx, height: 20 }
+  pub fn new(idx: u32) -> Self {
+    Self {
+      idx,
+      height: 20,
+      hidden: false,
+    }
+  }
+
+  pub fn get_idx(&self) -> u32 {
+    self.idx
   }
 
   pub fn get_height(&self) -> u16 {
@@ -17,31 +29,185 @@ impl Row {
   pub fn set_height(&mut self, height: u16) {
     self.height = height;
   }
+
+  pub fn is_hidden(&self) -> bool {
+    self.hidden
+  }
+
+  pub fn set_hidden(&mut self, hidden: bool) {
+    self.hidden = hidden;
+  }
+
+  /// Size taken up on screen: none when hidden.
+  fn get_visible_height(&self) -> u16 {
+    if self.hidden {
+      0
+    } else {
+      self.height
+    }
+  }
 }
 
 pub struct RowManager {
-  items: BTreeMap<u16, Row>,
+  items: BTreeMap<u32, Row>,
+  row_count: u32,
+  default_height: u16,
+  index: SizeIndex,
 }
 
 impl RowManager {
-  pub fn new() -> Self {
-    Self { items: BTreeMap::new() }
+  pub fn new(row_count: u32, default_height: u16) -> Self {
+    Self {
+      items: BTreeMap::new(),
+      row_count,
+      default_height,
+      index: SizeIndex::new(row_count, default_height),
+    }
+  }
+
+  fn rebuild_index(&mut self) {
+    let heights = self.items.values().map(|row| (row.idx, row.get_visible_height()));
+    self.index = SizeIndex::build(self.row_count, self.default_height, heights);
   }
 
   pub fn set_row(&mut self, row: Row) {
+    let delta = row.get_visible_height() as i64 - self.get_height(row.idx) as i64;
+    self.index.add(row.idx, delta);
     self.items.insert(row.idx, row);
   }
 
-  pub fn get_row(&self, idx: u16) -> Option<&Row> {
+  pub fn get_row(&self, idx: u32) -> Option<&Row> {
     self.items.get(&idx)
   }
 
-  pub fn get_rows_within(&self, row_idx: u16) -> Vec<&Row> {
+  /// Size of the row on screen, zero when hidden.
+  pub fn get_height(&self, idx: u32) -> u16 {
+    match self.items.get(&idx) {
+      Some(row) => row.get_visible_height(),
+      None => self.default_height,
+    }
+  }
+
+  /// Size of the row when shown.
+  pub fn get_shown_height(&self, idx: u32) -> u16 {
+    match self.items.get(&idx) {
+      Some(row) => row.height,
+      None => self.default_height,
+    }
+  }
+
+  pub fn is_hidden(&self, idx: u32) -> bool {
+    matches!(self.items.get(&idx), Some(row) if row.hidden)
+  }
+
+  /// Hide or show a row, keeping its size for when it is shown.
+  pub fn set_hidden(&mut self, idx: u32, hidden: bool) {
+    let mut row = Row::new(idx);
+    row.height = self.get_shown_height(idx);
+    row.hidden = hidden;
+    self.set_row(row);
+  }
+
+  pub fn get_default_height(&self) -> u16 {
+    self.default_height
+  }
+
+  pub fn set_default_height(&mut self, default_height: u16) {
+    self.default_height = default_height;
+    self.rebuild_index();
+  }
+
+  /// Move custom sizes as rows are inserted or deleted, see `shift_index`.
+  /// Sizes pushed past the last row are dropped. Returns the sizes of the
+  /// deleted rows, and whether they were hidden.
+  pub fn shift(&mut self, at: u32, delta: i64) -> Vec<(u32, u16, bool)> {
+    let mut removed = vec![];
+    let items = std::mem::take(&mut self.items);
+    for (idx, mut item) in items {
+      match shift_index(idx, at, delta) {
+        Some(new_idx) if new_idx <= self.row_count => {
+          item.idx = new_idx;
+          self.items.insert(new_idx, item);
+        }
+        Some(_) => (),
+        None => removed.push((idx, item.height, item.hidden)),
+      }
+    }
+    self.rebuild_index();
+    removed
+  }
+
+  pub fn get_row_count(&self) -> u32 {
+    self.row_count
+  }
+
+  pub fn set_row_count(&mut self, row_count: u32) {
+    self.row_count = row_count;
+    self.rebuild_index();
+  }
+
+  /// Top offset of the row.
+  pub fn get_offset(&self, idx: u32) -> f64 {
+    self.index.end_offset(idx.saturating_sub(1))
+  }
+
+  /// Number of rows ending before `offset`, with the offset where they end.
+  pub fn get_rows_before(&self, offset: f64) -> (u32, f64) {
+    self.index.find(offset)
+  }
+
+  /// Row `delta` shown rows away from `idx`, skipping hidden ones and
+  /// stopping at the last shown row before an edge.
+  pub fn step_visible(&self, idx: u32, delta: i64) -> u32 {
+    step_shown(idx, delta, self.row_count, |idx| self.get_height(idx) > 0)
+  }
+
+  /// Total height of all rows.
+  pub fn get_extent(&self) -> f64 {
+    self.index.end_offset(self.row_count)
+  }
+
+  pub fn get_rows_within(&self, row_idx: u32) -> Vec<&Row> {
     let mut results = vec![];
-    let rows: Vec<&u16> = self.items.keys().filter(|x| x <= &&row_idx).collect();
+    let rows: Vec<&u32> = self.items.keys().filter(|x| x <= &&row_idx).collect();
     for row in rows {
       results.push(self.items.get(row).unwrap());
     }
     results
   }
 }
+
+#[cfg(test)]
+mod tests {
+
+  use crate::row::{Row, RowManager};
+  #[test]
+  fn hidden_rows() {
+    let mut rows = RowManager::new(10, 20);
+    let mut row = Row::new(3);
+    row.set_height(50);
+    rows.set_row(row);
+    rows.set_hidden(3, true);
+    rows.set_hidden(4, true);
+    assert!(rows.is_hidden(3) && rows.is_hidden(4) && !rows.is_hidden(5));
+    assert_eq!((rows.get_height(3), rows.get_shown_height(3)), (0, 50));
+    assert_eq!((rows.get_height(4), rows.get_shown_height(4)), (0, 20));
+    assert_eq!(rows.get_offset(3), 40.0);
+    assert_eq!(rows.get_offset(5), 40.0);
+    assert_eq!(rows.get_offset(6), 60.0);
+    assert_eq!(rows.get_extent(), 160.0);
+    assert_eq!(rows.get_rows_before(45.0), (4, 40.0));
+
+    assert_eq!(rows.step_visible(2, 1), 5);
+    assert_eq!(rows.step_visible(5, -1), 2);
+    assert_eq!(rows.step_visible(2, 3), 7);
+    assert_eq!(rows.step_visible(9, 5), 10);
+    rows.set_hidden(1, true);
+    assert_eq!(rows.step_visible(2, -1), 2);
+
+    rows.set_hidden(3, false);
+    assert_eq!(rows.get_height(3), 50);
+    assert_eq!(rows.get_offset(5), 70.0);
+    assert_eq!(rows.step_visible(2, 1), 3);
+  }
+}
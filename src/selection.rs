@@ -0,0 +1,208 @@
+use super::reference::{CellRef, RangeKind, RangeRef};
+
+/// The selected cells: one or more ranges, the last of which is the one being
+/// extended. The anchor is where that range was started and the focus is the
+/// corner it has been extended to. The active cell starts at the anchor and
+/// can be stepped through the ranges without changing them.
+pub struct Selection {
+  anchor: (u32, u32),
+  focus: (u32, u32),
+  active: (u32, u32),
+  active_range: usize,
+  kind: RangeKind,
+  ranges: Vec<RangeRef>,
+}
+
+impl Selection {
+  pub fn new() -> Self {
+    Self {
+      anchor: (1, 1),
+      focus: (1, 1),
+      active: (1, 1),
+      active_range: 0,
+      kind: RangeKind::Cells,
+      ranges: vec![RangeRef::cell(1, 1)],
+    }
+  }
+
+  /// Select a single cell, either replacing the selection or, with `add`,
+  /// starting a new range next to the existing ones.
+  pub fn select_cell(&mut self, col_idx: u32, row_idx: u32, add: bool) {
+    self.start((col_idx, row_idx), RangeKind::Cells, RangeRef::cell(col_idx, row_idx), add);
+  }
+
+  /// Select a whole column. The active cell moves to its first row.
+  pub fn select_column(&mut self, col_idx: u32, add: bool) {
+    self.start((col_idx, 1), RangeKind::Columns, RangeRef::columns(col_idx, col_idx), add);
+  }
+
+  /// Select a whole row. The active cell moves to its first column.
+  pub fn select_row(&mut self, row_idx: u32, add: bool) {
+    self.start((1, row_idx), RangeKind::Rows, RangeRef::rows(row_idx, row_idx), add);
+  }
+
+  fn start(&mut self, anchor: (u32, u32), kind: RangeKind, range: RangeRef, add: bool) {
+    if !add {
+      self.ranges.clear();
+    }
+    self.anchor = anchor;
+    self.focus = anchor;
+    self.active = anchor;
+    self.active_range = self.ranges.len();
+    self.kind = kind;
+    self.ranges.push(range);
+  }
+
+  /// Stretch the current range from the anchor to the given cell. Column and
+  /// row selections only follow the matching coordinate.
+  pub fn extend_to(&mut self, col_idx: u32, row_idx: u32) {
+    self.focus = (col_idx, row_idx);
+    let range = match self.kind {
+      RangeKind::Cells => RangeRef::new(
+        CellRef::new(self.anchor.0, self.anchor.1),
+        CellRef::new(col_idx, row_idx),
+      ),
+      RangeKind::Columns => RangeRef::columns(self.anchor.0, col_idx),
+      RangeKind::Rows => RangeRef::rows(self.anchor.1, row_idx),
+    };
+    match self.ranges.last_mut() {
+      Some(last) => *last = range,
+      None => self.ranges.push(range),
+    }
+  }
+
+  /// Step the active cell through the selected cells: rows first when moving
+  /// left or right, columns first when moving up or down, wrapping from one
+  /// range to the next. A single selected cell simply moves in the direction
+  /// given, collapsing the selection onto the new cell.
+  pub fn advance(&mut self, delta_col: i64, delta_row: i64, col_count: u32, row_count: u32) {
+    if self.is_single_cell() {
+      let clamp = |idx: u32, delta: i64, count: u32| (idx as i64 + delta).max(1).min(count.max(1) as i64) as u32;
+      let col_idx = clamp(self.active.0, delta_col, col_count);
+      let row_idx = clamp(self.active.1, delta_row, row_count);
+      self.select_cell(col_idx, row_idx, false);
+      return;
+    }
+    let bounds = |range: &RangeRef| {
+      let (left, top) = (range.start.col as u64, range.start.row as u64);
+      let right = range.end.col.min(col_count).max(range.start.col) as u64;
+      let bottom = range.end.row.min(row_count).max(range.start.row) as u64;
+      (left, top, right - left + 1, bottom - top + 1)
+    };
+    let by_row = delta_col != 0;
+    let forward = delta_col > 0 || delta_row > 0;
+    let mut range_idx = self.active_range.min(self.ranges.len() - 1);
+    let (left, top, width, height) = bounds(&self.ranges[range_idx]);
+    let (col, row) = (self.active.0 as u64 - left, self.active.1 as u64 - top);
+    let position = if by_row { row * width + col } else { col * height + row };
+    let position = if forward {
+      if position + 1 < width * height {
+        position + 1
+      } else {
+        range_idx = (range_idx + 1) % self.ranges.len();
+        0
+      }
+    } else if position > 0 {
+      position - 1
+    } else {
+      range_idx = (range_idx + self.ranges.len() - 1) % self.ranges.len();
+      let (_, _, width, height) = bounds(&self.ranges[range_idx]);
+      width * height - 1
+    };
+    let (left, top, width, height) = bounds(&self.ranges[range_idx]);
+    let (col, row) = if by_row {
+      (position % width, position / width)
+    } else {
+      (position / height, position % height)
+    };
+    self.active = ((left + col) as u32, (top + row) as u32);
+    self.active_range = range_idx;
+  }
+
+  /// The active cell, where editing and typing go.
+  pub fn get_active(&self) -> (u32, u32) {
+    self.active
+  }
+
+  pub fn get_focus(&self) -> (u32, u32) {
+    self.focus
+  }
+
+  pub fn get_ranges(&self) -> &[RangeRef] {
+    &self.ranges
+  }
+
+  /// Whether the selection is nothing more than the active cell.
+  pub fn is_single_cell(&self) -> bool {
+    self.ranges.len() == 1 && self.ranges[0].is_cell()
+  }
+
+  /// Whether any selected range covers part of the column.
+  pub fn spans_column(&self, col_idx: u32) -> bool {
+    self.ranges.iter().any(|range| range.start.col <= col_idx && col_idx <= range.end.col)
+  }
+
+  /// Whether any selected range covers part of the row.
+  pub fn spans_row(&self, row_idx: u32) -> bool {
+    self.ranges.iter().any(|range| range.start.row <= row_idx && row_idx <= range.end.row)
+  }
+
+  /// The selected ranges in A1 notation, comma separated.
+  pub fn to_a1(&self) -> String {
+    self.ranges.iter().map(|range| range.to_a1()).collect::<Vec<String>>().join(",")
+  }
+
+  /// The selected ranges in R1C1 notation, comma separated, relative to the
+  /// active cell.
+  pub fn to_r1c1(&self) -> String {
+    let ranges = self.ranges.iter().map(|range| range.to_r1c1(self.active));
+    ranges.collect::<Vec<String>>().join(",")
+  }
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::Selection;
+  #[test]
+  fn extend_and_add() {
+    let mut selection = Selection::new();
+    selection.select_cell(2, 2, false);
+    selection.extend_to(3, 4);
+    assert_eq!(selection.to_a1(), "B2:C4");
+    assert_eq!(selection.get_active(), (2, 2));
+    selection.extend_to(1, 1);
+    assert_eq!(selection.to_a1(), "A1:B2");
+    selection.select_cell(5, 5, true);
+    selection.extend_to(4, 6);
+    assert_eq!(selection.to_a1(), "A1:B2,D5:E6");
+    assert_eq!(selection.to_r1c1(), "R[-4]C[-4]:R[-3]C[-3],RC[-1]:R[1]C");
+    selection.select_column(3, false);
+    selection.extend_to(1, 7);
+    assert_eq!(selection.to_a1(), "A:C");
+    assert!(selection.spans_row(9) && selection.spans_column(2) && !selection.spans_column(4));
+    selection.select_row(4, false);
+    assert_eq!(selection.get_active(), (1, 4));
+    assert!(!selection.is_single_cell());
+  }
+
+  #[test]
+  fn advance_within_ranges() {
+    let mut selection = Selection::new();
+    selection.select_cell(2, 2, false);
+    selection.advance(0, 1, 100, 100);
+    assert_eq!(selection.get_active(), (2, 3));
+    assert_eq!(selection.to_a1(), "B3");
+    selection.extend_to(3, 4);
+    selection.advance(1, 0, 100, 100);
+    assert_eq!(selection.get_active(), (3, 3));
+    selection.advance(1, 0, 100, 100);
+    assert_eq!(selection.get_active(), (2, 4));
+    selection.advance(0, 1, 100, 100);
+    assert_eq!(selection.get_active(), (3, 3));
+    selection.select_cell(9, 9, true);
+    selection.advance(0, -1, 100, 100);
+    assert_eq!(selection.get_active(), (3, 4));
+    assert_eq!(selection.to_a1(), "B3:C4,I9");
+  }
+}
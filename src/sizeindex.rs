@@ -0,0 +1,129 @@
+/// Fenwick tree over the difference between each row's (or column's) size and
+/// the default size, so that the offset of an index and the index at an offset
+/// are both answered in O(log n).
+pub struct SizeIndex {
+  default_size: i64,
+  tree: Vec<i64>,
+}
+
+impl SizeIndex {
+  pub fn new(len: u32, default_size: u16) -> Self {
+    Self {
+      default_size: default_size as i64,
+      tree: vec![0; len as usize + 1],
+    }
+  }
+
+  /// Build an index from the sizes that differ from the default.
+  pub fn build<I>(len: u32, default_size: u16, sizes: I) -> Self
+  where
+    I: Iterator<Item = (u32, u16)>,
+  {
+    let mut index = Self::new(len, default_size);
+    for (idx, size) in sizes {
+      if idx >= 1 && idx <= len {
+        index.tree[idx as usize] += size as i64 - index.default_size;
+      }
+    }
+    let len = len as usize;
+    for i in 1..=len {
+      let parent = i + (i & i.wrapping_neg());
+      if parent <= len {
+        index.tree[parent] += index.tree[i];
+      }
+    }
+    index
+  }
+
+  pub fn len(&self) -> u32 {
+    (self.tree.len() - 1) as u32
+  }
+
+  /// Record that the size at `idx` changed by `delta`.
+  pub fn add(&mut self, idx: u32, delta: i64) {
+    let mut i = idx as usize;
+    while i > 0 && i < self.tree.len() {
+      self.tree[i] += delta;
+      i += i & i.wrapping_neg();
+    }
+  }
+
+  /// Total size of indices `1..=idx`. Past the last index the default size is
+  /// assumed to continue.
+  pub fn end_offset(&self, idx: u32) -> f64 {
+    let mut sum = idx as i64 * self.default_size;
+    let mut i = idx.min(self.len()) as usize;
+    while i > 0 {
+      sum += self.tree[i];
+      i -= i & i.wrapping_neg();
+    }
+    sum as f64
+  }
+
+  /// Largest `k` whose end offset lies before `offset`, with that end offset.
+  /// Past the last index the default size is assumed to continue.
+  pub fn find(&self, offset: f64) -> (u32, f64) {
+    let len = self.tree.len() - 1;
+    let mut pos = 0;
+    let mut acc = 0;
+    let mut step = if len == 0 { 0 } else { 1 << (63 - (len as u64).leading_zeros()) };
+    while step > 0 {
+      let next = pos + step;
+      if next <= len && ((next as i64 * self.default_size + acc + self.tree[next]) as f64) < offset {
+        pos = next;
+        acc += self.tree[next];
+      }
+      step >>= 1;
+    }
+    let mut end = (pos as i64 * self.default_size + acc) as f64;
+    let mut pos = pos as u32;
+    if pos as usize == len && self.default_size > 0 && end < offset {
+      let extra = ((offset - end) / self.default_size as f64).ceil() as u32 - 1;
+      pos += extra;
+      end += extra as f64 * self.default_size as f64;
+    }
+    (pos, end)
+  }
+}
+
+/// Position `delta` shown lines away from `pos` among `count`, skipping
+/// those `is_shown` rejects and stopping at the last shown line before an
+/// edge.
+pub fn step_shown<F: Fn(u32) -> bool>(pos: u32, delta: i64, count: u32, is_shown: F) -> u32 {
+  let mut current = pos;
+  let mut probe = pos as i64;
+  let mut remaining = delta.abs();
+  while remaining > 0 {
+    probe += delta.signum();
+    if probe < 1 || probe > count as i64 {
+      break;
+    }
+    if is_shown(probe as u32) {
+      current = probe as u32;
+      remaining -= 1;
+    }
+  }
+  current
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::SizeIndex;
+  #[test]
+  fn offsets() {
+    let mut index = SizeIndex::build(1000, 20, vec![(3, 40), (5, 0)].into_iter());
+    assert_eq!(index.end_offset(2), 40.0);
+    assert_eq!(index.end_offset(5), 100.0);
+    assert_eq!(index.end_offset(1000), 20000.0);
+    assert_eq!(index.find(10.0), (0, 0.0));
+    assert_eq!(index.find(40.0), (1, 20.0));
+    assert_eq!(index.find(41.0), (2, 40.0));
+    assert_eq!(index.find(100.0), (3, 80.0));
+    assert_eq!(index.find(101.0), (5, 100.0));
+    index.add(3, -20);
+    assert_eq!(index.end_offset(1000), 19980.0);
+    assert_eq!(index.end_offset(1003), 20040.0);
+    assert_eq!(index.find(20_050.0), (1003, 20040.0));
+  }
+}
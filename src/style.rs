@@ -0,0 +1,427 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use super::format::NumberFormat;
+use super::reference::{shift_index, Axis};
+
+/// Extra padding per indent level, in pixels.
+pub const INDENT_WIDTH: f64 = 10.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HAlign {
+  Left,
+  Center,
+  Right,
+}
+
+impl HAlign {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      HAlign::Left => "left",
+      HAlign::Center => "center",
+      HAlign::Right => "right",
+    }
+  }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VAlign {
+  Top,
+  Middle,
+  Bottom,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BorderStyle {
+  Solid,
+  Dashed,
+  Dotted,
+  Double,
+}
+
+/// One edge of a cell's border.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct Border {
+  pub width: u16,
+  pub style: BorderStyle,
+  pub color: String,
+}
+
+impl Default for Border {
+  fn default() -> Self {
+    Self {
+      width: 1,
+      style: BorderStyle::Solid,
+      color: "black".to_string(),
+    }
+  }
+}
+
+/// Appearance of a cell, row or column. Attributes left unset fall through
+/// to the next style in the cascade, and finally to the sheet properties.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct Style {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub font_family: Option<String>,
+  /// In pixels.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub font_size: Option<u16>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub bold: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub italic: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub color: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub background: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub halign: Option<HAlign>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub valign: Option<VAlign>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub border_top: Option<Border>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub border_right: Option<Border>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub border_bottom: Option<Border>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub border_left: Option<Border>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub wrap: Option<bool>,
+  /// In levels of `INDENT_WIDTH`.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub indent: Option<u16>,
+  /// An Excel format code, see `NumberFormat`.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub number_format: Option<String>,
+}
+
+impl Style {
+  pub fn is_empty(&self) -> bool {
+    *self == Style::default()
+  }
+
+  /// This style with the attributes set in `over` replacing its own.
+  pub fn overlay(&self, over: &Style) -> Style {
+    Style {
+      font_family: over.font_family.clone().or_else(|| self.font_family.clone()),
+      font_size: over.font_size.or(self.font_size),
+      bold: over.bold.or(self.bold),
+      italic: over.italic.or(self.italic),
+      color: over.color.clone().or_else(|| self.color.clone()),
+      background: over.background.clone().or_else(|| self.background.clone()),
+      halign: over.halign.or(self.halign),
+      valign: over.valign.or(self.valign),
+      border_top: over.border_top.clone().or_else(|| self.border_top.clone()),
+      border_right: over.border_right.clone().or_else(|| self.border_right.clone()),
+      border_bottom: over.border_bottom.clone().or_else(|| self.border_bottom.clone()),
+      border_left: over.border_left.clone().or_else(|| self.border_left.clone()),
+      wrap: over.wrap.or(self.wrap),
+      indent: over.indent.or(self.indent),
+      number_format: over.number_format.clone().or_else(|| self.number_format.clone()),
+    }
+  }
+
+  /// CSS font for the style, filling in from `default`, a CSS font such as
+  /// `bold 13px Tahoma, sans-serif`.
+  pub fn get_font(&self, default: &str) -> String {
+    if self.font_family.is_none() && self.font_size.is_none() && self.bold.is_none() && self.italic.is_none() {
+      return default.to_string();
+    }
+    let (italic, bold, size, family) = parse_font(default);
+    let mut font = String::new();
+    if self.italic.unwrap_or(italic) {
+      font.push_str("italic ");
+    }
+    if self.bold.unwrap_or(bold) {
+      font.push_str("bold ");
+    }
+    let size = self.font_size.map(|size| size as f64).unwrap_or(size);
+    let family = self.font_family.as_deref().unwrap_or(family);
+    font.push_str(&format!("{}px {}", size, family));
+    font
+  }
+
+  /// Font size in pixels, falling back on that of `default`.
+  pub fn get_font_size(&self, default: &str) -> f64 {
+    match self.font_size {
+      Some(size) => size as f64,
+      None => parse_font(default).2,
+    }
+  }
+
+  pub fn get_borders(&self) -> [&Option<Border>; 4] {
+    [&self.border_top, &self.border_right, &self.border_bottom, &self.border_left]
+  }
+}
+
+/// Whether a CSS font is italic and bold, its size in pixels and its family.
+/// Sizes in other units count as 13px.
+fn parse_font(font: &str) -> (bool, bool, f64, &str) {
+  let (mut italic, mut bold) = (false, false);
+  let mut rest = font.trim();
+  while let Some(end) = rest.find(' ') {
+    let word = &rest[..end];
+    if let Some(size) = word.strip_suffix("px").and_then(|size| size.parse().ok()) {
+      return (italic, bold, size, rest[end..].trim());
+    }
+    match word {
+      "italic" | "oblique" => italic = true,
+      "bold" | "bolder" | "600" | "700" | "800" | "900" => bold = true,
+      _ if word.chars().next().map(|c| c.is_ascii_digit()).unwrap_or(false) => {
+        return (italic, bold, 13.0, rest[end..].trim());
+      }
+      _ => (),
+    }
+    rest = rest[end..].trim_start();
+  }
+  (italic, bold, 13.0, rest)
+}
+
+fn update<K: Ord>(map: &mut BTreeMap<K, u32>, key: K, id: Option<u32>) {
+  match id {
+    Some(id) => {
+      map.insert(key, id);
+    }
+    None => {
+      map.remove(&key);
+    }
+  }
+}
+
+/// Break text into lines no wider than `width` at spaces, as measured by
+/// `measure`. Words wider than a line get a line of their own.
+pub fn wrap_words<F: Fn(&str) -> f64>(text: &str, width: f64, measure: F) -> Vec<String> {
+  let mut lines = vec![];
+  for paragraph in text.split('\n') {
+    let mut line = String::new();
+    for word in paragraph.split(' ') {
+      if line.is_empty() {
+        line.push_str(word);
+        continue;
+      }
+      let candidate = format!("{} {}", line, word);
+      if measure(&candidate) <= width {
+        line = candidate;
+      } else {
+        lines.push(std::mem::replace(&mut line, word.to_string()));
+      }
+    }
+    lines.push(line);
+  }
+  lines
+}
+
+/// What a style is set on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StyleTarget {
+  Cell(u32, u32),
+  Row(u32),
+  Column(u32),
+}
+
+/// Styles set on the cells, rows and columns of a sheet, each distinct style
+/// kept once in a table and referred to by id. A cell is drawn with its
+/// column's style, overlaid by its row's and then its own.
+#[derive(Clone, Default)]
+pub struct SheetStyles {
+  table: Vec<Style>,
+  /// Number format of each style in the table, parsed once.
+  formats: Vec<Option<NumberFormat>>,
+  ids: HashMap<Style, u32>,
+  cells: BTreeMap<(u32, u32), u32>,
+  rows: BTreeMap<u32, u32>,
+  cols: BTreeMap<u32, u32>,
+}
+
+impl SheetStyles {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn intern(&mut self, style: Style) -> u32 {
+    if let Some(id) = self.ids.get(&style) {
+      return *id;
+    }
+    let id = self.table.len() as u32;
+    let format = style.number_format.as_deref().and_then(|code| NumberFormat::parse(code).ok());
+    self.formats.push(format);
+    self.table.push(style.clone());
+    self.ids.insert(style, id);
+    id
+  }
+
+  fn lookup(&self, id: Option<&u32>) -> Style {
+    match id {
+      Some(id) => self.table[*id as usize].clone(),
+      None => Style::default(),
+    }
+  }
+
+  /// Style set directly on a target, empty when there is none.
+  pub fn get(&self, target: StyleTarget) -> Style {
+    match target {
+      StyleTarget::Cell(col_idx, row_idx) => self.lookup(self.cells.get(&(col_idx, row_idx))),
+      StyleTarget::Row(row_idx) => self.lookup(self.rows.get(&row_idx)),
+      StyleTarget::Column(col_idx) => self.lookup(self.cols.get(&col_idx)),
+    }
+  }
+
+  /// Set the style of a target; an empty style removes it.
+  pub fn set(&mut self, target: StyleTarget, style: Style) {
+    let id = if style.is_empty() { None } else { Some(self.intern(style)) };
+    match target {
+      StyleTarget::Cell(col_idx, row_idx) => update(&mut self.cells, (col_idx, row_idx), id),
+      StyleTarget::Row(row_idx) => update(&mut self.rows, row_idx, id),
+      StyleTarget::Column(col_idx) => update(&mut self.cols, col_idx, id),
+    }
+  }
+
+  /// Style a cell is drawn with, after the cascade.
+  pub fn resolve(&self, col_idx: u32, row_idx: u32) -> Style {
+    let (col, row, cell) = (
+      self.cols.get(&col_idx),
+      self.rows.get(&row_idx),
+      self.cells.get(&(col_idx, row_idx)),
+    );
+    match (col, row, cell) {
+      (None, None, None) => Style::default(),
+      (_, None, None) => self.lookup(col),
+      _ => self.lookup(col).overlay(&self.lookup(row)).overlay(&self.lookup(cell)),
+    }
+  }
+
+  /// Number format a cell is shown with, from the most specific style
+  /// setting one; `None` when there is none or its code is invalid.
+  pub fn get_number_format(&self, col_idx: u32, row_idx: u32) -> Option<&NumberFormat> {
+    let ids = [self.cells.get(&(col_idx, row_idx)), self.rows.get(&row_idx), self.cols.get(&col_idx)];
+    let id = ids.iter().flatten().find(|id| self.table[***id as usize].number_format.is_some())?;
+    self.formats[**id as usize].as_ref()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.cells.is_empty() && self.rows.is_empty() && self.cols.is_empty()
+  }
+
+  pub fn clear(&mut self) {
+    *self = Self::new();
+  }
+
+  /// Move styles as lines are inserted or deleted, see `shift_index`.
+  /// Returns the styles of deleted lines and of cells in them.
+  pub fn shift(&mut self, axis: Axis, at: u32, delta: i64) -> Vec<(StyleTarget, Style)> {
+    let mut removed = vec![];
+    for ((col_idx, row_idx), id) in std::mem::take(&mut self.cells) {
+      let moved = match axis {
+        Axis::Rows => shift_index(row_idx, at, delta).map(|row| (col_idx, row)),
+        Axis::Columns => shift_index(col_idx, at, delta).map(|col| (col, row_idx)),
+      };
+      match moved {
+        Some(key) => {
+          self.cells.insert(key, id);
+        }
+        None => removed.push((StyleTarget::Cell(col_idx, row_idx), self.table[id as usize].clone())),
+      }
+    }
+    let lines = match axis {
+      Axis::Rows => &mut self.rows,
+      Axis::Columns => &mut self.cols,
+    };
+    let target = match axis {
+      Axis::Rows => StyleTarget::Row,
+      Axis::Columns => StyleTarget::Column,
+    };
+    for (idx, id) in std::mem::take(lines) {
+      match shift_index(idx, at, delta) {
+        Some(idx) => {
+          lines.insert(idx, id);
+        }
+        None => removed.push((target(idx), self.table[id as usize].clone())),
+      }
+    }
+    removed
+  }
+}
+
+#[cfg(test)]
+mod tests {
+
+  use crate::reference::Axis;
+  use crate::style::{parse_font, wrap_words, HAlign, SheetStyles, Style, StyleTarget};
+  use crate::value::CellValue;
+  #[test]
+  fn cascade_and_shift() {
+    let mut styles = SheetStyles::new();
+    let bold = Style {
+      bold: Some(true),
+      ..Style::default()
+    };
+    let red = Style {
+      color: Some("red".to_string()),
+      bold: Some(false),
+      ..Style::default()
+    };
+    styles.set(StyleTarget::Column(2), bold.clone());
+    styles.set(StyleTarget::Cell(1, 1), bold.clone());
+    styles.set(StyleTarget::Row(3), red.clone());
+    assert_eq!(styles.table.len(), 2);
+    assert_eq!(styles.resolve(2, 1), bold);
+    assert_eq!(styles.resolve(2, 3), red);
+    styles.set(StyleTarget::Cell(2, 3), Style {
+      halign: Some(HAlign::Right),
+      bold: Some(true),
+      ..Style::default()
+    });
+    let resolved = styles.resolve(2, 3);
+    assert_eq!((resolved.bold, resolved.color.as_deref()), (Some(true), Some("red")));
+
+    let removed = styles.shift(Axis::Rows, 3, -1);
+    assert_eq!(removed.len(), 2);
+    assert!(removed.contains(&(StyleTarget::Row(3), red)));
+    assert_eq!(styles.get(StyleTarget::Cell(1, 1)), bold);
+    styles.shift(Axis::Columns, 1, 2);
+    assert_eq!(styles.get(StyleTarget::Column(4)), bold);
+    assert_eq!(styles.resolve(3, 1), bold);
+    styles.set(StyleTarget::Cell(3, 1), Style::default());
+    styles.set(StyleTarget::Column(4), Style::default());
+    assert!(styles.is_empty());
+
+    let percent = Style {
+      number_format: Some("0%".to_string()),
+      ..Style::default()
+    };
+    styles.set(StyleTarget::Column(1), percent);
+    styles.set(StyleTarget::Cell(1, 2), bold);
+    styles.set(StyleTarget::Row(3), Style {
+      number_format: Some("0.0\"".to_string()),
+      ..Style::default()
+    });
+    let format = styles.get_number_format(1, 2).unwrap();
+    assert_eq!(format.format(&CellValue::Number(0.5)).0, "50%");
+    assert!(styles.get_number_format(1, 3).is_none());
+    assert!(styles.get_number_format(2, 2).is_none());
+  }
+
+  #[test]
+  fn fonts_and_wrapping() {
+    assert_eq!(parse_font("13px Tahoma, Geneva"), (false, false, 13.0, "Tahoma, Geneva"));
+    assert_eq!(parse_font("italic bold 12px Arial"), (true, true, 12.0, "Arial"));
+    let style = Style {
+      bold: Some(true),
+      font_size: Some(16),
+      ..Style::default()
+    };
+    assert_eq!(style.get_font("italic 12px Arial"), "italic bold 16px Arial");
+    assert_eq!(Style::default().get_font("12px Arial"), "12px Arial");
+
+    let measure = |text: &str| text.len() as f64;
+    assert_eq!(wrap_words("one two three", 7.0, measure), vec!["one two", "three"]);
+    assert_eq!(wrap_words("longword a\nb", 4.0, measure), vec!["longword", "a", "b"]);
+  }
+}
@@ -0,0 +1,243 @@
+use wasm_bindgen::{JsCast, JsValue};
+
+/// Number of days between the spreadsheet epoch (1899-12-30) and the unix epoch.
+const UNIX_EPOCH_SERIAL: f64 = 25569.0;
+const MILLIS_PER_DAY: f64 = 86_400_000.0;
+/// Serial of 9999-12-31, the last day dates can be.
+const MAX_SERIAL: f64 = 2_958_465.0;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CellError {
+  Null,
+  Div0,
+  Value,
+  Ref,
+  Name,
+  Num,
+  Na,
+}
+
+impl CellError {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      CellError::Null => "#NULL!",
+      CellError::Div0 => "#DIV/0!",
+      CellError::Value => "#VALUE!",
+      CellError::Ref => "#REF!",
+      CellError::Name => "#NAME?",
+      CellError::Num => "#NUM!",
+      CellError::Na => "#N/A",
+    }
+  }
+
+  pub fn parse(text: &str) -> Option<Self> {
+    match text.to_uppercase().as_str() {
+      "#NULL!" => Some(CellError::Null),
+      "#DIV/0!" => Some(CellError::Div0),
+      "#VALUE!" => Some(CellError::Value),
+      "#REF!" => Some(CellError::Ref),
+      "#NAME?" => Some(CellError::Name),
+      "#NUM!" => Some(CellError::Num),
+      "#N/A" => Some(CellError::Na),
+      _ => None,
+    }
+  }
+}
+
+/// Value held by a cell. Date/time values are stored as serial numbers
+/// (fractional days since 1899-12-30) so they can take part in arithmetic.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CellValue {
+  Empty,
+  Number(f64),
+  Text(String),
+  Boolean(bool),
+  DateTime(f64),
+  Error(CellError),
+}
+
+impl CellValue {
+  pub fn is_empty(&self) -> bool {
+    matches!(self, CellValue::Empty)
+  }
+
+  /// Interpret text typed or imported into a cell: numbers (including
+  /// percentages), `TRUE`/`FALSE`, error literals and `yyyy-mm-dd` dates are
+  /// recognised; anything else stays text.
+  pub fn parse(text: &str) -> Self {
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+      return CellValue::Empty;
+    }
+    if let Ok(number) = trimmed.parse::<f64>() {
+      if number.is_finite() {
+        return CellValue::Number(number);
+      }
+    }
+    if let Some(percent) = trimmed.strip_suffix('%') {
+      if let Ok(number) = percent.trim().parse::<f64>() {
+        return CellValue::Number(number / 100.0);
+      }
+    }
+    match trimmed.to_uppercase().as_str() {
+      "TRUE" => return CellValue::Boolean(true),
+      "FALSE" => return CellValue::Boolean(false),
+      _ => (),
+    }
+    if let Some(error) = CellError::parse(trimmed) {
+      return CellValue::Error(error);
+    }
+    match parse_date(trimmed) {
+      Some(serial) => CellValue::DateTime(serial),
+      None => CellValue::Text(text.to_string()),
+    }
+  }
+
+  pub fn from_js(value: &JsValue) -> Self {
+    if value.is_null() || value.is_undefined() {
+      CellValue::Empty
+    } else if let Some(date) = value.dyn_ref::<js_sys::Date>() {
+      CellValue::DateTime(date.get_time() / MILLIS_PER_DAY + UNIX_EPOCH_SERIAL)
+    } else if let Some(number) = value.as_f64() {
+      CellValue::Number(number)
+    } else if let Some(boolean) = value.as_bool() {
+      CellValue::Boolean(boolean)
+    } else if let Some(text) = value.as_string() {
+      CellValue::Text(text)
+    } else {
+      CellValue::Error(CellError::Value)
+    }
+  }
+
+  pub fn to_js(&self) -> JsValue {
+    match self {
+      CellValue::Empty => JsValue::NULL,
+      CellValue::Number(number) => JsValue::from(*number),
+      CellValue::Text(text) => JsValue::from(text.as_str()),
+      CellValue::Boolean(boolean) => JsValue::from(*boolean),
+      CellValue::DateTime(serial) => {
+        let millis = (serial - UNIX_EPOCH_SERIAL) * MILLIS_PER_DAY;
+        js_sys::Date::new(&JsValue::from(millis)).into()
+      }
+      CellValue::Error(error) => JsValue::from(error.as_str()),
+    }
+  }
+}
+
+impl std::fmt::Display for CellValue {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      CellValue::Empty => Ok(()),
+      CellValue::Number(number) => write!(f, "{}", number),
+      CellValue::Text(text) => f.write_str(text),
+      CellValue::Boolean(true) => f.write_str("TRUE"),
+      CellValue::Boolean(false) => f.write_str("FALSE"),
+      CellValue::DateTime(serial) => f.write_str(&format_serial(*serial)),
+      CellValue::Error(error) => f.write_str(error.as_str()),
+    }
+  }
+}
+
+/// Serial of a `yyyy-mm-dd` date, if it names a real day.
+fn parse_date(text: &str) -> Option<f64> {
+  let parts: Vec<&str> = text.split('-').collect();
+  if parts.len() != 3 || parts[0].len() != 4 || !parts.iter().all(|part| part.chars().all(|c| c.is_ascii_digit())) {
+    return None;
+  }
+  let year: i64 = parts[0].parse().ok()?;
+  let month: u32 = parts[1].parse().ok()?;
+  let day: u32 = parts[2].parse().ok()?;
+  if (1..=12).contains(&month) && (1..=days_in_month(year, month)).contains(&day) {
+    Some(ymd_to_serial(year, month, day))
+  } else {
+    None
+  }
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+  match month {
+    2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+    2 => 28,
+    4 | 6 | 9 | 11 => 30,
+    _ => 31,
+  }
+}
+
+/// Whether a serial falls on a day from the epoch to 9999-12-31, the dates
+/// that can be shown and taken apart.
+pub fn is_date_serial(serial: f64) -> bool {
+  (0.0..MAX_SERIAL + 1.0).contains(&serial)
+}
+
+/// Convert a serial day number to a (year, month, day) civil date.
+pub fn serial_to_ymd(serial: f64) -> (i64, u32, u32) {
+  let days = serial.floor() as i64 - UNIX_EPOCH_SERIAL as i64 + 719_468;
+  let era = if days >= 0 { days } else { days - 146_096 } / 146_097;
+  let doe = days - era * 146_097;
+  let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+  let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+  (year, month, day)
+}
+
+/// Convert a civil date to its serial day number.
+pub fn ymd_to_serial(year: i64, month: u32, day: u32) -> f64 {
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = if year >= 0 { year } else { year - 399 } / 400;
+  let yoe = year - era * 400;
+  let mp = (month as i64 + 9) % 12;
+  let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+  let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+  (era * 146_097 + doe - 719_468) as f64 + UNIX_EPOCH_SERIAL
+}
+
+fn format_serial(serial: f64) -> String {
+  // A serial with no calendar day is shown as the number it is.
+  if !is_date_serial(serial) {
+    return serial.to_string();
+  }
+  let (year, month, day) = serial_to_ymd(serial);
+  let seconds = ((serial - serial.floor()) * 86_400.0).round().min(86_399.0) as u32;
+  if seconds == 0 {
+    format!("{:04}-{:02}-{:02}", year, month, day)
+  } else {
+    format!(
+      "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+      year,
+      month,
+      day,
+      seconds / 3600,
+      seconds % 3600 / 60,
+      seconds % 60
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::{serial_to_ymd, ymd_to_serial, CellValue};
+  #[test]
+  fn serial_round_trip() {
+    assert_eq!(ymd_to_serial(1900, 1, 1), 2.0);
+    assert_eq!(ymd_to_serial(2020, 10, 18), 44122.0);
+    assert_eq!(serial_to_ymd(44122.75), (2020, 10, 18));
+    assert_eq!(CellValue::DateTime(44122.5).to_string(), "2020-10-18 12:00:00");
+    assert_eq!(CellValue::DateTime(-2.5).to_string(), "-2.5");
+    assert_eq!(CellValue::DateTime(1e300).to_string(), 1e300.to_string());
+    assert_eq!(CellValue::parse("2020-10-18"), CellValue::DateTime(44122.0));
+    assert_eq!(CellValue::parse(" 12.5 "), CellValue::Number(12.5));
+    assert_eq!(CellValue::parse("15%"), CellValue::Number(0.15));
+    assert_eq!(CellValue::parse("true"), CellValue::Boolean(true));
+    assert_eq!(CellValue::parse("inf"), CellValue::Text("inf".to_string()));
+    assert_eq!(CellValue::parse("2020-13-01"), CellValue::Text("2020-13-01".to_string()));
+    assert_eq!(CellValue::parse("2020-02-31"), CellValue::Text("2020-02-31".to_string()));
+    assert_eq!(CellValue::parse("2020-02-29"), CellValue::DateTime(ymd_to_serial(2020, 2, 29)));
+    assert_eq!(CellValue::parse("2019-02-29"), CellValue::Text("2019-02-29".to_string()));
+    let overflow = "2020-99999999999-01";
+    assert_eq!(CellValue::parse(overflow), CellValue::Text(overflow.to_string()));
+  }
+}
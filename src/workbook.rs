@@ -0,0 +1,433 @@
+use std::collections::BTreeMap;
+
+use super::column::ColumnManager;
+use super::formula;
+use super::functions::Function;
+use super::history::{Command, History};
+use super::model::{CellSnapshot, DataModel, RewrittenFormulas};
+use super::reference::{Axis, RangeRef};
+use super::row::RowManager;
+use super::xlsx::SheetData;
+
+/// Longest sheet name Excel accepts.
+const MAX_NAME_LENGTH: usize = 31;
+
+/// A named grid with its own cells, sizes and undo history.
+pub struct Sheet {
+  pub name: String,
+  pub data_model: DataModel,
+  pub row_manager: RowManager,
+  pub col_manager: ColumnManager,
+  pub history: History,
+}
+
+impl Sheet {
+  pub fn new(name: &str, col_manager: ColumnManager, row_manager: RowManager, undo_limit: usize) -> Self {
+    let mut data_model = DataModel::new();
+    data_model.set_sheet_name(name);
+    Self {
+      name: name.to_string(),
+      data_model,
+      row_manager,
+      col_manager,
+      history: History::new(undo_limit),
+    }
+  }
+
+  /// A copy of the cells, sizes, column order, styles and merges under
+  /// another name, with no history.
+  fn copy(&self, name: &str) -> Self {
+    let mut sheet = Self::new(
+      name,
+      ColumnManager::new(self.col_manager.get_col_count(), self.col_manager.get_default_width()),
+      RowManager::new(self.row_manager.get_row_count(), self.row_manager.get_default_height()),
+      self.history.get_limit(),
+    );
+    SheetData::capture(name, &self.data_model, &self.row_manager, &self.col_manager).load(
+      &mut sheet.data_model,
+      &mut sheet.row_manager,
+      &mut sheet.col_manager,
+    );
+    // Taken from a sheet of the same size, so the order is valid.
+    let _ = sheet.col_manager.set_order(&self.col_manager.get_order());
+    sheet.data_model.styles = self.data_model.styles.clone();
+    sheet.data_model.merges = self.data_model.merges.clone();
+    sheet
+  }
+}
+
+/// Sheets in tab order, one of them active. Formulas may read other sheets
+/// by name, e.g. `Sheet2!A1:B5`; `sync` carries values across after changes.
+pub struct Workbook {
+  sheets: Vec<Sheet>,
+  active: usize,
+  /// Functions registered by the host, handed to sheets added later.
+  functions: Vec<(String, Function)>,
+}
+
+impl Workbook {
+  pub fn new(sheet: Sheet) -> Self {
+    Self {
+      sheets: vec![sheet],
+      active: 0,
+      functions: vec![],
+    }
+  }
+
+  /// The active sheet.
+  pub fn sheet(&self) -> &Sheet {
+    &self.sheets[self.active]
+  }
+
+  pub fn sheet_mut(&mut self) -> &mut Sheet {
+    &mut self.sheets[self.active]
+  }
+
+  pub fn get_sheets(&self) -> &[Sheet] {
+    &self.sheets
+  }
+
+  pub fn get_sheets_mut(&mut self) -> &mut [Sheet] {
+    &mut self.sheets
+  }
+
+  pub fn len(&self) -> usize {
+    self.sheets.len()
+  }
+
+  pub fn get_active(&self) -> usize {
+    self.active
+  }
+
+  pub fn set_active(&mut self, idx: usize) -> bool {
+    if idx >= self.sheets.len() {
+      return false;
+    }
+    self.active = idx;
+    true
+  }
+
+  pub fn get_names(&self) -> Vec<String> {
+    self.sheets.iter().map(|sheet| sheet.name.clone()).collect()
+  }
+
+  /// Position of the sheet with the given name, ignoring case.
+  pub fn index_of(&self, name: &str) -> Option<usize> {
+    let name = name.to_uppercase();
+    self.sheets.iter().position(|sheet| sheet.name.to_uppercase() == name)
+  }
+
+  /// Check a name as Excel does: 1 to 31 characters, none of `[]:*?/\`, not
+  /// starting or ending with `'`, and not used by another sheet.
+  fn check_name(&self, name: &str, except: Option<usize>) -> Result<(), String> {
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+      return Err(format!("sheet names must be 1 to {} characters long", MAX_NAME_LENGTH));
+    }
+    if name.contains(|c| "[]:*?/\\".contains(c)) || name.starts_with('\'') || name.ends_with('\'') {
+      return Err(format!("{} is not a valid sheet name", name));
+    }
+    match self.index_of(name) {
+      Some(idx) if Some(idx) != except => Err(format!("there is already a sheet named {}", name)),
+      _ => Ok(()),
+    }
+  }
+
+  /// The first of `Sheet1`, `Sheet2`, ... not in use.
+  pub fn next_name(&self) -> String {
+    (1..)
+      .map(|number| format!("Sheet{}", number))
+      .find(|name| self.index_of(name).is_none())
+      .unwrap()
+  }
+
+  /// Insert a sheet at the given position. Returns where it went.
+  pub fn add(&mut self, idx: usize, mut sheet: Sheet) -> Result<usize, String> {
+    self.check_name(&sheet.name, None)?;
+    for (name, function) in self.functions.iter() {
+      sheet.data_model.register_function(name, function.clone());
+    }
+    let idx = idx.min(self.sheets.len());
+    self.sheets.insert(idx, sheet);
+    if idx <= self.active && self.sheets.len() > 1 {
+      self.active += 1;
+    }
+    self.sync();
+    Ok(idx)
+  }
+
+  /// Copy a sheet, placing the copy after it under a name like `Data (2)`.
+  pub fn duplicate(&mut self, idx: usize) -> Result<usize, String> {
+    let source = self.sheets.get(idx).ok_or_else(|| "no such sheet".to_string())?;
+    let base: String = source.name.chars().take(MAX_NAME_LENGTH - 4).collect();
+    let name = (2..)
+      .map(|number| format!("{} ({})", base, number))
+      .find(|name| self.index_of(name).is_none())
+      .unwrap();
+    let copy = source.copy(&name);
+    self.add(idx + 1, copy)
+  }
+
+  /// Rename a sheet, pointing formulas reading it at the new name.
+  pub fn rename(&mut self, idx: usize, name: &str) -> Result<(), String> {
+    if idx >= self.sheets.len() {
+      return Err("no such sheet".to_string());
+    }
+    self.check_name(name, Some(idx))?;
+    let old_name = std::mem::replace(&mut self.sheets[idx].name, name.to_string());
+    self.sheets[idx].data_model.set_sheet_name(name);
+    for sheet in self.sheets.iter_mut() {
+      sheet
+        .data_model
+        .rewrite_formulas(|source| formula::rename_sheet(source, &old_name, name));
+    }
+    self.sync();
+    Ok(())
+  }
+
+  /// Move a sheet to another position. The active sheet stays active.
+  pub fn move_sheet(&mut self, from: usize, to: usize) -> bool {
+    if from >= self.sheets.len() || to >= self.sheets.len() {
+      return false;
+    }
+    let sheet = self.sheets.remove(from);
+    self.sheets.insert(to, sheet);
+    self.active = match self.active {
+      active if active == from => to,
+      active if from < active && active <= to => active - 1,
+      active if to <= active && active < from => active + 1,
+      active => active,
+    };
+    true
+  }
+
+  /// Remove a sheet; formulas reading it evaluate to `#REF!`. The last sheet
+  /// cannot be removed.
+  pub fn remove(&mut self, idx: usize) -> Result<Sheet, String> {
+    if idx >= self.sheets.len() {
+      return Err("no such sheet".to_string());
+    }
+    if self.sheets.len() == 1 {
+      return Err("a workbook needs at least one sheet".to_string());
+    }
+    let sheet = self.sheets.remove(idx);
+    if self.active > idx || self.active == self.sheets.len() {
+      self.active -= 1;
+    }
+    self.sync();
+    Ok(sheet)
+  }
+
+  /// Replace every sheet, making the first active.
+  pub fn replace(&mut self, sheets: Vec<Sheet>) {
+    if sheets.is_empty() {
+      return;
+    }
+    self.sheets = sheets;
+    self.active = 0;
+    for sheet in self.sheets.iter_mut() {
+      for (name, function) in self.functions.iter() {
+        sheet.data_model.register_function(name, function.clone());
+      }
+    }
+    self.sync();
+  }
+
+  /// Point references on other sheets at the cells of sheet `idx` they read
+  /// after its rows or columns were inserted or deleted, as for
+  /// `DataModel::shift_lines`. Returns the formulas rewritten on each sheet,
+  /// by name, with their sources as they were.
+  pub fn shift_references(&mut self, idx: usize, axis: Axis, at: u32, delta: i64) -> Vec<(String, RewrittenFormulas)> {
+    let name = self.sheets[idx].name.to_uppercase();
+    let mut rewritten = vec![];
+    for (other, sheet) in self.sheets.iter_mut().enumerate() {
+      if other == idx || sheet.data_model.get_external_references().is_empty() {
+        continue;
+      }
+      let formulas = sheet.data_model.rewrite_formulas(|source| {
+        formula::rewrite_references(source, |sheet, range| match sheet {
+          Some(sheet) if sheet.to_uppercase() == name => range.shift(axis, at, delta),
+          _ => Some(range),
+        })
+      });
+      if !formulas.is_empty() {
+        rewritten.push((sheet.name.clone(), formulas));
+      }
+    }
+    rewritten
+  }
+
+  /// Put back the formulas of other sheets that an undone command's
+  /// deletions rewrote, once its shifts have been reversed. Sheets since
+  /// removed are skipped.
+  pub fn restore_external_formulas(&mut self, command: &Command) {
+    for (name, formulas) in command.get_external_formulas().into_iter().rev() {
+      let idx = match self.index_of(name) {
+        Some(idx) => idx,
+        None => continue,
+      };
+      let model = &mut self.sheets[idx].data_model;
+      for (col_idx, row_idx, source) in formulas.iter() {
+        model.insert_cell(*col_idx, *row_idx, CellSnapshot::formula(source));
+      }
+      model.recalculate_all();
+    }
+    self.sync();
+  }
+
+  /// Add or replace a formula function on every sheet, present and future.
+  pub fn register_function(&mut self, name: &str, function: Function) {
+    self.functions.retain(|(registered, _)| !registered.eq_ignore_ascii_case(name));
+    self.functions.push((name.to_string(), function.clone()));
+    for sheet in self.sheets.iter_mut() {
+      sheet.data_model.register_function(name, function.clone());
+    }
+    self.sync();
+  }
+
+  /// Re-evaluate the formulas calling volatile functions on every sheet, see
+  /// `DataModel::recalculate_volatile`.
+  pub fn recalculate_volatile(&mut self) {
+    for sheet in self.sheets.iter_mut() {
+      sheet.data_model.recalculate_volatile();
+    }
+  }
+
+  /// Hand each sheet the values it reads from other sheets, re-evaluating the
+  /// formulas reading them. As those may in turn be read elsewhere, this
+  /// repeats until nothing changes, at most once per sheet so that sheets
+  /// reading each other in a cycle cannot loop for ever.
+  pub fn sync(&mut self) {
+    for _ in 0..self.sheets.len() {
+      let mut changed = false;
+      for idx in 0..self.sheets.len() {
+        let mut ranges: BTreeMap<String, Vec<RangeRef>> = BTreeMap::new();
+        for (name, range) in self.sheets[idx].data_model.get_external_references() {
+          ranges.entry(name.to_uppercase()).or_default().push(range);
+        }
+        for (name, ranges) in ranges {
+          let values = self
+            .index_of(&name)
+            .map(|source| self.sheets[source].data_model.get_values_within(&ranges));
+          changed |= self.sheets[idx].data_model.set_external_values(&name, values);
+        }
+      }
+      if !changed {
+        break;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::{Sheet, Workbook};
+  use crate::column::ColumnManager;
+  use crate::history::{self, Command};
+  use crate::reference::Axis;
+  use crate::row::RowManager;
+  use crate::value::{CellError, CellValue};
+
+  fn sheet(name: &str) -> Sheet {
+    Sheet::new(name, ColumnManager::new(26, 85), RowManager::new(100, 20), 10)
+  }
+
+  #[test]
+  fn sheets_and_references() {
+    let mut workbook = Workbook::new(sheet("Sheet1"));
+    assert_eq!(workbook.add(1, sheet("sheet1")), Err("there is already a sheet named sheet1".to_string()));
+    assert!(workbook.add(1, sheet("a/b")).is_err());
+    let name = workbook.next_name();
+    assert_eq!(workbook.add(1, sheet(&name)), Ok(1));
+
+    workbook.get_sheets_mut()[1].data_model.set_value(1, 1, CellValue::Number(2.0));
+    workbook.get_sheets_mut()[1].data_model.set_value(1, 2, CellValue::Number(3.0));
+    let model = &mut workbook.sheet_mut().data_model;
+    model.set_formula(1, 1, "=SUM(Sheet2!A:A)*sheet2!A1");
+    model.set_formula(2, 1, "=A1+1");
+    workbook.sync();
+    assert_eq!(workbook.sheet().data_model.get_value(2, 1), &CellValue::Number(11.0));
+
+    workbook.get_sheets_mut()[1].data_model.set_value(1, 2, CellValue::Number(8.0));
+    workbook.sync();
+    assert_eq!(workbook.sheet().data_model.get_value(2, 1), &CellValue::Number(21.0));
+
+    workbook.rename(1, "Input data").unwrap();
+    assert_eq!(
+      workbook.sheet().data_model.get_formula(1, 1),
+      Some(&"=SUM('Input data'!A:A)*'Input data'!A1".to_string())
+    );
+    assert_eq!(workbook.sheet().data_model.get_value(2, 1), &CellValue::Number(21.0));
+    workbook.shift_references(1, Axis::Rows, 1, 2);
+    assert_eq!(
+      workbook.sheet().data_model.get_formula(1, 1),
+      Some(&"=SUM('Input data'!A:A)*'Input data'!A3".to_string())
+    );
+    workbook.shift_references(1, Axis::Rows, 1, -2);
+
+    assert_eq!(workbook.duplicate(1), Ok(2));
+    assert_eq!(workbook.get_names(), vec!["Sheet1", "Input data", "Input data (2)"]);
+    assert_eq!(workbook.get_sheets()[2].data_model.get_value(1, 2), &CellValue::Number(8.0));
+
+    assert!(workbook.set_active(2));
+    assert!(workbook.move_sheet(2, 0));
+    assert_eq!(workbook.get_active(), 0);
+    assert!(workbook.move_sheet(1, 2));
+    assert_eq!(workbook.get_names(), vec!["Input data (2)", "Input data", "Sheet1"]);
+
+    workbook.remove(1).unwrap();
+    assert_eq!(workbook.get_sheets()[1].data_model.get_value(1, 1), &CellValue::Error(CellError::Ref));
+    workbook.remove(0).unwrap();
+    assert_eq!(workbook.get_active(), 0);
+    assert!(workbook.remove(0).is_err());
+  }
+
+  #[test]
+  fn undo_delete_restores_other_sheets() {
+    let mut workbook = Workbook::new(sheet("Sheet1"));
+    workbook.add(1, sheet("Data")).unwrap();
+    for row_idx in 1..=3 {
+      workbook.get_sheets_mut()[1].data_model.set_value(1, row_idx, CellValue::Number(row_idx as f64));
+    }
+    workbook.sheet_mut().data_model.set_formula(1, 1, "=Data!A2*10+SUM(Data!A1:A3)");
+    workbook.sync();
+    assert_eq!(workbook.sheet().data_model.get_value(1, 1), &CellValue::Number(26.0));
+
+    let data = &mut workbook.get_sheets_mut()[1];
+    let (cells, sizes) = history::shift_lines(
+      &mut data.data_model,
+      &mut data.row_manager,
+      &mut data.col_manager,
+      Axis::Rows,
+      2,
+      -1,
+    );
+    let external = workbook.shift_references(1, Axis::Rows, 2, -1);
+    assert_eq!(external.len(), 1);
+    workbook.get_sheets_mut()[1].history.record(Command::DeleteLines {
+      axis: Axis::Rows,
+      at: 2,
+      count: 1,
+      cells,
+      sizes,
+      external,
+    });
+    workbook.sync();
+    assert_eq!(workbook.sheet().data_model.get_formula(1, 1), Some(&"=#REF!*10+SUM(Data!A1:A2)".to_string()));
+    assert_eq!(workbook.sheet().data_model.get_value(1, 1), &CellValue::Error(CellError::Ref));
+
+    let data = &mut workbook.get_sheets_mut()[1];
+    let command = data
+      .history
+      .undo(&mut data.data_model, &mut data.row_manager, &mut data.col_manager)
+      .cloned()
+      .unwrap();
+    workbook.shift_references(1, Axis::Rows, 2, 1);
+    workbook.restore_external_formulas(&command);
+    assert_eq!(
+      workbook.sheet().data_model.get_formula(1, 1),
+      Some(&"=Data!A2*10+SUM(Data!A1:A3)".to_string())
+    );
+    assert_eq!(workbook.sheet().data_model.get_value(1, 1), &CellValue::Number(26.0));
+  }
+}
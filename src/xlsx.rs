@@ -0,0 +1,921 @@
+use std::collections::BTreeMap;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use super::column::{Column, ColumnManager};
+use super::formula;
+use super::model::{CellSnapshot, DataModel};
+use super::reference::{self, CellRef};
+use super::row::{Row, RowManager};
+use super::value::{CellError, CellValue};
+
+const MAIN_NS: &str = "http://schemas.openxmlformats.org/spreadsheetml/2006/main";
+const REL_NS: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships";
+const PACKAGE_REL_NS: &str = "http://schemas.openxmlformats.org/package/2006/relationships";
+
+/// Width in pixels of the widest digit of the default font, which Excel
+/// measures column widths in.
+const DIGIT_WIDTH: f64 = 7.0;
+/// Serial number offset of workbooks using the 1904 date system.
+const DATE_1904_OFFSET: f64 = 1462.0;
+/// Index of the date style among the cell formats written.
+const DATE_STYLE: u32 = 1;
+
+/// One worksheet as stored in a workbook: cell contents with the cached
+/// results of formulas, sizes in pixels and hidden rows and columns.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SheetData {
+  pub name: String,
+  pub cells: Vec<(u32, u32, CellSnapshot)>,
+  pub col_widths: Vec<(u32, u16)>,
+  pub row_heights: Vec<(u32, u16)>,
+  pub hidden_cols: Vec<u32>,
+  pub hidden_rows: Vec<u32>,
+  pub default_col_width: u16,
+  pub default_row_height: u16,
+}
+
+impl SheetData {
+  pub fn new(name: &str) -> Self {
+    Self {
+      name: name.to_string(),
+      cells: vec![],
+      col_widths: vec![],
+      row_heights: vec![],
+      hidden_cols: vec![],
+      hidden_rows: vec![],
+      default_col_width: 64,
+      default_row_height: 20,
+    }
+  }
+
+  /// Take the contents and sizes of a sheet.
+  pub fn capture(name: &str, model: &DataModel, rows: &RowManager, cols: &ColumnManager) -> Self {
+    let mut cells: Vec<(u32, u32, CellSnapshot)> = model
+      .get_cells()
+      .map(|cell| {
+        let (col, row) = (cell.get_col_idx(), cell.get_row_idx());
+        (col, row, model.snapshot(col, row))
+      })
+      .collect();
+    cells.sort_by_key(|(col, row, _)| (*row, *col));
+    let col_entries = cols.get_cols_within(cols.get_col_count());
+    let row_entries = rows.get_rows_within(rows.get_row_count());
+    Self {
+      name: name.to_string(),
+      cells,
+      col_widths: col_entries
+        .iter()
+        .filter(|col| col.get_width() != cols.get_default_width())
+        .map(|col| (col.get_idx(), col.get_width()))
+        .collect(),
+      row_heights: row_entries.iter().map(|row| (row.get_idx(), row.get_height())).collect(),
+      hidden_cols: col_entries.iter().filter(|col| col.is_hidden()).map(|col| col.get_idx()).collect(),
+      hidden_rows: row_entries.iter().filter(|row| row.is_hidden()).map(|row| row.get_idx()).collect(),
+      default_col_width: cols.get_default_width(),
+      default_row_height: rows.get_default_height(),
+    }
+  }
+
+  /// Replace the contents and sizes of a sheet, growing it to fit.
+  pub fn load(&self, model: &mut DataModel, rows: &mut RowManager, cols: &mut ColumnManager) {
+    model.clear();
+    model.load_cells(&self.cells);
+    let (max_col, max_row) = model.get_data_extent();
+    let max_col = self.col_widths.iter().map(|(idx, _)| *idx).fold(max_col, u32::max);
+    let max_row = self.row_heights.iter().map(|(idx, _)| *idx).fold(max_row, u32::max);
+    let max_col = self.hidden_cols.iter().copied().fold(max_col, u32::max);
+    let max_row = self.hidden_rows.iter().copied().fold(max_row, u32::max);
+    *cols = ColumnManager::new(cols.get_col_count().max(max_col), self.default_col_width);
+    for (col_idx, width) in self.col_widths.iter() {
+      let mut column = Column::new(*col_idx);
+      column.set_width(*width);
+      cols.set_column(column);
+    }
+    *rows = RowManager::new(rows.get_row_count().max(max_row), self.default_row_height);
+    for (row_idx, height) in self.row_heights.iter() {
+      let mut row = Row::new(*row_idx);
+      row.set_height(*height);
+      rows.set_row(row);
+    }
+    for col_idx in self.hidden_cols.iter() {
+      cols.set_hidden(*col_idx, true);
+    }
+    for row_idx in self.hidden_rows.iter() {
+      rows.set_hidden(*row_idx, true);
+    }
+  }
+}
+
+/// Column width in characters, as stored, to pixels.
+fn width_to_px(width: f64) -> u16 {
+  (width * DIGIT_WIDTH + 0.5).max(0.0) as u16
+}
+
+fn px_to_width(px: u16) -> f64 {
+  (px as f64 / DIGIT_WIDTH * 256.0).trunc() / 256.0
+}
+
+/// Row height in points to pixels.
+fn points_to_px(points: f64) -> u16 {
+  (points * 4.0 / 3.0).round().max(0.0) as u16
+}
+
+fn px_to_points(px: u16) -> f64 {
+  px as f64 * 0.75
+}
+
+/// Whether a boolean attribute is set, as `1` or `true`.
+fn is_true(value: Option<&String>) -> bool {
+  matches!(value.map(|value| value.as_str()), Some("1") | Some("true"))
+}
+
+/// Attributes of an element by local name, so `r:id` is found as `id`.
+fn attributes(reader: &Reader<&[u8]>, element: &BytesStart) -> BTreeMap<String, String> {
+  let mut attributes = BTreeMap::new();
+  for attribute in element.attributes().flatten() {
+    let key = String::from_utf8_lossy(attribute.key);
+    let key = key.rsplit(':').next().unwrap_or_default().to_string();
+    if let Ok(value) = attribute.unescape_and_decode_value(reader) {
+      attributes.insert(key, value);
+    }
+  }
+  attributes
+}
+
+fn local_name(element: &[u8]) -> String {
+  let name = String::from_utf8_lossy(element);
+  name.rsplit(':').next().unwrap_or_default().to_string()
+}
+
+/// An XML part read as a stream of element starts, ends and text, each start
+/// with its attributes. Elements without content give a start and an end.
+enum Node {
+  Start(String, BTreeMap<String, String>),
+  End(String),
+  Text(String),
+}
+
+fn parse_xml(xml: &str) -> Result<Vec<Node>, String> {
+  let mut reader = Reader::from_str(xml);
+  let mut buf = vec![];
+  let mut nodes = vec![];
+  loop {
+    match reader.read_event(&mut buf) {
+      Ok(Event::Start(ref element)) => {
+        nodes.push(Node::Start(local_name(element.name()), attributes(&reader, element)));
+      }
+      Ok(Event::Empty(ref element)) => {
+        let name = local_name(element.name());
+        nodes.push(Node::Start(name.clone(), attributes(&reader, element)));
+        nodes.push(Node::End(name));
+      }
+      Ok(Event::End(ref element)) => nodes.push(Node::End(local_name(element.name()))),
+      Ok(Event::Text(ref text)) | Ok(Event::CData(ref text)) => {
+        let text = text.unescape_and_decode(&reader).map_err(|error| error.to_string())?;
+        nodes.push(Node::Text(text));
+      }
+      Ok(Event::Eof) => break,
+      Ok(_) => (),
+      Err(error) => return Err(format!("malformed XML at {}: {}", reader.buffer_position(), error)),
+    }
+    buf.clear();
+  }
+  Ok(nodes)
+}
+
+fn part(files: &BTreeMap<String, Vec<u8>>, path: &str) -> Result<Vec<Node>, String> {
+  match files.get(path) {
+    Some(bytes) => parse_xml(&String::from_utf8_lossy(bytes)),
+    None => Err(format!("workbook is missing {}", path)),
+  }
+}
+
+/// Read the worksheets of an .xlsx workbook, in tab order.
+pub fn read(bytes: &[u8]) -> Result<Vec<SheetData>, String> {
+  let files = super::zip::read(bytes)?;
+
+  let mut targets = BTreeMap::new();
+  let mut shared_strings_path = "xl/sharedStrings.xml".to_string();
+  let mut styles_path = "xl/styles.xml".to_string();
+  if files.contains_key("xl/_rels/workbook.xml.rels") {
+    for node in part(&files, "xl/_rels/workbook.xml.rels")? {
+      if let Node::Start(name, attributes) = node {
+        if name != "Relationship" {
+          continue;
+        }
+        let target = match attributes.get("Target") {
+          Some(target) if target.starts_with('/') => target[1..].to_string(),
+          Some(target) => ["xl/", target].concat(),
+          None => continue,
+        };
+        let kind = attributes.get("Type").map_or("", |kind| kind.rsplit('/').next().unwrap_or_default());
+        match kind {
+          "sharedStrings" => shared_strings_path = target.clone(),
+          "styles" => styles_path = target.clone(),
+          _ => (),
+        }
+        if let Some(id) = attributes.get("Id") {
+          targets.insert(id.clone(), target);
+        }
+      }
+    }
+  }
+
+  let mut date_1904 = false;
+  let mut sheets = vec![];
+  for node in part(&files, "xl/workbook.xml")? {
+    if let Node::Start(name, attributes) = node {
+      match name.as_str() {
+        "workbookPr" => {
+          date_1904 = matches!(attributes.get("date1904").map(|value| value.as_str()), Some("1") | Some("true"))
+        }
+        "sheet" => {
+          let name = attributes.get("name").cloned().unwrap_or_default();
+          let target = match attributes.get("id").and_then(|id| targets.get(id)) {
+            Some(target) => target.clone(),
+            None => format!("xl/worksheets/sheet{}.xml", sheets.len() + 1),
+          };
+          sheets.push((name, target));
+        }
+        _ => (),
+      }
+    }
+  }
+
+  let shared_strings = if files.contains_key(&shared_strings_path) {
+    read_shared_strings(part(&files, &shared_strings_path)?)
+  } else {
+    vec![]
+  };
+  let date_styles = if files.contains_key(&styles_path) {
+    read_date_styles(part(&files, &styles_path)?)
+  } else {
+    vec![]
+  };
+  let context = Context {
+    shared_strings,
+    date_styles,
+    date_offset: if date_1904 { DATE_1904_OFFSET } else { 0.0 },
+  };
+  sheets
+    .into_iter()
+    .map(|(name, target)| read_worksheet(&name, part(&files, &target)?, &context))
+    .collect()
+}
+
+/// Workbook-wide tables needed to read cells.
+struct Context {
+  shared_strings: Vec<String>,
+  /// Whether each cell format shows a date.
+  date_styles: Vec<bool>,
+  date_offset: f64,
+}
+
+/// Shared strings: the text runs of each string item, leaving out phonetic
+/// hints.
+fn read_shared_strings(nodes: Vec<Node>) -> Vec<String> {
+  let mut strings = vec![];
+  let mut current = String::new();
+  let mut in_text = false;
+  let mut in_phonetic = false;
+  for node in nodes {
+    match node {
+      Node::Start(name, _) => match name.as_str() {
+        "si" => current.clear(),
+        "t" => in_text = true,
+        "rPh" => in_phonetic = true,
+        _ => (),
+      },
+      Node::End(name) => match name.as_str() {
+        "si" => strings.push(std::mem::take(&mut current)),
+        "t" => in_text = false,
+        "rPh" => in_phonetic = false,
+        _ => (),
+      },
+      Node::Text(text) if in_text && !in_phonetic => current.push_str(&text),
+      Node::Text(_) => (),
+    }
+  }
+  strings
+}
+
+fn read_date_styles(nodes: Vec<Node>) -> Vec<bool> {
+  let mut formats = BTreeMap::new();
+  let mut styles = vec![];
+  let mut in_cell_formats = false;
+  for node in nodes {
+    match node {
+      Node::Start(name, attributes) => match name.as_str() {
+        "numFmt" => {
+          if let (Some(id), Some(code)) = (attributes.get("numFmtId"), attributes.get("formatCode")) {
+            formats.insert(id.clone(), code.clone());
+          }
+        }
+        "cellXfs" => in_cell_formats = true,
+        "xf" if in_cell_formats => {
+          let id = attributes.get("numFmtId").cloned().unwrap_or_default();
+          let code = formats.get(&id).map_or("", |code| code.as_str());
+          styles.push(is_date_format(id.parse().unwrap_or(0), code));
+        }
+        _ => (),
+      },
+      Node::End(name) if name == "cellXfs" => in_cell_formats = false,
+      _ => (),
+    }
+  }
+  styles
+}
+
+/// Whether a number format shows a date or time: one of the built-in date
+/// formats, or a custom code using date or time parts outside of quoted text
+/// and brackets.
+fn is_date_format(id: u32, code: &str) -> bool {
+  match id {
+    14..=22 | 45..=47 => return true,
+    0..=163 => return false,
+    _ => (),
+  }
+  let mut quoted = false;
+  let mut bracketed = false;
+  let mut escaped = false;
+  for c in code.chars() {
+    if escaped {
+      escaped = false;
+      continue;
+    }
+    match c {
+      '"' => quoted = !quoted,
+      '[' if !quoted => bracketed = true,
+      ']' if !quoted => bracketed = false,
+      '\\' if !quoted => escaped = true,
+      ';' if !quoted && !bracketed => break,
+      _ if quoted || bracketed => (),
+      'd' | 'D' | 'm' | 'M' | 'y' | 'Y' | 'h' | 'H' | 's' | 'S' => return true,
+      _ => (),
+    }
+  }
+  false
+}
+
+/// A cell being read, with what its elements held.
+#[derive(Default)]
+struct PendingCell {
+  col: u32,
+  row: u32,
+  kind: String,
+  style: usize,
+  value: Option<String>,
+  formula: Option<String>,
+  shared_index: Option<String>,
+  inline: String,
+}
+
+fn read_worksheet(name: &str, nodes: Vec<Node>, context: &Context) -> Result<SheetData, String> {
+  let mut sheet = SheetData::new(name);
+  let mut base_col_width = None;
+  let mut default_col_width = None;
+  let mut default_row_height = None;
+  // Formulas shared over a range, by index, with the cell they are written at.
+  let mut shared_formulas: BTreeMap<String, (u32, u32, String)> = BTreeMap::new();
+  let mut row_idx: u32 = 0;
+  let mut col_idx: u32 = 0;
+  let mut cell: Option<PendingCell> = None;
+  let mut text_target = "";
+  for node in nodes {
+    match node {
+      Node::Start(name, attributes) => match name.as_str() {
+        "sheetFormatPr" => {
+          base_col_width = attributes.get("baseColWidth").and_then(|width| width.parse::<f64>().ok());
+          default_col_width = attributes.get("defaultColWidth").and_then(|width| width.parse::<f64>().ok());
+          default_row_height = attributes.get("defaultRowHeight").and_then(|height| height.parse::<f64>().ok());
+        }
+        "col" => {
+          let min: u32 = attributes.get("min").and_then(|min| min.parse().ok()).unwrap_or(0);
+          let max: u32 = attributes.get("max").and_then(|max| max.parse().ok()).unwrap_or(min);
+          let hidden = is_true(attributes.get("hidden"));
+          let width = attributes.get("width").and_then(|width| width.parse::<f64>().ok());
+          for idx in min.max(1)..=max.min(super::DEFAULT_COLUMN_COUNT) {
+            if let Some(width) = width {
+              sheet.col_widths.push((idx, width_to_px(width)));
+            }
+            if hidden {
+              sheet.hidden_cols.push(idx);
+            }
+          }
+        }
+        "row" => {
+          row_idx = attributes.get("r").and_then(|r| r.parse().ok()).unwrap_or(row_idx.saturating_add(1));
+          col_idx = 0;
+          if row_idx > super::DEFAULT_ROW_COUNT {
+            continue;
+          }
+          if let Some(height) = attributes.get("ht").and_then(|height| height.parse::<f64>().ok()) {
+            sheet.row_heights.push((row_idx, points_to_px(height)));
+          }
+          if is_true(attributes.get("hidden")) {
+            sheet.hidden_rows.push(row_idx);
+          }
+        }
+        "c" => {
+          let (col, row) = match attributes.get("r").and_then(|r| CellRef::parse(r)) {
+            Some(reference) => (reference.col, reference.row),
+            None => (col_idx.saturating_add(1), row_idx.max(1)),
+          };
+          col_idx = col;
+          // Like columns, cells past the grid are dropped.
+          if col > super::DEFAULT_COLUMN_COUNT || row > super::DEFAULT_ROW_COUNT {
+            continue;
+          }
+          cell = Some(PendingCell {
+            col,
+            row,
+            kind: attributes.get("t").cloned().unwrap_or_default(),
+            style: attributes.get("s").and_then(|s| s.parse().ok()).unwrap_or(0),
+            ..PendingCell::default()
+          });
+        }
+        "v" => text_target = "v",
+        "t" => text_target = "t",
+        "f" => {
+          text_target = "f";
+          if let Some(cell) = cell.as_mut() {
+            if attributes.get("t").map(|kind| kind.as_str()) == Some("shared") {
+              cell.shared_index = attributes.get("si").cloned();
+            }
+            cell.formula = Some(String::new());
+          }
+        }
+        _ => (),
+      },
+      Node::Text(text) => {
+        if let Some(cell) = cell.as_mut() {
+          match text_target {
+            "v" => cell.value.get_or_insert_with(String::new).push_str(&text),
+            "f" => cell.formula.get_or_insert_with(String::new).push_str(&text),
+            "t" => cell.inline.push_str(&text),
+            _ => (),
+          }
+        }
+      }
+      Node::End(name) => match name.as_str() {
+        "v" | "f" | "t" => text_target = "",
+        "c" => {
+          if let Some(mut pending) = cell.take() {
+            if let Some(index) = pending.shared_index.clone() {
+              let source = pending.formula.clone().unwrap_or_default();
+              if source.is_empty() {
+                pending.formula = shared_formulas
+                  .get(&index)
+                  .map(|(col, row, source)| formula::translate(source, (*col, *row), (pending.col, pending.row)));
+              } else {
+                shared_formulas.insert(index, (pending.col, pending.row, source));
+              }
+            }
+            if let Some(snapshot) = read_cell(&pending, context) {
+              sheet.cells.push((pending.col, pending.row, snapshot));
+            }
+          }
+        }
+        _ => (),
+      },
+    }
+  }
+  sheet.default_col_width = match (default_col_width, base_col_width) {
+    (Some(width), _) => width_to_px(width),
+    // The base width is in characters, without the padding, and the result is
+    // rounded up to a multiple of 8 pixels.
+    (None, Some(base)) => ((base * DIGIT_WIDTH + 5.0) / 8.0).ceil() as u16 * 8,
+    (None, None) => 64,
+  };
+  sheet.default_row_height = default_row_height.map_or(20, points_to_px);
+  let default_col_width = sheet.default_col_width;
+  sheet.col_widths.retain(|(_, width)| *width != default_col_width);
+  Ok(sheet)
+}
+
+fn read_cell(cell: &PendingCell, context: &Context) -> Option<CellSnapshot> {
+  let raw = cell.value.clone().unwrap_or_default();
+  let value = match cell.kind.as_str() {
+    "s" => match raw.trim().parse::<usize>().ok().and_then(|idx| context.shared_strings.get(idx)) {
+      Some(text) => CellValue::Text(text.clone()),
+      None => CellValue::Empty,
+    },
+    "str" => CellValue::Text(raw),
+    "inlineStr" => CellValue::Text(cell.inline.clone()),
+    "b" => CellValue::Boolean(raw.trim() == "1" || raw.trim() == "true"),
+    "e" => CellValue::Error(CellError::parse(raw.trim()).unwrap_or(CellError::Value)),
+    "d" => CellValue::parse(raw.get(..10).unwrap_or(&raw)),
+    _ => match raw.trim().parse::<f64>() {
+      Ok(number) if context.date_styles.get(cell.style).copied().unwrap_or(false) => {
+        CellValue::DateTime(number + context.date_offset)
+      }
+      Ok(number) => CellValue::Number(number),
+      Err(_) => CellValue::Empty,
+    },
+  };
+  match &cell.formula {
+    Some(source) if !source.trim().is_empty() => {
+      // Newer functions are written with a prefix naming the release that added them.
+      let source = source.replace("_xlfn.", "").replace("_xlws.", "");
+      Some(CellSnapshot {
+        value,
+        formula: Some(["=", source.trim_start_matches('=')].concat()),
+      })
+    }
+    _ if value.is_empty() => None,
+    _ => Some(CellSnapshot::value(value)),
+  }
+}
+
+fn escape(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+const XML_DECLARATION: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n";
+
+/// Write worksheets as an .xlsx workbook. Text goes to the shared string
+/// table and dates get a date format; formulas are written with their
+/// current results so other readers need not recalculate.
+pub fn write(sheets: &[SheetData]) -> Vec<u8> {
+  let mut shared_strings = SharedStrings::default();
+  let mut files = vec![];
+  let mut content_types = String::from(XML_DECLARATION);
+  content_types.push_str(
+    "<Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">\
+     <Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\"/>\
+     <Default Extension=\"xml\" ContentType=\"application/xml\"/>\
+     <Override PartName=\"/xl/workbook.xml\" \
+     ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml\"/>\
+     <Override PartName=\"/xl/styles.xml\" \
+     ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml\"/>\
+     <Override PartName=\"/xl/sharedStrings.xml\" \
+     ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.sharedStrings+xml\"/>",
+  );
+  let mut workbook = format!(
+    "{}<workbook xmlns=\"{}\" xmlns:r=\"{}\"><sheets>",
+    XML_DECLARATION, MAIN_NS, REL_NS
+  );
+  let mut workbook_rels = format!("{}<Relationships xmlns=\"{}\">", XML_DECLARATION, PACKAGE_REL_NS);
+  for (idx, sheet) in sheets.iter().enumerate() {
+    let number = idx + 1;
+    content_types.push_str(&format!(
+      "<Override PartName=\"/xl/worksheets/sheet{}.xml\" \
+       ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml\"/>",
+      number
+    ));
+    workbook.push_str(&format!(
+      "<sheet name=\"{}\" sheetId=\"{}\" r:id=\"rId{}\"/>",
+      escape(&sheet.name),
+      number,
+      number
+    ));
+    workbook_rels.push_str(&format!(
+      "<Relationship Id=\"rId{}\" Type=\"{}/worksheet\" Target=\"worksheets/sheet{}.xml\"/>",
+      number, REL_NS, number
+    ));
+    files.push((
+      format!("xl/worksheets/sheet{}.xml", number),
+      write_worksheet(sheet, &mut shared_strings).into_bytes(),
+    ));
+  }
+  content_types.push_str("</Types>");
+  workbook.push_str("</sheets></workbook>");
+  workbook_rels.push_str(&format!(
+    "<Relationship Id=\"rId{}\" Type=\"{}/styles\" Target=\"styles.xml\"/>\
+     <Relationship Id=\"rId{}\" Type=\"{}/sharedStrings\" Target=\"sharedStrings.xml\"/>\
+     </Relationships>",
+    sheets.len() + 1,
+    REL_NS,
+    sheets.len() + 2,
+    REL_NS
+  ));
+  let package_rels = format!(
+    "{}<Relationships xmlns=\"{}\">\
+     <Relationship Id=\"rId1\" Type=\"{}/officeDocument\" Target=\"xl/workbook.xml\"/></Relationships>",
+    XML_DECLARATION, PACKAGE_REL_NS, REL_NS
+  );
+
+  let mut parts = vec![
+    ("[Content_Types].xml".to_string(), content_types.into_bytes()),
+    ("_rels/.rels".to_string(), package_rels.into_bytes()),
+    ("xl/workbook.xml".to_string(), workbook.into_bytes()),
+    ("xl/_rels/workbook.xml.rels".to_string(), workbook_rels.into_bytes()),
+    ("xl/styles.xml".to_string(), write_styles().into_bytes()),
+    ("xl/sharedStrings.xml".to_string(), shared_strings.to_xml().into_bytes()),
+  ];
+  parts.extend(files);
+  super::zip::write(&parts)
+}
+
+#[derive(Default)]
+struct SharedStrings {
+  strings: Vec<String>,
+  index: BTreeMap<String, usize>,
+  count: usize,
+}
+
+impl SharedStrings {
+  fn add(&mut self, text: &str) -> usize {
+    self.count += 1;
+    if let Some(idx) = self.index.get(text) {
+      return *idx;
+    }
+    self.strings.push(text.to_string());
+    self.index.insert(text.to_string(), self.strings.len() - 1);
+    self.strings.len() - 1
+  }
+
+  fn to_xml(&self) -> String {
+    let mut xml = format!(
+      "{}<sst xmlns=\"{}\" count=\"{}\" uniqueCount=\"{}\">",
+      XML_DECLARATION,
+      MAIN_NS,
+      self.count,
+      self.strings.len()
+    );
+    for text in self.strings.iter() {
+      xml.push_str("<si>");
+      xml.push_str(&text_element(text));
+      xml.push_str("</si>");
+    }
+    xml.push_str("</sst>");
+    xml
+  }
+}
+
+/// A `<t>` element, keeping leading and trailing whitespace.
+fn text_element(text: &str) -> String {
+  if text.trim() != text {
+    format!("<t xml:space=\"preserve\">{}</t>", escape(text))
+  } else {
+    format!("<t>{}</t>", escape(text))
+  }
+}
+
+fn write_styles() -> String {
+  format!(
+    "{}<styleSheet xmlns=\"{}\">\
+     <numFmts count=\"1\"><numFmt numFmtId=\"164\" formatCode=\"yyyy-mm-dd\"/></numFmts>\
+     <fonts count=\"1\"><font><sz val=\"11\"/><name val=\"Calibri\"/></font></fonts>\
+     <fills count=\"2\"><fill><patternFill patternType=\"none\"/></fill>\
+     <fill><patternFill patternType=\"gray125\"/></fill></fills>\
+     <borders count=\"1\"><border><left/><right/><top/><bottom/><diagonal/></border></borders>\
+     <cellStyleXfs count=\"1\"><xf numFmtId=\"0\" fontId=\"0\" fillId=\"0\" borderId=\"0\"/></cellStyleXfs>\
+     <cellXfs count=\"2\"><xf numFmtId=\"0\" fontId=\"0\" fillId=\"0\" borderId=\"0\" xfId=\"0\"/>\
+     <xf numFmtId=\"164\" fontId=\"0\" fillId=\"0\" borderId=\"0\" xfId=\"0\" applyNumberFormat=\"1\"/></cellXfs>\
+     <cellStyles count=\"1\"><cellStyle name=\"Normal\" xfId=\"0\" builtinId=\"0\"/></cellStyles>\
+     </styleSheet>",
+    XML_DECLARATION, MAIN_NS
+  )
+}
+
+type RowContent<'a> = (Option<u16>, bool, Vec<(u32, &'a CellSnapshot)>);
+
+fn write_worksheet(sheet: &SheetData, shared_strings: &mut SharedStrings) -> String {
+  let mut xml = format!("{}<worksheet xmlns=\"{}\">", XML_DECLARATION, MAIN_NS);
+  xml.push_str(&format!(
+    "<sheetFormatPr defaultColWidth=\"{}\" defaultRowHeight=\"{}\" customHeight=\"1\"/>",
+    px_to_width(sheet.default_col_width),
+    px_to_points(sheet.default_row_height)
+  ));
+  // The width of each column written, and whether it is hidden.
+  let mut cols: BTreeMap<u32, (u16, bool)> = BTreeMap::new();
+  for (idx, width) in sheet.col_widths.iter() {
+    cols.entry(*idx).or_insert((sheet.default_col_width, false)).0 = *width;
+  }
+  for idx in sheet.hidden_cols.iter() {
+    cols.entry(*idx).or_insert((sheet.default_col_width, false)).1 = true;
+  }
+  if !cols.is_empty() {
+    xml.push_str("<cols>");
+    for (idx, (width, hidden)) in cols {
+      xml.push_str(&format!(
+        "<col min=\"{}\" max=\"{}\" width=\"{}\" customWidth=\"1\"{}/>",
+        idx,
+        idx,
+        px_to_width(width),
+        if hidden { " hidden=\"1\"" } else { "" }
+      ));
+    }
+    xml.push_str("</cols>");
+  }
+
+  // The height of each row written, if set, and its cells.
+  let mut rows: BTreeMap<u32, RowContent> = BTreeMap::new();
+  for (row_idx, height) in sheet.row_heights.iter() {
+    rows.entry(*row_idx).or_default().0 = Some(*height);
+  }
+  for row_idx in sheet.hidden_rows.iter() {
+    rows.entry(*row_idx).or_default().1 = true;
+  }
+  for (col_idx, row_idx, snapshot) in sheet.cells.iter() {
+    rows.entry(*row_idx).or_default().2.push((*col_idx, snapshot));
+  }
+  xml.push_str("<sheetData>");
+  for (row_idx, (height, hidden, mut cells)) in rows {
+    xml.push_str(&format!("<row r=\"{}\"", row_idx));
+    if let Some(height) = height {
+      xml.push_str(&format!(" ht=\"{}\" customHeight=\"1\"", px_to_points(height)));
+    }
+    if hidden {
+      xml.push_str(" hidden=\"1\"");
+    }
+    xml.push('>');
+    cells.sort_by_key(|(col_idx, _)| *col_idx);
+    for (col_idx, snapshot) in cells {
+      xml.push_str(&write_cell(col_idx, row_idx, snapshot, shared_strings));
+    }
+    xml.push_str("</row>");
+  }
+  xml.push_str("</sheetData></worksheet>");
+  xml
+}
+
+fn write_cell(col_idx: u32, row_idx: u32, snapshot: &CellSnapshot, shared_strings: &mut SharedStrings) -> String {
+  let name = [reference::column_name(col_idx), row_idx.to_string()].concat();
+  let formula = snapshot
+    .formula
+    .as_ref()
+    .map(|source| format!("<f>{}</f>", escape(source.trim_start_matches('='))));
+  let (kind, value) = match (&snapshot.value, &formula) {
+    (CellValue::Empty, _) => ("", None),
+    (CellValue::Number(number), _) => ("", Some(number.to_string())),
+    (CellValue::DateTime(serial), _) => ("", Some(serial.to_string())),
+    (CellValue::Text(text), None) => ("s", Some(shared_strings.add(text).to_string())),
+    (CellValue::Text(text), Some(_)) => ("str", Some(escape(text))),
+    (CellValue::Boolean(flag), _) => ("b", Some(if *flag { "1" } else { "0" }.to_string())),
+    (CellValue::Error(error), _) => ("e", Some(escape(error.as_str()))),
+  };
+  let mut xml = format!("<c r=\"{}\"", name);
+  if let CellValue::DateTime(_) = snapshot.value {
+    xml.push_str(&format!(" s=\"{}\"", DATE_STYLE));
+  }
+  if !kind.is_empty() {
+    xml.push_str(&format!(" t=\"{}\"", kind));
+  }
+  xml.push('>');
+  xml.push_str(&formula.unwrap_or_default());
+  if let Some(value) = value {
+    xml.push_str(&format!("<v>{}</v>", value));
+  }
+  xml.push_str("</c>");
+  xml
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::{is_date_format, read, write, SheetData};
+  use crate::column::{Column, ColumnManager};
+  use crate::model::{CellSnapshot, DataModel};
+  use crate::reference::Axis;
+  use crate::row::{Row, RowManager};
+  use crate::value::{CellError, CellValue};
+  use crate::zip;
+  #[test]
+  fn write_and_read_back() {
+    let mut model = DataModel::new();
+    let mut rows = RowManager::new(100, 20);
+    let mut cols = ColumnManager::new(26, 85);
+    model.set_value(1, 1, CellValue::Number(1.5));
+    model.set_value(2, 1, CellValue::Text(" a & <b> ".to_string()));
+    model.set_value(3, 1, CellValue::Boolean(true));
+    model.set_value(1, 2, CellValue::DateTime(44122.0));
+    model.set_value(2, 2, CellValue::Error(CellError::Na));
+    model.set_value(3, 2, CellValue::Text(" a & <b> ".to_string()));
+    model.set_formula(1, 3, "=A1*2");
+    model.set_formula(2, 3, "=B1&\"!\"");
+    let mut column = Column::new(2);
+    column.set_width(150);
+    cols.set_column(column);
+    let mut row = Row::new(5);
+    row.set_height(33);
+    rows.set_row(row);
+
+    let sheet = SheetData::capture("Data & more", &model, &rows, &cols);
+    let read_back = read(&write(&[sheet.clone(), SheetData::new("Empty")])).unwrap();
+    assert_eq!(read_back.len(), 2);
+    assert_eq!(read_back[0], sheet);
+    assert_eq!(read_back[1].name, "Empty");
+
+    let mut model = DataModel::new();
+    let mut rows = RowManager::new(2, 20);
+    let mut cols = ColumnManager::new(2, 85);
+    read_back[0].load(&mut model, &mut rows, &mut cols);
+    assert_eq!(model.get_value(2, 3), &CellValue::Text(" a & <b> !".to_string()));
+    assert_eq!(cols.get_width(2), 150);
+    assert_eq!(cols.get_width(3), 85);
+    assert_eq!(rows.get_height(5), 33);
+    assert_eq!((cols.get_col_count(), rows.get_row_count()), (3, 5));
+  }
+
+  #[test]
+  fn hidden_lines() {
+    let mut rows = RowManager::new(100, 20);
+    let mut cols = ColumnManager::new(26, 85);
+    let mut row = Row::new(5);
+    row.set_height(33);
+    rows.set_row(row);
+    rows.set_hidden(5, true);
+    rows.set_hidden(7, true);
+    cols.set_hidden(4, true);
+
+    let sheet = SheetData::capture("Hidden", &DataModel::new(), &rows, &cols);
+    let read_back = read(&write(std::slice::from_ref(&sheet))).unwrap();
+    assert_eq!(read_back[0], sheet);
+    let (mut rows, mut cols) = (RowManager::new(2, 20), ColumnManager::new(2, 85));
+    read_back[0].load(&mut DataModel::new(), &mut rows, &mut cols);
+    assert_eq!((rows.get_height(5), rows.get_shown_height(5)), (0, 33));
+    assert!(cols.is_hidden(4) && rows.is_hidden(7) && !rows.is_hidden(6));
+    assert_eq!((cols.get_col_count(), rows.get_row_count()), (4, 7));
+  }
+
+  #[test]
+  fn excel_workbook() {
+    let workbook = r#"<workbook xmlns:r="rel"><workbookPr date1904="1"/><sheets>
+      <sheet name="First" sheetId="1" r:id="rId7"/></sheets></workbook>"#;
+    let rels = r#"<Relationships>
+      <Relationship Id="rId7" Type="x/worksheet" Target="/xl/worksheets/data.xml"/>
+      <Relationship Id="rId8" Type="x/sharedStrings" Target="strings.xml"/>
+      <Relationship Id="rId9" Type="x/styles" Target="styles.xml"/></Relationships>"#;
+    let strings = r#"<sst><si><t>plain</t></si><si><r><t>rich </t></r><r><t>text</t></r>
+      <rPh><t>hint</t></rPh></si></sst>"#;
+    let styles = r#"<styleSheet><numFmts><numFmt numFmtId="165" formatCode="&quot;day&quot; d"/></numFmts>
+      <cellStyleXfs><xf numFmtId="14"/></cellStyleXfs>
+      <cellXfs><xf numFmtId="0"/><xf numFmtId="165"/></cellXfs></styleSheet>"#;
+    let sheet = r#"<worksheet><sheetFormatPr baseColWidth="8" defaultRowHeight="15"/>
+      <cols><col min="1" max="2" width="9.140625"/><col min="3" max="3" width="20" customWidth="1"/></cols>
+      <sheetData><row r="1" ht="30" customHeight="1">
+      <c r="A1" t="s"><v>0</v></c><c t="s"><v>1</v></c><c s="1"><v>0</v></c></row>
+      <row><c r="A2"><f t="shared" ref="A2:A4" si="0">_xlfn.SUM(C$1,B2)</f><v>3</v></c></row>
+      <row r="4"><c r="A4"><f t="shared" si="0"/><v>4</v></c><c r="B4" t="inlineStr"><is><t>inline</t></is></c>
+      <c r="C4" t="e"><v>#DIV/0!</v></c><c r="D4"><f>Other!A1</f><v>9</v></c><c r="E4" s="1"/>
+      <c r="XFE4"><v>1</v></c></row>
+      <row r="4000000000" ht="30" hidden="1"><c r="A4000000000"><v>1</v></c></row><row><c><v>2</v></c></row>
+      </sheetData></worksheet>"#;
+    let files: Vec<(String, Vec<u8>)> = vec![
+      ("xl/workbook.xml", workbook),
+      ("xl/_rels/workbook.xml.rels", rels),
+      ("xl/strings.xml", strings),
+      ("xl/styles.xml", styles),
+      ("xl/worksheets/data.xml", sheet),
+    ]
+    .into_iter()
+    .map(|(name, xml)| (name.to_string(), xml.as_bytes().to_vec()))
+    .collect();
+    let sheets = read(&zip::write(&files)).unwrap();
+    assert_eq!(sheets.len(), 1);
+    let sheet = &sheets[0];
+    assert_eq!(sheet.name, "First");
+    assert_eq!((sheet.default_col_width, sheet.default_row_height), (64, 20));
+    assert_eq!(sheet.col_widths, vec![(3, 140)]);
+    assert_eq!(sheet.row_heights, vec![(1, 40)]);
+    let number = CellValue::Number;
+    assert_eq!(
+      sheet.cells,
+      vec![
+        (1, 1, CellSnapshot::value(CellValue::Text("plain".to_string()))),
+        (2, 1, CellSnapshot::value(CellValue::Text("rich text".to_string()))),
+        (3, 1, CellSnapshot::value(CellValue::DateTime(1462.0))),
+        (1, 2, CellSnapshot { value: number(3.0), formula: Some("=SUM(C$1,B2)".to_string()) }),
+        (1, 4, CellSnapshot { value: number(4.0), formula: Some("=SUM(C$1,B4)".to_string()) }),
+        (2, 4, CellSnapshot::value(CellValue::Text("inline".to_string()))),
+        (3, 4, CellSnapshot::value(CellValue::Error(CellError::Div0))),
+        (4, 4, CellSnapshot { value: number(9.0), formula: Some("=Other!A1".to_string()) }),
+      ]
+    );
+
+    let mut model = DataModel::new();
+    let mut rows = RowManager::new(100, 20);
+    let mut cols = ColumnManager::new(100, 85);
+    sheet.load(&mut model, &mut rows, &mut cols);
+    // Loaded on its own, the sheet has no `Other` sheet to read, so the value
+    // stored in the file stays, also as the cell moves, until the formula is
+    // entered anew.
+    assert_eq!(model.get_value(4, 4), &number(9.0));
+    model.shift_lines(Axis::Rows, 1, 1);
+    assert_eq!(model.get_value(4, 5), &number(9.0));
+    model.shift_lines(Axis::Rows, 1, -1);
+    assert_eq!(model.get_formula(4, 4), Some(&"=Other!A1".to_string()));
+    assert_eq!(model.get_value(1, 2), &number(1462.0));
+    model.set_formula(4, 4, "=Other!A1");
+    assert_eq!(model.get_value(4, 4), &CellValue::Error(CellError::Ref));
+  }
+
+  #[test]
+  fn date_formats() {
+    assert!(is_date_format(14, ""));
+    assert!(!is_date_format(2, ""));
+    assert!(is_date_format(164, "dd/mm/yyyy;@"));
+    assert!(is_date_format(164, "[$-409]h:mm AM/PM"));
+    assert!(!is_date_format(164, "\"days\" 0.00"));
+    assert!(!is_date_format(164, "[Red]0.00;[Blue]-0.00"));
+    assert!(!is_date_format(164, "0.00\\s"));
+  }
+}
@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_DIRECTORY: u32 = 0x0605_4b50;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+/// Names are UTF-8.
+const FLAG_UTF8: u16 = 0x0800;
+/// 1980-01-01, the earliest date the format can hold.
+const DOS_DATE: u16 = 0x21;
+
+fn read_u16(bytes: &[u8], pos: usize) -> Result<u16, String> {
+  match bytes.get(pos..pos + 2) {
+    Some(b) => Ok(u16::from_le_bytes([b[0], b[1]])),
+    None => Err("zip archive is truncated".to_string()),
+  }
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> Result<u32, String> {
+  match bytes.get(pos..pos + 4) {
+    Some(b) => Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+    None => Err("zip archive is truncated".to_string()),
+  }
+}
+
+/// Read every file of a zip archive, by name. Entries must be stored or
+/// deflated; ZIP64 archives are not supported.
+pub fn read(bytes: &[u8]) -> Result<BTreeMap<String, Vec<u8>>, String> {
+  // The end of directory record is at least 22 bytes and followed by a comment.
+  let end = (0..bytes.len().saturating_sub(21))
+    .rev()
+    .find(|pos| read_u32(bytes, *pos) == Ok(END_OF_DIRECTORY))
+    .ok_or_else(|| "not a zip archive".to_string())?;
+  let count = read_u16(bytes, end + 10)?;
+  let mut pos = read_u32(bytes, end + 16)? as usize;
+  let mut files = BTreeMap::new();
+  for _ in 0..count {
+    if read_u32(bytes, pos)? != CENTRAL_HEADER {
+      return Err("zip directory is corrupt".to_string());
+    }
+    let method = read_u16(bytes, pos + 10)?;
+    let compressed_size = read_u32(bytes, pos + 20)?;
+    let size = read_u32(bytes, pos + 24)?;
+    let name_len = read_u16(bytes, pos + 28)? as usize;
+    let extra_len = read_u16(bytes, pos + 30)? as usize;
+    let comment_len = read_u16(bytes, pos + 32)? as usize;
+    let offset = read_u32(bytes, pos + 42)?;
+    if compressed_size == u32::MAX || size == u32::MAX || offset == u32::MAX {
+      return Err("ZIP64 archives are not supported".to_string());
+    }
+    let name = bytes
+      .get(pos + 46..pos + 46 + name_len)
+      .ok_or_else(|| "zip archive is truncated".to_string())?;
+    let name = String::from_utf8_lossy(name).to_string();
+    pos += 46 + name_len + extra_len + comment_len;
+
+    let offset = offset as usize;
+    if read_u32(bytes, offset)? != LOCAL_HEADER {
+      return Err(format!("zip entry {} is corrupt", name));
+    }
+    let start = offset + 30 + read_u16(bytes, offset + 26)? as usize + read_u16(bytes, offset + 28)? as usize;
+    let data = bytes
+      .get(start..start + compressed_size as usize)
+      .ok_or_else(|| "zip archive is truncated".to_string())?;
+    // Inflate only about as far as the size the directory gives, so that a
+    // small archive cannot claim all memory. The inflater doubles its buffer
+    // and fails once that would pass the limit, so twice the size is needed
+    // to be sure of room for the whole entry.
+    let data = match method {
+      STORED => data.to_vec(),
+      DEFLATED => miniz_oxide::inflate::decompress_to_vec_with_limit(data, 2 * size as usize + 2)
+        .map_err(|_| format!("zip entry {} is corrupt", name))?,
+      _ => return Err(format!("zip entry {} uses an unsupported compression method", name)),
+    };
+    if data.len() != size as usize {
+      return Err(format!("zip entry {} is corrupt", name));
+    }
+    if !name.ends_with('/') {
+      files.insert(name, data);
+    }
+  }
+  Ok(files)
+}
+
+/// Write files into a zip archive, deflating each.
+pub fn write(files: &[(String, Vec<u8>)]) -> Vec<u8> {
+  let mut archive = vec![];
+  let mut directory = vec![];
+  for (name, data) in files {
+    let compressed = miniz_oxide::deflate::compress_to_vec(data, 6);
+    let crc = crc32(data);
+    let offset = archive.len() as u32;
+
+    let mut header = vec![];
+    push_u16(&mut header, 20);
+    push_u16(&mut header, FLAG_UTF8);
+    push_u16(&mut header, DEFLATED);
+    push_u16(&mut header, 0);
+    push_u16(&mut header, DOS_DATE);
+    push_u32(&mut header, crc);
+    push_u32(&mut header, compressed.len() as u32);
+    push_u32(&mut header, data.len() as u32);
+    push_u16(&mut header, name.len() as u16);
+    push_u16(&mut header, 0);
+
+    push_u32(&mut archive, LOCAL_HEADER);
+    archive.extend_from_slice(&header);
+    archive.extend_from_slice(name.as_bytes());
+    archive.extend_from_slice(&compressed);
+
+    push_u32(&mut directory, CENTRAL_HEADER);
+    push_u16(&mut directory, 20);
+    directory.extend_from_slice(&header);
+    // Comment length, disk number, internal and external attributes.
+    push_u16(&mut directory, 0);
+    push_u16(&mut directory, 0);
+    push_u16(&mut directory, 0);
+    push_u32(&mut directory, 0);
+    push_u32(&mut directory, offset);
+    directory.extend_from_slice(name.as_bytes());
+  }
+  let directory_offset = archive.len() as u32;
+  archive.extend_from_slice(&directory);
+  push_u32(&mut archive, END_OF_DIRECTORY);
+  push_u16(&mut archive, 0);
+  push_u16(&mut archive, 0);
+  push_u16(&mut archive, files.len() as u16);
+  push_u16(&mut archive, files.len() as u16);
+  push_u32(&mut archive, directory.len() as u32);
+  push_u32(&mut archive, directory_offset);
+  push_u16(&mut archive, 0);
+  archive
+}
+
+fn push_u16(bytes: &mut Vec<u8>, value: u16) {
+  bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+  bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn crc32(data: &[u8]) -> u32 {
+  let mut crc = !0u32;
+  for byte in data {
+    crc ^= *byte as u32;
+    for _ in 0..8 {
+      crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+    }
+  }
+  !crc
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::{crc32, read, write};
+  #[test]
+  fn round_trip() {
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    let files = vec![
+      ("[Content_Types].xml".to_string(), b"<Types/>".to_vec()),
+      ("xl/empty.xml".to_string(), vec![]),
+      ("xl/big.xml".to_string(), "<row/>".repeat(1000).into_bytes()),
+    ];
+    let archive = write(&files);
+    assert!(archive.len() < 1000);
+    let read_back = read(&archive).unwrap();
+    assert_eq!(read_back.len(), 3);
+    for (name, data) in files.iter() {
+      assert_eq!(&read_back[name], data);
+    }
+    assert!(read(b"PK not really").is_err());
+    assert!(read(&archive[..archive.len() / 2]).is_err());
+
+    // An entry inflating past the size the directory gives is rejected.
+    let mut archive = write(&files[2..]);
+    let directory = archive.windows(4).position(|bytes| bytes == b"PK\x01\x02").unwrap();
+    archive[directory + 24..directory + 28].copy_from_slice(&100u32.to_le_bytes());
+    assert!(read(&archive).is_err());
+  }
+}